@@ -1,11 +1,16 @@
 use std::sync::Arc;
 
 use anyhow::Context;
-use foxbot_models::{GroupConfig, GroupConfigKey};
+use foxbot_models::{ChatAction, FileCache, GroupConfig, GroupConfigKey, Subscriptions};
 use tgbotapi::requests::GetChatMember;
 
 use crate::*;
 
+/// Default number of hours to keep searching for sources of channel posts.
+const CHANNEL_RECHECK_HOURS: i64 = 72;
+/// Largest file the Bot API allows bots to download.
+const MAX_DOWNLOAD_SIZE: u64 = 20_000_000;
+
 #[tracing::instrument(skip(handler, job), fields(job_id = job.id()))]
 pub async fn process_channel_update(handler: Arc<Handler>, job: faktory::Job) -> Result<(), Error> {
     let data = job
@@ -18,9 +23,9 @@ pub async fn process_channel_update(handler: Arc<Handler>, job: faktory::Job) ->
 
     tracing::trace!("got enqueued message: {:?}", message);
 
-    // Photos should exist for job to be enqueued.
-    let sizes = match &message.photo {
-        Some(photo) => photo,
+    // Media should exist for job to be enqueued.
+    let file = match hashable_file(&message) {
+        Some(file) => file,
         _ => return Ok(()),
     };
 
@@ -31,26 +36,35 @@ pub async fn process_channel_update(handler: Arc<Handler>, job: faktory::Job) ->
         );
     }
 
-    let file = find_best_photo(sizes).ok_or(Error::MissingData)?;
-    let (searched_hash, mut matches) = match_image(
-        &handler.telegram,
-        &handler.redis,
-        &handler.fuzzysearch,
-        file,
-        Some(3),
-    )
-    .await?;
+    if file.file_size.unwrap_or(0) > MAX_DOWNLOAD_SIZE {
+        tracing::debug!("channel media was too large to download");
+        return Ok(());
+    }
+
+    let searched_hash = match hash_media(&handler, &file).await? {
+        Some(hash) => hash,
+        None => return Ok(()),
+    };
+    let mut matches = lookup_single_hash(&handler.fuzzysearch, searched_hash, Some(3)).await?;
 
     // Only keep matches with a distance of 3 or less
     matches.retain(|m| m.distance.unwrap_or(10) <= 3);
 
+    let links = extract_links(&message);
+
     if matches.is_empty() {
         tracing::debug!("unable to find sources for image");
+
+        // Posts that already have links were probably sourced by the poster.
+        // Otherwise, remember the post so it can be edited if a source
+        // appears later.
+        if links.is_empty() {
+            record_unsourced_post(&handler, &message, searched_hash).await?;
+        }
+
         return Ok(());
     }
 
-    let links = extract_links(&message);
-
     let mut sites = handler.sites.lock().await;
 
     // If any matches contained a link we found in the message, skip adding
@@ -90,16 +104,16 @@ pub async fn process_channel_update(handler: Arc<Handler>, job: faktory::Job) ->
     // Keep order of sites consistent.
     sort_results_by(&foxbot_models::Sites::default_order(), &mut matches, true);
 
-    let firsts = first_of_each_site(&matches)
-        .into_iter()
-        .map(|(site, file)| (site, file.url()))
-        .collect();
+    let (firsts, artists) = edit_sources(&matches);
 
     let data = serde_json::to_value(&MessageEdit {
         chat_id: message.chat.id.to_string(),
         message_id: message.message_id,
         media_group_id: message.media_group_id,
         firsts,
+        artists,
+        caption: message.caption,
+        caption_entities: message.caption_entities,
     })?;
 
     let mut job = faktory::Job::new("channel_edit", vec![data]).on_queue("foxbot_background");
@@ -110,6 +124,74 @@ pub async fn process_channel_update(handler: Arc<Handler>, job: faktory::Job) ->
     Ok(())
 }
 
+/// Hash channel media, extracting the first frame of videos. Returns `None`
+/// if the media could be downloaded but not decoded, as it can't be sourced.
+async fn hash_media(handler: &Handler, file: &HashableFile<'_>) -> anyhow::Result<Option<i64>> {
+    if let Some(hash) = FileCache::get(&handler.redis, file.file_unique_id)
+        .await
+        .context("unable to query file cache")?
+    {
+        return Ok(Some(hash));
+    }
+
+    let data = download_file(&handler.telegram, file.file_id).await?;
+
+    let data = if file.is_video {
+        match crate::video::first_frame(&data).await {
+            Ok(frame) => frame,
+            Err(err) => {
+                tracing::warn!("unable to extract frame from channel video: {:?}", err);
+                return Ok(None);
+            }
+        }
+    } else {
+        data
+    };
+
+    let hash = match tokio::task::spawn_blocking(move || fuzzysearch::hash_bytes(&data))
+        .await
+        .context("unable to spawn blocking")?
+    {
+        Ok(hash) => hash,
+        Err(err) => {
+            tracing::warn!("unable to hash channel media: {:?}", err);
+            return Ok(None);
+        }
+    };
+
+    FileCache::set(&handler.redis, file.file_unique_id, hash)
+        .await
+        .context("unable to set file cache")?;
+
+    Ok(Some(hash))
+}
+
+/// Get the first link from each site and the artists from those sources,
+/// without duplicates.
+pub(crate) fn edit_sources(matches: &[fuzzysearch::File]) -> (Vec<(Sites, String)>, Vec<String>) {
+    let firsts = first_of_each_site(matches);
+
+    let mut artists: Vec<String> = Vec::new();
+    for artist in firsts
+        .iter()
+        .flat_map(|(_site, file)| file.artists.iter().flatten())
+    {
+        if !artists
+            .iter()
+            .any(|known| known.eq_ignore_ascii_case(artist))
+        {
+            artists.push(artist.to_owned());
+        }
+    }
+
+    let firsts = firsts
+        .into_iter()
+        .map(|(site, file)| (site, file.url()))
+        .collect();
+
+    (firsts, artists)
+}
+
 #[tracing::instrument(skip(handler, job), fields(job_id = job.id()))]
 pub async fn process_channel_edit(handler: Arc<Handler>, job: faktory::Job) -> Result<(), Error> {
     let data: serde_json::Value = job
@@ -126,6 +208,9 @@ pub async fn process_channel_edit(handler: Arc<Handler>, job: faktory::Job) -> R
         message_id,
         media_group_id,
         firsts,
+        artists,
+        caption,
+        caption_entities,
     } = serde_json::value::from_value(data.clone())?;
     let chat_id: &str = &chat_id;
 
@@ -141,30 +226,37 @@ pub async fn process_channel_edit(handler: Arc<Handler>, job: faktory::Job) -> R
         return Ok(());
     }
 
-    // If this photo was part of a media group, we should set a caption on
+    let policy = match chat_id.parse() {
+        Ok(channel_id) => ChannelEditPolicy::load(&handler.conn, channel_id).await?,
+        Err(_) => ChannelEditPolicy::default(),
+    };
+
+    // If this photo was part of a media group, we have to set a caption on
     // the image because we can't make an inline keyboard on it.
-    let resp = if media_group_id.is_some() {
-        let caption = firsts
-            .into_iter()
-            .map(|(_site, url)| url)
-            .collect::<Vec<_>>()
-            .join("\n");
-
-        let edit_caption_markup = EditMessageCaption {
-            chat_id: chat_id.into(),
-            message_id: Some(message_id),
-            caption: Some(caption),
-            ..Default::default()
-        };
+    let media_group = media_group_id.is_some();
 
-        handler.telegram.make_request(&edit_caption_markup).await
-    // Not a media group, we should create an inline keyboard.
-    } else {
+    let lines = handler
+        .get_fluent_bundle(None, |bundle| {
+            channel_caption_lines(bundle, &policy, media_group, &firsts, &artists)
+        })
+        .await;
+    let new_caption = append_caption(
+        caption.as_deref(),
+        caption_entities.as_deref().unwrap_or_default(),
+        lines,
+    );
+
+    // Posts that wanted links only in their caption get buttons instead if
+    // there wasn't enough room left in the caption.
+    let wants_buttons =
+        policy.wants_buttons(media_group) || (!media_group && new_caption.is_none());
+
+    let reply_markup = if wants_buttons {
         let buttons: Vec<_> = firsts
-            .into_iter()
+            .iter()
             .map(|(site, url)| InlineKeyboardButton {
                 text: site.as_str().to_string(),
-                url: Some(url),
+                url: Some(url.to_owned()),
                 ..Default::default()
             })
             .collect();
@@ -175,18 +267,40 @@ pub async fn process_channel_edit(handler: Arc<Handler>, job: faktory::Job) -> R
             buttons.chunks(1).map(|chunk| chunk.to_vec()).collect()
         };
 
-        let markup = InlineKeyboardMarkup {
+        Some(ReplyMarkup::InlineKeyboardMarkup(InlineKeyboardMarkup {
             inline_keyboard: buttons,
-        };
+        }))
+    } else {
+        None
+    };
 
-        let edit_reply_markup = EditMessageReplyMarkup {
-            chat_id: chat_id.into(),
-            message_id: Some(message_id),
-            reply_markup: Some(ReplyMarkup::InlineKeyboardMarkup(markup)),
-            ..Default::default()
-        };
+    let resp = match (new_caption, reply_markup) {
+        (Some(caption), reply_markup) => {
+            let edit_caption_markup = EditMessageCaption {
+                chat_id: chat_id.into(),
+                message_id: Some(message_id),
+                caption: Some(caption),
+                parse_mode: Some(tgbotapi::requests::ParseMode::MarkdownV2),
+                reply_markup,
+                ..Default::default()
+            };
 
-        handler.telegram.make_request(&edit_reply_markup).await
+            handler.telegram.make_request(&edit_caption_markup).await
+        }
+        (None, Some(reply_markup)) => {
+            let edit_reply_markup = EditMessageReplyMarkup {
+                chat_id: chat_id.into(),
+                message_id: Some(message_id),
+                reply_markup: Some(reply_markup),
+                ..Default::default()
+            };
+
+            handler.telegram.make_request(&edit_reply_markup).await
+        }
+        (None, None) => {
+            tracing::warn!("sources did not fit in media group caption");
+            return Ok(());
+        }
     };
 
     match resp {
@@ -232,6 +346,12 @@ pub async fn process_channel_edit(handler: Arc<Handler>, job: faktory::Job) -> R
         })) => {
             tracing::warn!("got 400 error, ignoring: {:?}", description);
 
+            if let Ok(channel_id) = chat_id.parse() {
+                handler
+                    .record_action(channel_id, ChatAction::ChannelEditFailed, Some(message_id))
+                    .await;
+            }
+
             Ok(())
         }
         // If permissions have changed (bot was removed from channel, etc.)
@@ -244,13 +364,175 @@ pub async fn process_channel_edit(handler: Arc<Handler>, job: faktory::Job) -> R
         })) => {
             tracing::warn!("got 403 error, ignoring: {:?}", description);
 
+            if let Ok(channel_id) = chat_id.parse() {
+                handler
+                    .record_action(channel_id, ChatAction::ChannelEditFailed, Some(message_id))
+                    .await;
+            }
+
+            Ok(())
+        }
+        Ok(_) => {
+            // The linked discussion group skipped this post when it was
+            // forwarded, so it may want the sources now.
+            if let Ok(channel_id) = chat_id.parse() {
+                handler
+                    .record_action(channel_id, ChatAction::ChannelEdited, Some(message_id))
+                    .await;
+
+                if let Err(err) =
+                    crate::group::reply_linked_forward(&handler, channel_id, message_id, &firsts)
+                        .await
+                {
+                    tracing::error!("could not reply to linked channel forward: {:?}", err);
+                }
+            }
+
             Ok(())
         }
-        Ok(_) => Ok(()),
         Err(e) => Err(e.into()),
     }
 }
 
+/// Remember a channel post without sources, if the bot is able to edit it
+/// when a source is found.
+async fn record_unsourced_post(
+    handler: &Handler,
+    message: &tgbotapi::Message,
+    hash: i64,
+) -> Result<(), Error> {
+    handler
+        .record_action(
+            message.chat.id,
+            ChatAction::ChannelUnsourced,
+            Some(message.message_id),
+        )
+        .await;
+
+    let can_edit = GroupConfig::get::<bool>(
+        &handler.conn,
+        message.chat.id,
+        GroupConfigKey::CanEditChannel,
+    )
+    .await?
+    .unwrap_or(false);
+
+    if !can_edit {
+        tracing::trace!("bot cannot edit channel, not recording unsourced post");
+        return Ok(());
+    }
+
+    tracing::debug!("recording unsourced post for later notification");
+
+    Subscriptions::add_channel_subscription(
+        &handler.conn,
+        message.chat.id,
+        message.message_id,
+        message.media_group_id.as_deref(),
+        hash,
+        message.caption.as_deref(),
+        message.caption_entities.as_deref(),
+    )
+    .await?;
+
+    schedule_channel_recheck(
+        handler,
+        ChannelRecheck {
+            chat_id: message.chat.id,
+            message_id: message.message_id,
+            first_checked: chrono::Utc::now().timestamp(),
+            attempt: 0,
+        },
+    )
+    .await
+}
+
+/// Enqueue another search for an unsourced channel post, waiting twice as
+/// long as the previous attempt. Nothing is enqueued once the next attempt
+/// would be after the recheck period.
+async fn schedule_channel_recheck(handler: &Handler, recheck: ChannelRecheck) -> Result<(), Error> {
+    let recheck_hours = handler
+        .config
+        .channel_recheck_hours
+        .unwrap_or(CHANNEL_RECHECK_HOURS);
+
+    let first_checked = chrono::DateTime::from_utc(
+        chrono::NaiveDateTime::from_timestamp(recheck.first_checked, 0),
+        chrono::Utc,
+    );
+    let delay = chrono::Duration::hours(1 << recheck.attempt.min(16));
+    let next_check = chrono::Utc::now() + delay;
+
+    if next_check > first_checked + chrono::Duration::hours(recheck_hours) {
+        tracing::debug!(
+            attempt = recheck.attempt,
+            "recheck period is over, waiting for new hashes"
+        );
+        return Ok(());
+    }
+
+    let data = serde_json::to_value(&recheck)?;
+
+    let mut job = faktory::Job::new("channel_recheck", vec![data]).on_queue("foxbot_background");
+    job.at = Some(next_check);
+    job.custom = get_faktory_custom();
+
+    handler.enqueue(job).await;
+
+    Ok(())
+}
+
+/// Search for sources again for a channel post that had none, editing the
+/// post if any were found.
+#[tracing::instrument(skip(handler, job), fields(job_id = job.id()))]
+pub async fn process_channel_recheck(
+    handler: Arc<Handler>,
+    job: faktory::Job,
+) -> Result<(), Error> {
+    let data = job
+        .args()
+        .iter()
+        .next()
+        .ok_or(Error::MissingData)?
+        .to_owned();
+    let recheck: ChannelRecheck = serde_json::value::from_value(data)?;
+
+    // The post may have already been sourced from a new hash, or expired.
+    let subscription = match Subscriptions::get_channel_subscription(
+        &handler.conn,
+        recheck.chat_id,
+        recheck.message_id,
+    )
+    .await?
+    {
+        Some(subscription) => subscription,
+        None => {
+            tracing::debug!("channel post no longer needs a source");
+            return Ok(());
+        }
+    };
+
+    let mut matches = lookup_single_hash(&handler.fuzzysearch, subscription.hash, Some(3)).await?;
+    matches.retain(|m| m.distance.unwrap_or(10) <= 3);
+
+    if matches.is_empty() {
+        tracing::debug!(attempt = recheck.attempt, "still unable to find sources");
+
+        return schedule_channel_recheck(
+            &handler,
+            ChannelRecheck {
+                attempt: recheck.attempt + 1,
+                ..recheck
+            },
+        )
+        .await;
+    }
+
+    tracing::debug!(attempt = recheck.attempt, "found sources on recheck");
+
+    crate::subscribe::enqueue_channel_edits(&handler, vec![subscription], &mut matches).await
+}
+
 /// Telegram only shows a caption on a media group if there is a single caption
 /// anywhere in the group. When users upload a group, we need to check if we can
 /// only set a single source to make the link more visible. This can be done by
@@ -404,6 +686,11 @@ async fn store_channel_edit(handler: &Handler, message: &tgbotapi::Message) -> a
 
 #[cfg(test)]
 mod tests {
+    use foxbot_models::{FileCache, GroupConfig, GroupConfigKey};
+    use foxbot_sites::InMemoryReverseSearch;
+
+    use crate::test_helpers::{get_handler, job};
+
     async fn get_redis() -> redis::aio::ConnectionManager {
         let redis_client =
             redis::Client::open(std::env::var("REDIS_DSN").expect("Missing REDIS_DSN")).unwrap();
@@ -501,4 +788,91 @@ mod tests {
             "adding a new with an old source should set a completed flag"
         );
     }
+
+    fn channel_message(chat_id: i64, file_unique_id: &str) -> tgbotapi::Message {
+        tgbotapi::Message {
+            message_id: 1,
+            chat: tgbotapi::Chat {
+                id: chat_id,
+                chat_type: tgbotapi::ChatType::Channel,
+                ..Default::default()
+            },
+            photo: Some(vec![tgbotapi::PhotoSize {
+                file_id: "file-id".to_string(),
+                file_unique_id: file_unique_id.to_string(),
+                width: 100,
+                height: 100,
+                ..Default::default()
+            }]),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_process_channel_update() {
+        let _ = tracing_subscriber::fmt::try_init();
+
+        use super::{process_channel_update, MessageEdit};
+
+        let search = InMemoryReverseSearch::new();
+        search.insert(
+            42,
+            fuzzysearch::File {
+                site_id: 123,
+                site_info: Some(fuzzysearch::SiteInfo::FurAffinity(
+                    fuzzysearch::FurAffinityFile { file_id: 123 },
+                )),
+                ..Default::default()
+            },
+        );
+
+        let (handler, jobs) = get_handler(search).await;
+        let chat_id = -100_123;
+
+        FileCache::set(&handler.redis, "known-photo", 42)
+            .await
+            .unwrap();
+        FileCache::set(&handler.redis, "unknown-photo", -42)
+            .await
+            .unwrap();
+        GroupConfig::set(&handler.conn, GroupConfigKey::CanEditChannel, chat_id, true)
+            .await
+            .unwrap();
+
+        let message = channel_message(chat_id, "unknown-photo");
+        process_channel_update(handler.clone(), job("channel_update", message))
+            .await
+            .unwrap();
+        {
+            let mut jobs = jobs.lock().unwrap();
+            assert_eq!(jobs.len(), 1, "unknown image should be checked again");
+            assert_eq!(
+                jobs[0].kind(),
+                "channel_recheck",
+                "unknown image should not be edited"
+            );
+            jobs.clear();
+        }
+
+        let message = channel_message(chat_id, "known-photo");
+        process_channel_update(handler.clone(), job("channel_update", message))
+            .await
+            .unwrap();
+
+        let jobs = jobs.lock().unwrap();
+        assert_eq!(jobs.len(), 1, "known image should enqueue an edit");
+        assert_eq!(jobs[0].kind(), "channel_edit");
+
+        let edit: MessageEdit = serde_json::value::from_value(jobs[0].args()[0].clone()).unwrap();
+        assert_eq!(edit.chat_id, chat_id.to_string());
+        assert_eq!(edit.message_id, 1);
+        assert_eq!(
+            edit.firsts,
+            vec![(
+                foxbot_models::Sites::FurAffinity,
+                "https://www.furaffinity.net/view/123/".to_string()
+            )]
+        );
+    }
 }
@@ -0,0 +1,136 @@
+use std::sync::Arc;
+
+use chrono::TimeZone;
+use fluent::fluent_args;
+use tgbotapi::requests::{GetChat, SendMessage};
+
+use crate::*;
+use foxbot_models::{ChatAction, ChatActions, ChatDigest, DueDigest};
+
+/// Actions counted in a digest, with the name of the message describing
+/// them, in the order they are shown.
+const DIGEST_ACTIONS: &[(ChatAction, &str)] = &[
+    (ChatAction::ChannelEdited, "digest-channel-edited"),
+    (ChatAction::ChannelUnsourced, "digest-channel-unsourced"),
+    (ChatAction::ChannelEditFailed, "digest-channel-edit-failed"),
+    (ChatAction::GroupSourced, "digest-group-sourced"),
+];
+
+/// Send an admin a summary of what happened in a chat since their last
+/// digest.
+#[tracing::instrument(skip(handler, job), fields(job_id = job.id()))]
+pub async fn process_chat_digest(handler: Arc<Handler>, job: faktory::Job) -> Result<(), Error> {
+    let data = job
+        .args()
+        .iter()
+        .next()
+        .ok_or(Error::MissingData)?
+        .to_owned();
+    let DueDigest {
+        chat_id,
+        user_id,
+        since,
+        language_code,
+        chat_gone,
+    } = serde_json::value::from_value(data)?;
+
+    // The digest may have already been sent by an earlier job, or turned off
+    // or given to another admin since this job was enqueued.
+    match ChatDigest::get(&handler.conn, chat_id).await? {
+        Some(current) if current.user_id == user_id && current.since == since => (),
+        _ => {
+            tracing::debug!("digest is no longer due");
+            return Ok(());
+        }
+    }
+
+    let counts =
+        ChatActions::summary(&handler.conn, chat_id, chrono::Utc.timestamp(since, 0)).await?;
+
+    // If the bot was removed or can no longer access the chat, still send
+    // what happened before it left, but make it the last digest.
+    let (title, chat_gone) = match handler
+        .telegram
+        .make_request(&GetChat {
+            chat_id: chat_id.into(),
+        })
+        .await
+    {
+        Ok(chat) => (chat.title, chat_gone),
+        Err(tgbotapi::Error::Telegram(tgbotapi::TelegramError {
+            error_code: Some(code),
+            ..
+        })) if code == 400 || code == 403 => {
+            tracing::info!("bot can no longer access chat, ending digest");
+            (None, true)
+        }
+        Err(err) => return Err(err.into()),
+    };
+    let title = title.unwrap_or_else(|| chat_id.to_string());
+
+    let text = handler
+        .get_fluent_bundle(language_code.as_deref(), |bundle| {
+            let mut buf = String::new();
+
+            let args = fluent_args!["chat" => title];
+            buf.push_str(&get_message(bundle, "digest-title", Some(args)).unwrap());
+
+            let mut empty = true;
+
+            for (action, name) in DIGEST_ACTIONS {
+                let count = match counts.get(action) {
+                    Some(count) if *count > 0 => *count,
+                    _ => continue,
+                };
+                empty = false;
+
+                let args = fluent_args!["count" => count];
+                buf.push('\n');
+                buf.push_str(&get_message(bundle, name, Some(args)).unwrap());
+            }
+
+            if empty {
+                buf.push('\n');
+                buf.push_str(&get_message(bundle, "digest-empty", None).unwrap());
+            }
+
+            if counts.contains_key(&ChatAction::PermissionLost) {
+                buf.push_str("\n\n");
+                buf.push_str(&get_message(bundle, "digest-permission-lost", None).unwrap());
+            }
+
+            if chat_gone {
+                buf.push_str("\n\n");
+                buf.push_str(&get_message(bundle, "digest-chat-gone", None).unwrap());
+            }
+
+            buf
+        })
+        .await;
+
+    let send_message = SendMessage {
+        chat_id: user_id.into(),
+        text,
+        ..Default::default()
+    };
+
+    match handler.telegram.make_request(&send_message).await {
+        // The user blocked the bot, so the digest can't be delivered anymore.
+        Err(tgbotapi::Error::Telegram(tgbotapi::TelegramError {
+            error_code: Some(403),
+            ..
+        })) => {
+            tracing::info!("user blocked bot, disabling digest");
+            ChatDigest::disable(&handler.conn, chat_id).await?;
+        }
+        Ok(_) if chat_gone => {
+            ChatDigest::disable(&handler.conn, chat_id).await?;
+        }
+        Ok(_) => {
+            ChatDigest::mark_sent(&handler.conn, chat_id).await?;
+        }
+        Err(err) => return Err(err.into()),
+    }
+
+    Ok(())
+}
@@ -1,10 +1,12 @@
 use anyhow::Context;
 use fluent::fluent_args;
-use rusoto_s3::S3;
-use tgbotapi::requests::GetChat;
+use tgbotapi::requests::{EditMessageReplyMarkup, GetChat};
 
 use crate::*;
-use foxbot_models::{FileCache, GroupConfig, GroupConfigKey, MediaGroup};
+use foxbot_models::{
+    ChatAction, FileCache, GroupConfig, GroupConfigKey, GroupRepost, GroupSourceDelivery,
+    InlineHistory, LinkedForward, MediaGroup,
+};
 
 #[tracing::instrument(skip(handler, job), fields(job_id = job.id(), chat_id))]
 pub async fn process_group_photo(handler: Arc<Handler>, job: faktory::Job) -> Result<(), Error> {
@@ -31,6 +33,10 @@ pub async fn process_group_photo(handler: Arc<Handler>, job: faktory::Job) -> Re
         );
     }
 
+    if let Err(err) = check_repost(&handler, &message, photo_sizes).await {
+        tracing::error!("could not check if message was a repost: {:?}", err);
+    }
+
     match GroupConfig::get(&handler.conn, message.chat.id, GroupConfigKey::GroupAdd).await? {
         Some(true) => tracing::debug!("group wants automatic sources"),
         _ => {
@@ -52,6 +58,8 @@ pub async fn process_group_photo(handler: Arc<Handler>, job: faktory::Job) -> Re
         return Ok(());
     }
 
+    let policy = GroupSourcePolicy::load(&handler.conn, message.chat.id).await?;
+
     match is_controlled_channel(&handler, &message).await {
         Ok(true) => {
             tracing::debug!("message was forwarded from controlled channel");
@@ -64,14 +72,18 @@ pub async fn process_group_photo(handler: Arc<Handler>, job: faktory::Job) -> Re
             );
             let hours_ago = (now - message_date).num_hours();
 
-            tracing::trace!(hours_ago, "calculated message age");
+            tracing::trace!(hours_ago, rule = ?policy.linked_forwards, "calculated message age");
+
+            // Older posts may be worth sourcing if a channel has posts that
+            // weren't sourced when they were posted, either because the bot
+            // wasn't enabled then or the source wasn't discovered yet.
+            if policy.skip_linked_forward(hours_ago) {
+                tracing::debug!("group skips forwards from controlled channel at this age");
+
+                if let Err(err) = remember_linked_forward(&handler, &message).await {
+                    tracing::error!("could not remember linked channel forward: {:?}", err);
+                }
 
-            // After 6 hours, it's fair game to try and source the post. This is
-            // important if a channel has posts that weren't sourced when they
-            // were posted, either because the bot wasn't enabled then or the
-            // source wasn't discovered yet.
-            if hours_ago < 6 {
-                tracing::debug!("message was too new to ensure source existence, skipping");
                 return Ok(());
             }
         }
@@ -82,27 +94,34 @@ pub async fn process_group_photo(handler: Arc<Handler>, job: faktory::Job) -> Re
         ),
     }
 
+    // Images sent through the bot already have a source, so they are only
+    // worth looking at when new sources can be added to them.
+    let via_bot =
+        matches!(message.via_bot, Some(tgbotapi::User { id, .. }) if id == handler.bot_user.id);
+    if via_bot && policy.delivery != GroupSourceDelivery::Edit {
+        tracing::trace!("message was sent through bot, skipping");
+        return Ok(());
+    }
+
     let best_photo = find_best_photo(photo_sizes).unwrap();
     let mut matches = match_image(
         &handler.telegram,
         &handler.redis,
         &handler.fuzzysearch,
         best_photo,
-        Some(3),
+        policy.search_distance(),
     )
     .await?
     .1;
-    sort_results(
+    sort_group_results(
         &handler.conn,
+        message.chat.id,
         message.from.as_ref().unwrap().id,
         &mut matches,
     )
     .await?;
 
-    let wanted_matches = matches
-        .iter()
-        .filter(|m| m.distance.unwrap() <= MAX_SOURCE_DISTANCE)
-        .collect::<Vec<_>>();
+    let mut wanted_matches = policy.filter(&matches);
 
     if wanted_matches.is_empty() {
         tracing::debug!("found no matches for group image");
@@ -112,7 +131,16 @@ pub async fn process_group_photo(handler: Arc<Handler>, job: faktory::Job) -> Re
     let links = extract_links(&message);
     let sites = handler.sites.lock().await;
 
-    if wanted_matches
+    // Images sent through the bot always contain the source they were sent
+    // from, so only the other sources should be added to them.
+    if via_bot {
+        wanted_matches.retain(|m| !link_was_seen(&sites, &links, &m.url()));
+
+        if wanted_matches.is_empty() {
+            tracing::debug!("message sent through bot had no new sources");
+            return Ok(());
+        }
+    } else if wanted_matches
         .iter()
         .any(|m| link_was_seen(&sites, &links, &m.url()))
     {
@@ -122,54 +150,90 @@ pub async fn process_group_photo(handler: Arc<Handler>, job: faktory::Job) -> Re
 
     drop(sites);
 
-    let twitter_matches = wanted_matches
-        .iter()
-        .filter(|m| matches!(m.site_info, Some(fuzzysearch::SiteInfo::Twitter)))
-        .count();
-    let other_matches = wanted_matches.len() - twitter_matches;
-
     // Prevents memes from getting a million links in chat
-    if other_matches <= 1 && twitter_matches >= NOISY_SOURCE_COUNT {
+    if policy.is_noisy(&wanted_matches) {
         tracing::trace!(
-            twitter_matches,
-            other_matches,
+            matches = wanted_matches.len(),
+            rule = ?policy.noisy_rule,
             "had too many matches, ignoring"
         );
         return Ok(());
     }
 
+    wanted_matches.truncate(policy.link_limit());
+
     let lang = message
         .from
         .as_ref()
         .and_then(|from| from.language_code.as_deref());
 
-    let text = handler
+    // Only edit a message when it can be matched to the inline result it was
+    // sent from, otherwise reply like any other image.
+    let inline_message_id = match inline_result_url(&message) {
+        Some(url) if via_bot => {
+            let user_id = message.from.as_ref().unwrap().id;
+            InlineHistory::take_sent_message(&handler.redis, user_id, url).await?
+        }
+        _ => None,
+    };
+
+    if let Some(inline_message_id) = inline_message_id {
+        tracing::debug!("adding sources to message sent through bot");
+
+        let mut inline_keyboard = message
+            .reply_markup
+            .as_ref()
+            .map(|markup| markup.inline_keyboard.clone())
+            .unwrap_or_default();
+        handler
+            .get_fluent_bundle(lang, |bundle| {
+                inline_keyboard.extend(source_buttons(&wanted_matches, bundle))
+            })
+            .await;
+
+        return enqueue_group_source(
+            &handler,
+            GroupSource {
+                chat_id: message.chat.id.to_string(),
+                reply_to_message_id: message.message_id,
+                text: String::new(),
+                reply_markup: Some(InlineKeyboardMarkup { inline_keyboard }),
+                inline_message_id: Some(inline_message_id),
+                delete_after: 0,
+            },
+        )
+        .await;
+    }
+
+    let (text, reply_markup) = handler
         .get_fluent_bundle(lang, |bundle| {
-            if wanted_matches.len() == 1 {
-                let mut args = fluent::FluentArgs::new();
-                let m = wanted_matches.first().unwrap();
-                args.insert("link", m.url().into());
-
-                if let Some(rating) = get_rating_bundle_name(&m.rating) {
-                    let rating = get_message(bundle, rating, None).unwrap();
-                    args.insert("rating", rating.into());
+            if policy.delivery != GroupSourceDelivery::Reply {
+                let text = get_message(bundle, "automatic-compact", None).unwrap();
+                let markup = InlineKeyboardMarkup {
+                    inline_keyboard: source_buttons(&wanted_matches, bundle),
+                };
+
+                (text, Some(markup))
+            } else if wanted_matches.len() == 1 {
+                let (args, has_rating) = match_args(bundle, wanted_matches.first().unwrap());
+
+                let text = if has_rating {
                     get_message(bundle, "automatic-single", Some(args)).unwrap()
                 } else {
                     get_message(bundle, "automatic-single-unknown", Some(args)).unwrap()
-                }
+                };
+
+                (text, None)
             } else {
                 let mut buf = String::new();
 
                 buf.push_str(&get_message(bundle, "automatic-multiple", None).unwrap());
                 buf.push('\n');
 
-                for result in wanted_matches {
-                    let mut args = fluent::FluentArgs::new();
-                    args.insert("link", result.url().into());
+                for result in &wanted_matches {
+                    let (args, has_rating) = match_args(bundle, result);
 
-                    let message = if let Some(rating) = get_rating_bundle_name(&result.rating) {
-                        let rating = get_message(bundle, rating, None).unwrap();
-                        args.insert("rating", rating.into());
+                    let message = if has_rating {
                         get_message(bundle, "automatic-multiple-result", Some(args)).unwrap()
                     } else {
                         get_message(bundle, "automatic-multiple-result-unknown", Some(args))
@@ -180,16 +244,108 @@ pub async fn process_group_photo(handler: Arc<Handler>, job: faktory::Job) -> Re
                     buf.push('\n');
                 }
 
-                buf
+                (buf, None)
             }
         })
         .await;
 
-    let data = serde_json::to_value(&GroupSource {
-        chat_id: message.chat.id.to_string(),
-        reply_to_message_id: message.message_id,
-        text,
-    })?;
+    enqueue_group_source(
+        &handler,
+        GroupSource {
+            chat_id: message.chat.id.to_string(),
+            reply_to_message_id: message.message_id,
+            text,
+            reply_markup,
+            inline_message_id: None,
+            delete_after: policy.delete_after,
+        },
+    )
+    .await
+}
+
+/// Remember which group message a channel post was forwarded to, so the
+/// group can get a source reply if the channel post gets a source.
+async fn remember_linked_forward(
+    handler: &Handler,
+    message: &tgbotapi::Message,
+) -> anyhow::Result<()> {
+    let (channel, channel_message_id) =
+        match (&message.forward_from_chat, message.forward_from_message_id) {
+            (Some(channel), Some(channel_message_id)) => (channel, channel_message_id),
+            _ => return Ok(()),
+        };
+
+    LinkedForward::set(
+        &handler.redis,
+        channel.id,
+        channel_message_id,
+        &LinkedForward {
+            chat_id: message.chat.id,
+            message_id: message.message_id,
+        },
+    )
+    .await
+}
+
+/// Reply with sources to the group message a channel post was forwarded to,
+/// after the bot added those sources to the channel post.
+pub async fn reply_linked_forward(
+    handler: &Handler,
+    channel_id: i64,
+    channel_message_id: i32,
+    firsts: &[(Sites, String)],
+) -> anyhow::Result<()> {
+    let forward = match LinkedForward::get(&handler.redis, channel_id, channel_message_id).await? {
+        Some(forward) => forward,
+        None => return Ok(()),
+    };
+
+    let policy = GroupSourcePolicy::load(&handler.conn, forward.chat_id).await?;
+    if !policy.linked_replies {
+        tracing::trace!("group does not want replies for linked channel sources");
+        return Ok(());
+    }
+
+    tracing::debug!(
+        chat_id = forward.chat_id,
+        message_id = forward.message_id,
+        "replying to linked channel forward"
+    );
+
+    let text = handler
+        .get_fluent_bundle(None, |bundle| {
+            get_message(bundle, "automatic-compact", None).unwrap()
+        })
+        .await;
+
+    let inline_keyboard = firsts
+        .iter()
+        .map(|(site, url)| {
+            vec![InlineKeyboardButton {
+                text: site.as_str().to_string(),
+                url: Some(url.to_owned()),
+                ..Default::default()
+            }]
+        })
+        .collect();
+
+    enqueue_group_source(
+        handler,
+        GroupSource {
+            chat_id: forward.chat_id.to_string(),
+            reply_to_message_id: forward.message_id,
+            text,
+            reply_markup: Some(InlineKeyboardMarkup { inline_keyboard }),
+            inline_message_id: None,
+            delete_after: policy.delete_after,
+        },
+    )
+    .await
+    .map_err(Into::into)
+}
+
+async fn enqueue_group_source(handler: &Handler, source: GroupSource) -> Result<(), Error> {
+    let data = serde_json::to_value(&source)?;
 
     let mut job = faktory::Job::new("group_source", vec![data]).on_queue("foxbot_background");
     job.custom = get_faktory_custom();
@@ -199,6 +355,67 @@ pub async fn process_group_photo(handler: Arc<Handler>, job: faktory::Job) -> Re
     Ok(())
 }
 
+/// Delete a source reply later, if the group wants replies removed.
+async fn schedule_source_delete(
+    handler: &Handler,
+    chat_id: &str,
+    message_id: i32,
+    delete_after: u64,
+) -> Result<(), Error> {
+    if delete_after == 0 {
+        return Ok(());
+    }
+
+    let data = serde_json::to_value(&GroupSourceDelete {
+        chat_id: chat_id.to_string(),
+        message_id,
+    })?;
+
+    let mut job =
+        faktory::Job::new("group_source_delete", vec![data]).on_queue("foxbot_background");
+    job.at = Some(chrono::Utc::now() + chrono::Duration::seconds(delete_after as i64));
+    job.custom = get_faktory_custom();
+
+    handler.enqueue(job).await;
+
+    Ok(())
+}
+
+#[tracing::instrument(skip(handler, job), fields(job_id = job.id(), chat_id))]
+pub async fn process_group_source_delete(
+    handler: Arc<Handler>,
+    job: faktory::Job,
+) -> Result<(), Error> {
+    let data: serde_json::Value = job
+        .args()
+        .iter()
+        .next()
+        .ok_or(Error::MissingData)?
+        .to_owned();
+
+    let GroupSourceDelete {
+        chat_id,
+        message_id,
+    } = serde_json::value::from_value(data)?;
+    tracing::Span::current().record("chat_id", &chat_id.as_str());
+
+    let delete_message = tgbotapi::requests::DeleteMessage {
+        chat_id: chat_id.into(),
+        message_id,
+    };
+
+    match handler.telegram.make_request(&delete_message).await {
+        // The message may have already been deleted or the bot may have
+        // lost permission, neither of which can be fixed by retrying.
+        Ok(_)
+        | Err(tgbotapi::Error::Telegram(tgbotapi::TelegramError {
+            error_code: Some(400),
+            ..
+        })) => Ok(()),
+        Err(err) => Err(err.into()),
+    }
+}
+
 #[tracing::instrument(skip(handler, job), fields(job_id = job.id(), chat_id))]
 pub async fn process_group_source(handler: Arc<Handler>, job: faktory::Job) -> Result<(), Error> {
     use tgbotapi::requests::SendMessage;
@@ -216,6 +433,9 @@ pub async fn process_group_source(handler: Arc<Handler>, job: faktory::Job) -> R
         chat_id,
         reply_to_message_id,
         text,
+        reply_markup,
+        inline_message_id,
+        delete_after,
     } = serde_json::value::from_value(data.clone())?;
     let chat_id: &str = &chat_id;
     tracing::Span::current().record("chat_id", &chat_id);
@@ -232,16 +452,32 @@ pub async fn process_group_source(handler: Arc<Handler>, job: faktory::Job) -> R
         return Ok(());
     }
 
-    let message = SendMessage {
-        chat_id: chat_id.into(),
-        reply_to_message_id: Some(reply_to_message_id),
-        disable_web_page_preview: Some(true),
-        disable_notification: Some(true),
-        text,
-        ..Default::default()
+    let result = match inline_message_id {
+        Some(inline_message_id) => {
+            let edit = EditMessageReplyMarkup {
+                inline_message_id: Some(inline_message_id),
+                reply_markup: reply_markup.map(ReplyMarkup::InlineKeyboardMarkup),
+                ..Default::default()
+            };
+
+            handler.telegram.make_request(&edit).await.map(|_| None)
+        }
+        None => {
+            let message = SendMessage {
+                chat_id: chat_id.into(),
+                reply_to_message_id: Some(reply_to_message_id),
+                disable_web_page_preview: Some(true),
+                disable_notification: Some(true),
+                text,
+                reply_markup: reply_markup.map(ReplyMarkup::InlineKeyboardMarkup),
+                ..Default::default()
+            };
+
+            handler.telegram.make_request(&message).await.map(Some)
+        }
     };
 
-    match handler.telegram.make_request(&message).await {
+    match result {
         Err(tgbotapi::Error::Telegram(tgbotapi::TelegramError {
             parameters:
                 Some(tgbotapi::ResponseParameters {
@@ -266,6 +502,108 @@ pub async fn process_group_source(handler: Arc<Handler>, job: faktory::Job) -> R
 
             Ok(())
         }
+        Ok(sent) => {
+            if let Ok(group_id) = chat_id.parse() {
+                handler
+                    .record_action(
+                        group_id,
+                        ChatAction::GroupSourced,
+                        Some(reply_to_message_id),
+                    )
+                    .await;
+            }
+
+            match sent {
+                Some(sent) => {
+                    schedule_source_delete(&handler, chat_id, sent.message_id, delete_after).await
+                }
+                None => Ok(()),
+            }
+        }
+        Err(tgbotapi::Error::Telegram(tgbotapi::TelegramError {
+            error_code: Some(400),
+            ..
+        })) => Ok(()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Remember a group image and reply if a similar image was posted recently,
+/// when the group has repost detection enabled.
+#[tracing::instrument(skip(handler, message, photo_sizes))]
+async fn check_repost(
+    handler: &Handler,
+    message: &tgbotapi::Message,
+    photo_sizes: &[tgbotapi::PhotoSize],
+) -> anyhow::Result<()> {
+    let policy = RepostPolicy::load(&handler.conn, message.chat.id).await?;
+    if !policy.enabled {
+        return Ok(());
+    }
+
+    let best_photo = find_best_photo(photo_sizes).unwrap();
+    let hash = hash_image(&handler.telegram, &handler.redis, best_photo).await?;
+
+    let earlier = GroupRepost::find(
+        &handler.conn,
+        message.chat.id,
+        hash,
+        policy.max_distance as i64,
+        policy.window_hours as i32,
+    )
+    .await?;
+
+    GroupRepost::record(&handler.conn, message.chat.id, message.message_id, hash).await?;
+
+    // A retried job may find the message itself.
+    let earlier = match earlier {
+        Some(earlier) if earlier.message_id != message.message_id => earlier,
+        _ => return Ok(()),
+    };
+
+    tracing::debug!(message_id = earlier.message_id, "image was a repost");
+
+    let hours = (chrono::Utc::now() - earlier.posted_at).num_hours();
+    let lang = message
+        .from
+        .as_ref()
+        .and_then(|from| from.language_code.as_deref());
+    let link = message_link(&message.chat, earlier.message_id);
+
+    let (text, reply_markup) = handler
+        .get_fluent_bundle(lang, |bundle| {
+            let text = get_message(
+                bundle,
+                "repost-detected",
+                Some(fluent_args!["hours" => hours]),
+            )
+            .unwrap();
+
+            let reply_markup = link.map(|link| {
+                ReplyMarkup::InlineKeyboardMarkup(InlineKeyboardMarkup {
+                    inline_keyboard: vec![vec![InlineKeyboardButton {
+                        text: get_message(bundle, "repost-view", None).unwrap(),
+                        url: Some(link),
+                        ..Default::default()
+                    }]],
+                })
+            });
+
+            (text, reply_markup)
+        })
+        .await;
+
+    let send_message = SendMessage {
+        chat_id: message.chat_id(),
+        reply_to_message_id: Some(message.message_id),
+        text,
+        reply_markup,
+        disable_notification: Some(true),
+        ..Default::default()
+    };
+
+    match handler.telegram.make_request(&send_message).await {
+        // The message may have been deleted before the reply was sent.
         Ok(_)
         | Err(tgbotapi::Error::Telegram(tgbotapi::TelegramError {
             error_code: Some(400),
@@ -275,6 +613,19 @@ pub async fn process_group_source(handler: Arc<Handler>, job: faktory::Job) -> R
     }
 }
 
+/// Get the direct link to the media of a message sent from an inline result,
+/// which is the first button added to results.
+fn inline_result_url(message: &tgbotapi::Message) -> Option<&str> {
+    message
+        .reply_markup
+        .as_ref()?
+        .inline_keyboard
+        .first()?
+        .first()?
+        .url
+        .as_deref()
+}
+
 /// Check if group is linked to a channel.
 #[tracing::instrument(skip(handler, message))]
 async fn store_linked_chat(handler: &Handler, message: &tgbotapi::Message) -> anyhow::Result<()> {
@@ -450,7 +801,7 @@ pub async fn process_group_mediagroup_hash(
     .await?
     .unwrap_or(false)
     {
-        tracing::debug!("group doesn't want inline album sources, uploading image to cdn bucket");
+        tracing::debug!("group doesn't want inline album sources, uploading image to storage");
 
         let kind = infer::get(&data).unwrap();
 
@@ -459,16 +810,10 @@ pub async fn process_group_mediagroup_hash(
             message.message.media_group_id.as_ref().unwrap(),
             best_photo.file_id,
         );
-        let put = rusoto_s3::PutObjectRequest {
-            acl: Some("download".into()),
-            bucket: handler.config.s3_bucket.to_string(),
-            content_type: Some(kind.mime_type().into()),
-            key: path,
-            content_length: Some(data.len() as i64),
-            body: Some(data.clone().into()),
-            ..Default::default()
-        };
-        handler.s3.put_object(put).await.unwrap();
+        handler
+            .storage
+            .put_download(&path, kind.mime_type(), bytes::Bytes::from(data.clone()))
+            .await?;
     }
 
     let hash = tokio::task::spawn_blocking(move || fuzzysearch::hash_bytes(&data))
@@ -481,10 +826,13 @@ pub async fn process_group_mediagroup_hash(
         .await
         .context("unable to set file cache")?;
 
-    let mut sources = lookup_single_hash(&handler.fuzzysearch, hash, Some(3)).await?;
+    let policy = GroupSourcePolicy::load(&handler.conn, message.message.chat.id).await?;
+    let mut sources =
+        lookup_single_hash(&handler.fuzzysearch, hash, policy.search_distance()).await?;
 
-    sort_results(
+    sort_group_results(
         &handler.conn,
+        message.message.chat.id,
         message.message.from.as_ref().unwrap().id,
         &mut sources,
     )
@@ -552,6 +900,8 @@ pub async fn process_group_mediagroup_check(
         .as_ref()
         .and_then(|from| from.language_code.as_deref());
 
+    let policy = GroupSourcePolicy::load(&handler.conn, first_message.chat.id).await?;
+
     if GroupConfig::get(
         &handler.conn,
         first_message.chat.id,
@@ -637,12 +987,13 @@ pub async fn process_group_mediagroup_check(
             &handler.redis,
             &handler.fuzzysearch,
             best_photo,
-            Some(3),
+            policy.search_distance(),
         )
         .await?
         .1;
-        sort_results(
+        sort_group_results(
             &handler.conn,
+            message.message.chat.id,
             message.message.from.as_ref().unwrap().id,
             &mut sources,
         )
@@ -664,13 +1015,17 @@ pub async fn process_group_mediagroup_check(
     handler
         .get_fluent_bundle(lang_code, |bundle| {
             for (index, message) in messages.iter().enumerate() {
-                let urls = message
-                    .sources
-                    .as_ref()
-                    .unwrap()
+                let sources = policy.filter(message.sources.as_ref().unwrap());
+                if policy.is_noisy(&sources) {
+                    continue;
+                }
+
+                // Albums can have many images, so only show a couple links
+                // for each.
+                let urls = sources
                     .iter()
                     .map(|file| file.url())
-                    .take(2)
+                    .take(policy.link_limit().min(2))
                     .collect::<Vec<_>>();
                 if urls.is_empty() {
                     continue;
@@ -691,6 +1046,11 @@ pub async fn process_group_mediagroup_check(
         })
         .await;
 
+    if buf.is_empty() {
+        tracing::debug!("media group had no wanted sources, skipping message");
+        return Ok(());
+    }
+
     let first_message = &messages.first().as_ref().unwrap().message;
 
     let send_message = tgbotapi::requests::SendMessage {
@@ -702,9 +1062,15 @@ pub async fn process_group_mediagroup_check(
         ..Default::default()
     };
 
-    handler.telegram.make_request(&send_message).await?;
+    let sent = handler.telegram.make_request(&send_message).await?;
 
-    Ok(())
+    schedule_source_delete(
+        &handler,
+        &first_message.chat.id.to_string(),
+        sent.message_id,
+        policy.delete_after,
+    )
+    .await
 }
 
 #[tracing::instrument(skip(handler, job), fields(job_id = job.id(), media_group_id))]
@@ -735,12 +1101,7 @@ pub async fn process_group_mediagroup_prune(
         let best_photo = find_best_photo(&message.message.photo.as_deref().unwrap()).unwrap();
 
         let path = format!("mg/{}/{}", media_group_id, best_photo.file_id);
-        let delete = rusoto_s3::DeleteObjectRequest {
-            bucket: handler.config.s3_bucket.to_string(),
-            key: path,
-            ..Default::default()
-        };
-        handler.s3.delete_object(delete).await.unwrap();
+        handler.storage.delete(&path).await?;
     }
 
     MediaGroup::purge_media_group(&handler.conn, media_group_id).await?;
@@ -749,3 +1110,232 @@ pub async fn process_group_mediagroup_prune(
 
     Ok(())
 }
+
+#[tracing::instrument(skip(handler, job), fields(job_id = job.id(), chat_id))]
+pub async fn process_group_mirror(handler: Arc<Handler>, job: faktory::Job) -> Result<(), Error> {
+    let data: serde_json::Value = job
+        .args()
+        .iter()
+        .next()
+        .ok_or(Error::MissingData)?
+        .to_owned();
+
+    let message: tgbotapi::Message = serde_json::value::from_value(data)?;
+    tracing::Span::current().record("chat_id", &message.chat.id);
+
+    let from = match &message.from {
+        Some(from) => from,
+        None => return Ok(()),
+    };
+
+    // The setting may have been changed since the job was enqueued.
+    if !GroupConfig::get(&handler.conn, message.chat.id, GroupConfigKey::AutoMirror)
+        .await?
+        .unwrap_or(false)
+    {
+        tracing::debug!("group no longer has automatic mirroring enabled");
+        return Ok(());
+    }
+
+    if has_media(&message) {
+        tracing::debug!("message already contained media, skipping");
+        return Ok(());
+    }
+
+    let links = extract_links(&message);
+
+    let MirrorImages {
+        results,
+        mut missing,
+    } = {
+        let mut sites = handler.sites.lock().await;
+
+        let allowed = auto_mirror_sites(&handler.conn, message.chat.id, &sites).await?;
+        let links = allowed_mirror_links(links, &mut sites, &allowed).await;
+
+        if links.is_empty() {
+            tracing::debug!("message had no links from allowed sites");
+            return Ok(());
+        }
+
+        find_mirror_images(from, links, &mut sites).await?
+    };
+
+    // Unlike the mirror command, nobody asked for these images, so there is
+    // no need to explain when nothing was found.
+    if results.is_empty() {
+        tracing::debug!("found no images to mirror");
+        return Ok(());
+    }
+
+    let requests = mirror_requests(
+        message.chat_id(),
+        message.message_id,
+        &results,
+        &mut missing,
+    )
+    .await;
+
+    for request in requests {
+        match request {
+            MirrorRequest::Photo(photo) => {
+                handler.telegram.make_request(&photo).await?;
+            }
+            MirrorRequest::Video(video) => {
+                handler.telegram.make_request(&video).await?;
+            }
+            MirrorRequest::MediaGroup(media_group) => {
+                handler.telegram.make_request(&media_group).await?;
+            }
+        }
+    }
+
+    if !missing.is_empty() {
+        let text = handler
+            .get_fluent_bundle(from.language_code.as_deref(), |bundle| {
+                mirror_missing_message(bundle, &missing)
+            })
+            .await;
+
+        let send_message = SendMessage {
+            chat_id: message.chat_id(),
+            reply_to_message_id: Some(message.message_id),
+            text,
+            disable_web_page_preview: Some(true),
+            disable_notification: Some(true),
+            ..Default::default()
+        };
+
+        handler.telegram.make_request(&send_message).await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use foxbot_models::{FileCache, GroupConfig, GroupConfigKey};
+    use foxbot_sites::InMemoryReverseSearch;
+
+    use crate::test_helpers::{get_handler, job};
+
+    #[test]
+    fn test_inline_result_url() {
+        let button = |url: Option<&str>| tgbotapi::InlineKeyboardButton {
+            text: "Direct Link".to_string(),
+            url: url.map(ToString::to_string),
+            ..Default::default()
+        };
+
+        let message = tgbotapi::Message {
+            reply_markup: Some(tgbotapi::InlineKeyboardMarkup {
+                inline_keyboard: vec![vec![
+                    button(Some("https://example.com/image.png")),
+                    button(Some("https://example.com/post")),
+                ]],
+            }),
+            ..Default::default()
+        };
+        assert_eq!(
+            super::inline_result_url(&message),
+            Some("https://example.com/image.png")
+        );
+
+        let message = tgbotapi::Message {
+            reply_markup: Some(tgbotapi::InlineKeyboardMarkup {
+                inline_keyboard: vec![vec![button(None)]],
+            }),
+            ..Default::default()
+        };
+        assert_eq!(super::inline_result_url(&message), None);
+
+        assert_eq!(super::inline_result_url(&Default::default()), None);
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_process_group_photo() {
+        let _ = tracing_subscriber::fmt::try_init();
+
+        use super::{process_group_photo, GroupSource};
+
+        let search = InMemoryReverseSearch::new();
+        search.insert(
+            42,
+            fuzzysearch::File {
+                site_id: 123,
+                site_info: Some(fuzzysearch::SiteInfo::FurAffinity(
+                    fuzzysearch::FurAffinityFile { file_id: 123 },
+                )),
+                rating: Some(fuzzysearch::Rating::General),
+                ..Default::default()
+            },
+        );
+
+        let (handler, jobs) = get_handler(search).await;
+        let chat_id = -100_456;
+
+        FileCache::set(&handler.redis, "group-photo", 42)
+            .await
+            .unwrap();
+        GroupConfig::set(
+            &handler.conn,
+            GroupConfigKey::HasLinkedChat,
+            chat_id,
+            None::<i64>,
+        )
+        .await
+        .unwrap();
+        GroupConfig::set(&handler.conn, GroupConfigKey::GroupAdd, chat_id, false)
+            .await
+            .unwrap();
+
+        let message = tgbotapi::Message {
+            message_id: 2,
+            chat: tgbotapi::Chat {
+                id: chat_id,
+                chat_type: tgbotapi::ChatType::Supergroup,
+                ..Default::default()
+            },
+            from: Some(tgbotapi::User {
+                id: 1,
+                ..Default::default()
+            }),
+            photo: Some(vec![tgbotapi::PhotoSize {
+                file_id: "file-id".to_string(),
+                file_unique_id: "group-photo".to_string(),
+                width: 100,
+                height: 100,
+                ..Default::default()
+            }]),
+            ..Default::default()
+        };
+
+        process_group_photo(handler.clone(), job("group_photo", &message))
+            .await
+            .unwrap();
+        assert!(
+            jobs.lock().unwrap().is_empty(),
+            "group without automatic sources should be skipped"
+        );
+
+        GroupConfig::set(&handler.conn, GroupConfigKey::GroupAdd, chat_id, true)
+            .await
+            .unwrap();
+
+        process_group_photo(handler.clone(), job("group_photo", &message))
+            .await
+            .unwrap();
+
+        let jobs = jobs.lock().unwrap();
+        assert_eq!(jobs.len(), 1, "known image should enqueue a source");
+        assert_eq!(jobs[0].kind(), "group_source");
+
+        let source: GroupSource = serde_json::value::from_value(jobs[0].args()[0].clone()).unwrap();
+        assert_eq!(source.chat_id, chat_id.to_string());
+        assert_eq!(source.reply_to_message_id, 2);
+        assert!(source
+            .text
+            .contains("https://www.furaffinity.net/view/123/"));
+    }
+}
@@ -15,8 +15,10 @@ use foxbot_sites::BoxedSite;
 use foxbot_utils::*;
 
 mod channel;
+mod digest;
 mod group;
 mod subscribe;
+mod video;
 
 fn main() {
     use opentelemetry::KeyValue;
@@ -29,6 +31,11 @@ fn main() {
     };
     let config_clone = config.clone();
 
+    let storage_config = match envy::from_env::<StorageConfig>() {
+        Ok(config) => config,
+        Err(err) => panic!("{:#?}", err),
+    };
+
     let runtime = Arc::new(
         tokio::runtime::Builder::new_current_thread()
             .enable_all()
@@ -97,10 +104,12 @@ fn main() {
         .block_on(pool)
         .expect("unable to create database pool");
 
+    let fuzzysearch = foxbot_sites::new_fuzzysearch(config.fautil_apitoken, config.fautil_endpoint);
+
     let sites = runtime.block_on(foxbot_sites::get_all_sites(
         config.fa_a,
         config.fa_b,
-        config.fautil_apitoken.clone(),
+        fuzzysearch.clone(),
         config.weasyl_apitoken,
         config.twitter_consumer_key,
         config.twitter_consumer_secret,
@@ -112,22 +121,15 @@ fn main() {
     ));
 
     let telegram = tgbotapi::Telegram::new(config.telegram_apitoken);
-    let fuzzysearch = fuzzysearch::FuzzySearch::new(config.fautil_apitoken);
 
     let redis = redis::Client::open(config.redis_dsn).unwrap();
     let redis = runtime
         .block_on(redis::aio::ConnectionManager::new(redis))
         .expect("unable to open redis connection");
 
-    let region = rusoto_core::Region::Custom {
-        name: config.s3_region,
-        endpoint: config.s3_endpoint,
-    };
-
-    let client = rusoto_core::request::HttpClient::new().unwrap();
-    let provider =
-        rusoto_credential::StaticProvider::new_minimal(config.s3_token, config.s3_secret);
-    let s3 = rusoto_s3::S3Client::new_with(client, provider, region);
+    let storage = runtime
+        .block_on(get_storage(&storage_config))
+        .expect("unable to configure storage");
 
     let producer = faktory::Producer::connect(None).unwrap();
 
@@ -139,13 +141,13 @@ fn main() {
         sites: tokio::sync::Mutex::new(sites),
         telegram: Arc::new(telegram),
         bot_user,
-        producer: Arc::new(Mutex::new(producer)),
+        producer: JobProducer::Faktory(Arc::new(Mutex::new(producer))),
         fuzzysearch,
         conn: pool,
         redis,
         langs: load_langs(),
         best_langs: Default::default(),
-        s3,
+        storage,
         config: config_clone,
     });
 
@@ -153,8 +155,11 @@ fn main() {
 
     worker_environment.register("channel_update", channel::process_channel_update);
     worker_environment.register("channel_edit", channel::process_channel_edit);
+    worker_environment.register("channel_recheck", channel::process_channel_recheck);
     worker_environment.register("group_photo", group::process_group_photo);
     worker_environment.register("group_source", group::process_group_source);
+    worker_environment.register("group_source_delete", group::process_group_source_delete);
+    worker_environment.register("group_mirror", group::process_group_mirror);
     worker_environment.register(
         "group_mediagroup_message",
         group::process_group_mediagroup_message,
@@ -173,6 +178,9 @@ fn main() {
     );
     worker_environment.register("hash_new", subscribe::process_hash_new);
     worker_environment.register("hash_notify", subscribe::process_hash_notify);
+    worker_environment.register("artist_notify", subscribe::process_artist_notify);
+    worker_environment.register("video_transcode", video::process_video_transcode);
+    worker_environment.register("chat_digest", digest::process_chat_digest);
 
     let faktory = worker_environment.finalize();
 
@@ -244,9 +252,6 @@ pub enum Error {
 
 type BestLangs = std::collections::HashMap<String, LangBundle>;
 
-const MAX_SOURCE_DISTANCE: u64 = 3;
-const NOISY_SOURCE_COUNT: usize = 4;
-
 #[derive(serde::Deserialize, Debug, Clone)]
 struct Config {
     // Site config
@@ -267,19 +272,15 @@ struct Config {
 
     // FuzzySearch config
     fautil_apitoken: String,
-
-    // S3 compatible storage config
-    s3_bucket: String,
-    s3_endpoint: String,
-    s3_region: String,
-    s3_token: String,
-    s3_secret: String,
+    fautil_endpoint: Option<String>,
 
     // Worker configuration
     background_workers: Option<usize>,
     database_url: String,
     redis_dsn: String,
     internet_url: String,
+    internal_secret: Option<String>,
+    channel_recheck_hours: Option<i64>,
 }
 
 #[derive(serde::Deserialize, serde::Serialize)]
@@ -288,6 +289,24 @@ struct MessageEdit {
     message_id: i32,
     media_group_id: Option<String>,
     firsts: Vec<(Sites, String)>,
+    /// Artists from each source, for crediting them in the caption.
+    #[serde(default)]
+    artists: Vec<String>,
+    /// The post's existing caption, kept when appending to it.
+    #[serde(default)]
+    caption: Option<String>,
+    #[serde(default)]
+    caption_entities: Option<Vec<tgbotapi::MessageEntity>>,
+}
+
+#[derive(serde::Deserialize, serde::Serialize)]
+struct ChannelRecheck {
+    chat_id: i64,
+    message_id: i32,
+    /// Unix timestamp of when the post was first found without a source.
+    first_checked: i64,
+    /// Number of times the post has already been checked again.
+    attempt: u32,
 }
 
 #[derive(serde::Deserialize, serde::Serialize)]
@@ -295,6 +314,27 @@ struct GroupSource {
     chat_id: String,
     reply_to_message_id: i32,
     text: String,
+    reply_markup: Option<InlineKeyboardMarkup>,
+    /// Inline message to add the reply markup to, instead of replying.
+    #[serde(default)]
+    inline_message_id: Option<String>,
+    /// Seconds after which the reply should be deleted, or 0 to keep it.
+    #[serde(default)]
+    delete_after: u64,
+}
+
+#[derive(serde::Deserialize, serde::Serialize)]
+struct GroupSourceDelete {
+    chat_id: String,
+    message_id: i32,
+}
+
+/// Where jobs created while processing other jobs are sent.
+enum JobProducer {
+    Faktory(Arc<Mutex<faktory::Producer<std::net::TcpStream>>>),
+    /// Keep jobs in memory so tests can inspect them.
+    #[cfg(test)]
+    Collected(Arc<Mutex<Vec<faktory::Job>>>),
 }
 
 pub struct Handler {
@@ -304,23 +344,29 @@ pub struct Handler {
     best_langs: tokio::sync::RwLock<BestLangs>,
     config: Config,
 
-    producer: Arc<Mutex<faktory::Producer<std::net::TcpStream>>>,
+    producer: JobProducer,
     telegram: Arc<tgbotapi::Telegram>,
     bot_user: tgbotapi::User,
-    fuzzysearch: fuzzysearch::FuzzySearch,
+    fuzzysearch: foxbot_sites::BoxedReverseSearch,
     conn: sqlx::Pool<sqlx::Postgres>,
     redis: redis::aio::ConnectionManager,
-    s3: rusoto_s3::S3Client,
+    storage: BoxedStorage,
 }
 
 impl Handler {
     /// Enqueue a new Faktory job by spawning a blocking task.
     async fn enqueue(&self, job: faktory::Job) {
-        let producer = self.producer.clone();
-        tokio::task::spawn_blocking(move || {
-            let mut producer = producer.lock().unwrap();
-            producer.enqueue(job).unwrap();
-        });
+        match &self.producer {
+            JobProducer::Faktory(producer) => {
+                let producer = producer.clone();
+                tokio::task::spawn_blocking(move || {
+                    let mut producer = producer.lock().unwrap();
+                    producer.enqueue(job).unwrap();
+                });
+            }
+            #[cfg(test)]
+            JobProducer::Collected(jobs) => jobs.lock().unwrap().push(job),
+        }
     }
 
     /// Build a fluent language bundle for a specified language and cache the
@@ -354,6 +400,21 @@ impl Handler {
         let bundle = lock.get(requested).expect("value just inserted is missing");
         callback(bundle)
     }
+
+    /// Record an action for the chat's digest. Failures are only logged, as
+    /// the action itself has already happened.
+    async fn record_action(
+        &self,
+        chat_id: i64,
+        action: foxbot_models::ChatAction,
+        message_id: Option<i32>,
+    ) {
+        if let Err(err) =
+            foxbot_models::ChatActions::record(&self.conn, chat_id, action, message_id).await
+        {
+            tracing::error!("could not record chat action: {:?}", err);
+        }
+    }
 }
 
 /// Set that chat needs additional time before another message can be sent.
@@ -428,3 +489,86 @@ fn get_custom_span(job: &faktory::Job) -> tracing::Span {
 
     span
 }
+
+#[cfg(test)]
+pub mod test_helpers {
+    use std::sync::{Arc, Mutex};
+
+    use foxbot_sites::InMemoryReverseSearch;
+
+    use crate::{Config, Handler, JobProducer};
+
+    /// Create a handler using the database and Redis from the environment,
+    /// with sources from the provided in-memory reverse search. Enqueued jobs
+    /// are collected in the returned Vec instead of being sent to Faktory.
+    pub async fn get_handler(
+        search: InMemoryReverseSearch,
+    ) -> (Arc<Handler>, Arc<Mutex<Vec<faktory::Job>>>) {
+        let database_url = std::env::var("DATABASE_URL").expect("Missing DATABASE_URL");
+        let redis_dsn = std::env::var("REDIS_DSN").expect("Missing REDIS_DSN");
+
+        let conn = sqlx::postgres::PgPoolOptions::new()
+            .max_connections(2)
+            .connect(&database_url)
+            .await
+            .expect("unable to create database pool");
+
+        let redis = redis::Client::open(redis_dsn.clone()).unwrap();
+        let redis = redis::aio::ConnectionManager::new(redis)
+            .await
+            .expect("unable to open Redis connection");
+
+        let mut langs = std::collections::HashMap::new();
+        langs.insert(
+            "en-US".parse().unwrap(),
+            vec![include_str!("../../langs/en-US/foxbot.ftl").to_string()],
+        );
+
+        let config = Config {
+            fa_a: String::new(),
+            fa_b: String::new(),
+            weasyl_apitoken: String::new(),
+            inkbunny_username: String::new(),
+            inkbunny_password: String::new(),
+            e621_login: String::new(),
+            e621_api_key: String::new(),
+            twitter_consumer_key: String::new(),
+            twitter_consumer_secret: String::new(),
+            telegram_apitoken: String::new(),
+            fautil_apitoken: String::new(),
+            fautil_endpoint: None,
+            background_workers: None,
+            database_url,
+            redis_dsn,
+            internet_url: String::new(),
+            internal_secret: None,
+            channel_recheck_hours: None,
+        };
+
+        let jobs = Arc::new(Mutex::new(Vec::new()));
+
+        let handler = Arc::new(Handler {
+            sites: tokio::sync::Mutex::new(Vec::new()),
+            langs,
+            best_langs: Default::default(),
+            config,
+            producer: JobProducer::Collected(jobs.clone()),
+            telegram: Arc::new(tgbotapi::Telegram::new(String::new())),
+            bot_user: tgbotapi::User::default(),
+            fuzzysearch: Arc::new(search),
+            conn,
+            redis,
+            storage: Arc::new(LocalStorage::new(
+                std::env::temp_dir().join("foxbot-test-storage"),
+                "http://localhost/storage",
+            )),
+        });
+
+        (handler, jobs)
+    }
+
+    /// Build a job with a single argument, as if it was received from Faktory.
+    pub fn job<T: serde::Serialize>(name: &str, data: T) -> faktory::Job {
+        faktory::Job::new(name, vec![serde_json::to_value(data).unwrap()])
+    }
+}
@@ -3,7 +3,20 @@ use std::sync::Arc;
 use fluent::fluent_args;
 
 use crate::*;
-use foxbot_models::Subscriptions;
+use foxbot_models::{ArtistWatches, ChannelSubscription, Subscriptions};
+
+/// How long to wait for more uploads from watched artists before sending a
+/// notification.
+const ARTIST_BATCH_SECONDS: i64 = 60 * 5;
+/// How long to remember which uploads have been sent to a user.
+const ARTIST_SEEN_SECONDS: usize = 60 * 60 * 24 * 7;
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct ArtistUpload {
+    artist: String,
+    site: Sites,
+    link: String,
+}
 
 #[derive(serde::Serialize, serde::Deserialize)]
 struct HashNotify {
@@ -53,19 +66,39 @@ pub async fn process_hash_new(handler: Arc<Handler>, job: faktory::Job) -> Resul
     let hash = message.parse().map_err(|_| Error::MissingData)?;
 
     let subscriptions = Subscriptions::search_subscriptions(&handler.conn, hash).await?;
-    if subscriptions.is_empty() {
-        tracing::trace!("got hash with no subscriptions");
+    let channel_subscriptions =
+        Subscriptions::search_channel_subscriptions(&handler.conn, hash).await?;
+
+    // Most new hashes aren't wanted by anyone, so avoid looking them up.
+    if subscriptions.is_empty()
+        && channel_subscriptions.is_empty()
+        && !ArtistWatches::any_watches(&handler.conn).await?
+    {
+        tracing::trace!("got hash with no subscriptions or watches");
         return Ok(());
     }
 
-    tracing::debug!("found hash with subscriptions, loading full information");
-
-    let matches = lookup_single_hash(&handler.fuzzysearch, hash, Some(3)).await?;
+    // Full information is always needed to check if the artist of the new
+    // file is being watched.
+    let mut matches = lookup_single_hash(&handler.fuzzysearch, hash, Some(3)).await?;
     if matches.is_empty() {
         tracing::warn!("got hash notification but found no matches");
         return Ok(());
     }
 
+    enqueue_artist_uploads(&handler, &matches).await?;
+
+    if !channel_subscriptions.is_empty() {
+        enqueue_channel_edits(&handler, channel_subscriptions, &mut matches).await?;
+    }
+
+    if subscriptions.is_empty() {
+        tracing::trace!("got hash with no subscriptions");
+        return Ok(());
+    }
+
+    tracing::debug!("found hash with subscriptions");
+
     let text = if matches.len() == 1 {
         let file = matches.first().unwrap();
 
@@ -121,6 +154,186 @@ pub async fn process_hash_new(handler: Arc<Handler>, job: faktory::Job) -> Resul
     Ok(())
 }
 
+/// Collect new uploads for users watching the artists of the matched files.
+///
+/// Uploads are stored in Redis for each user and the first upload schedules
+/// an `artist_notify` job, so a burst of uploads results in a single message.
+async fn enqueue_artist_uploads(
+    handler: &Handler,
+    matches: &[fuzzysearch::File],
+) -> anyhow::Result<()> {
+    use redis::AsyncCommands;
+
+    let mut redis = handler.redis.clone();
+
+    // Only exact matches were just uploaded, other results may be older
+    // files of a similar image.
+    for file in matches.iter().filter(|file| file.distance == Some(0)) {
+        let artists = match file.artists.as_deref() {
+            Some(artists) if !artists.is_empty() => artists,
+            _ => continue,
+        };
+
+        let site: Sites = match file.site_name().parse() {
+            Ok(site) => site,
+            _ => continue,
+        };
+
+        let watchers = ArtistWatches::find_watchers(&handler.conn, &site, artists).await?;
+
+        for (user_id, artist) in watchers {
+            let link = file.url();
+
+            // Files are often seen more than once as other files with the
+            // same hash are added, only notify about each file once.
+            let seen_key = format!("artist-watch-seen:{}", user_id);
+            let added: usize = redis.sadd(&seen_key, &link).await?;
+            redis.expire(&seen_key, ARTIST_SEEN_SECONDS).await?;
+            if added == 0 {
+                continue;
+            }
+
+            tracing::debug!(user_id, %artist, "found upload for watched artist");
+
+            let upload = serde_json::to_string(&ArtistUpload {
+                artist,
+                site: site.clone(),
+                link,
+            })?;
+
+            let pending_key = format!("artist-watch-pending:{}", user_id);
+            let pending: usize = redis.rpush(&pending_key, upload).await?;
+            redis.expire(&pending_key, ARTIST_SEEN_SECONDS).await?;
+
+            if pending > 1 {
+                continue;
+            }
+
+            let mut job = faktory::Job::new("artist_notify", vec![serde_json::to_value(user_id)?])
+                .on_queue("foxbot_background");
+            job.at = Some(chrono::Utc::now() + chrono::Duration::seconds(ARTIST_BATCH_SECONDS));
+            job.custom = get_faktory_custom();
+
+            handler.enqueue(job).await;
+        }
+    }
+
+    Ok(())
+}
+
+/// Take all pending uploads for a user.
+async fn take_artist_uploads(
+    redis: &redis::aio::ConnectionManager,
+    user_id: i64,
+) -> anyhow::Result<Vec<ArtistUpload>> {
+    let mut redis = redis.clone();
+    let pending_key = format!("artist-watch-pending:{}", user_id);
+
+    let (uploads,): (Vec<String>,) = redis::pipe()
+        .atomic()
+        .lrange(&pending_key, 0, -1)
+        .del(&pending_key)
+        .ignore()
+        .query_async(&mut redis)
+        .await?;
+
+    uploads
+        .iter()
+        .map(|upload| serde_json::from_str(upload).map_err(Into::into))
+        .collect()
+}
+
+#[tracing::instrument(skip(handler, job), fields(job_id = job.id()))]
+pub async fn process_artist_notify(handler: Arc<Handler>, job: faktory::Job) -> Result<(), Error> {
+    use tgbotapi::requests::SendMessage;
+
+    let data = job
+        .args()
+        .iter()
+        .next()
+        .ok_or(Error::MissingData)?
+        .to_owned();
+    let user_id: i64 = serde_json::value::from_value(data)?;
+
+    let uploads = take_artist_uploads(&handler.redis, user_id).await?;
+    if uploads.is_empty() {
+        tracing::debug!("artist notification had no pending uploads");
+        return Ok(());
+    }
+
+    let text = handler
+        .get_fluent_bundle(None, |bundle| {
+            let mut buf = String::new();
+
+            buf.push_str(&get_message(bundle, "watch-new-uploads", None).unwrap());
+            buf.push('\n');
+
+            for upload in uploads {
+                let args = fluent_args![
+                    "artist" => upload.artist,
+                    "site" => upload.site.as_str(),
+                    "link" => upload.link
+                ];
+
+                buf.push_str(&get_message(bundle, "watch-new-upload-item", Some(args)).unwrap());
+                buf.push('\n');
+            }
+
+            buf
+        })
+        .await;
+
+    let send_message = SendMessage {
+        chat_id: user_id.into(),
+        text,
+        ..Default::default()
+    };
+    handler.telegram.make_request(&send_message).await?;
+
+    Ok(())
+}
+
+/// Edit channel posts that did not have a source when they were posted using
+/// the same process as new channel posts.
+pub(crate) async fn enqueue_channel_edits(
+    handler: &Handler,
+    channel_subscriptions: Vec<ChannelSubscription>,
+    matches: &mut [fuzzysearch::File],
+) -> Result<(), Error> {
+    // Keep order of sites consistent.
+    sort_results_by(&foxbot_models::Sites::default_order(), matches, true);
+
+    let (firsts, artists) = crate::channel::edit_sources(matches);
+
+    for sub in channel_subscriptions {
+        tracing::debug!(
+            chat_id = sub.chat_id,
+            message_id = sub.message_id,
+            "found source for previously unsourced channel post"
+        );
+
+        let data = serde_json::to_value(&MessageEdit {
+            chat_id: sub.chat_id.to_string(),
+            message_id: sub.message_id,
+            media_group_id: sub.media_group_id,
+            firsts: firsts.clone(),
+            artists: artists.clone(),
+            caption: sub.caption,
+            caption_entities: sub.caption_entities,
+        })?;
+
+        let mut job = faktory::Job::new("channel_edit", vec![data]).on_queue("foxbot_background");
+        job.custom = get_faktory_custom();
+
+        handler.enqueue(job).await;
+
+        Subscriptions::remove_channel_subscription(&handler.conn, sub.chat_id, sub.message_id)
+            .await?;
+    }
+
+    Ok(())
+}
+
 #[tracing::instrument(skip(handler, job), fields(job_id = job.id()))]
 pub async fn process_hash_notify(handler: Arc<Handler>, job: faktory::Job) -> Result<(), Error> {
     use tgbotapi::requests::{SendMessage, SendPhoto};
@@ -165,3 +378,148 @@ pub async fn process_hash_notify(handler: Arc<Handler>, job: faktory::Job) -> Re
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use foxbot_models::{ArtistWatches, Sites, Subscriptions};
+    use foxbot_sites::InMemoryReverseSearch;
+
+    use crate::test_helpers::{get_handler, job};
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_hash_new_channel_edit() {
+        let _ = tracing_subscriber::fmt::try_init();
+
+        use super::process_hash_new;
+        use crate::MessageEdit;
+
+        let search = InMemoryReverseSearch::new();
+        search.insert(
+            42,
+            fuzzysearch::File {
+                site_id: 123,
+                site_info: Some(fuzzysearch::SiteInfo::FurAffinity(
+                    fuzzysearch::FurAffinityFile { file_id: 123 },
+                )),
+                ..Default::default()
+            },
+        );
+
+        let (handler, jobs) = get_handler(search).await;
+        let chat_id = -100_456;
+
+        Subscriptions::add_channel_subscription(&handler.conn, chat_id, 5, None, 43, None, None)
+            .await
+            .unwrap();
+
+        process_hash_new(handler.clone(), job("hash_new", "-42"))
+            .await
+            .unwrap();
+        assert!(
+            jobs.lock().unwrap().is_empty(),
+            "unrelated hash should not edit post"
+        );
+
+        process_hash_new(handler.clone(), job("hash_new", "42"))
+            .await
+            .unwrap();
+
+        {
+            let jobs = jobs.lock().unwrap();
+            assert_eq!(jobs.len(), 1, "similar hash should enqueue an edit");
+            assert_eq!(jobs[0].kind(), "channel_edit");
+
+            let edit: MessageEdit =
+                serde_json::value::from_value(jobs[0].args()[0].clone()).unwrap();
+            assert_eq!(edit.chat_id, chat_id.to_string());
+            assert_eq!(edit.message_id, 5);
+        }
+
+        assert!(
+            Subscriptions::search_channel_subscriptions(&handler.conn, 42)
+                .await
+                .unwrap()
+                .is_empty(),
+            "edited post should be removed"
+        );
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_hash_new_artist_watch() {
+        let _ = tracing_subscriber::fmt::try_init();
+
+        use super::{process_hash_new, take_artist_uploads};
+
+        let search = InMemoryReverseSearch::new();
+        for (hash, file_id) in [(100, 1), (200, 2)].iter() {
+            search.insert(
+                *hash,
+                fuzzysearch::File {
+                    site_id: *file_id,
+                    artists: Some(vec!["Watched".to_string()]),
+                    site_info: Some(fuzzysearch::SiteInfo::FurAffinity(
+                        fuzzysearch::FurAffinityFile { file_id: *file_id },
+                    )),
+                    ..Default::default()
+                },
+            );
+        }
+
+        let (handler, jobs) = get_handler(search).await;
+        let user_id = 789;
+
+        // Only remove this user's keys, the database may be shared.
+        let mut redis = handler.redis.clone();
+        let _: () = redis::cmd("DEL")
+            .arg(format!("artist-watch-seen:{}", user_id))
+            .arg(format!("artist-watch-pending:{}", user_id))
+            .query_async(&mut redis)
+            .await
+            .unwrap();
+
+        ArtistWatches::add_watch(&handler.conn, user_id, &Sites::FurAffinity, "watched")
+            .await
+            .unwrap();
+
+        process_hash_new(handler.clone(), job("hash_new", "100"))
+            .await
+            .unwrap();
+        process_hash_new(handler.clone(), job("hash_new", "200"))
+            .await
+            .unwrap();
+        process_hash_new(handler.clone(), job("hash_new", "200"))
+            .await
+            .unwrap();
+
+        {
+            let jobs = jobs.lock().unwrap();
+            assert_eq!(jobs.len(), 1, "uploads should be batched into one job");
+            assert_eq!(jobs[0].kind(), "artist_notify");
+        }
+
+        let uploads = take_artist_uploads(&handler.redis, user_id).await.unwrap();
+        assert_eq!(uploads.len(), 2, "each upload should only be seen once");
+        assert_eq!(uploads[0].artist, "watched");
+        assert_eq!(uploads[0].link, "https://www.furaffinity.net/view/1/");
+
+        assert!(
+            take_artist_uploads(&handler.redis, user_id)
+                .await
+                .unwrap()
+                .is_empty(),
+            "uploads should be removed after being taken"
+        );
+
+        ArtistWatches::remove_watch(&handler.conn, user_id, &Sites::FurAffinity, "Watched")
+            .await
+            .unwrap();
+
+        let _: () = redis::cmd("DEL")
+            .arg(format!("artist-watch-seen:{}", user_id))
+            .query_async(&mut redis)
+            .await
+            .unwrap();
+    }
+}
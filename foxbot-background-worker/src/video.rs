@@ -0,0 +1,400 @@
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::Arc;
+
+use anyhow::Context;
+use tokio::io::AsyncBufReadExt;
+
+use crate::*;
+
+/// Largest video that will be downloaded for transcoding.
+const MAX_VIDEO_SIZE: usize = 100_000_000;
+/// Longest output video, matching the Coconut configuration.
+const MAX_VIDEO_DURATION: f64 = 150.0;
+/// Width of generated thumbnails.
+const THUMBNAIL_WIDTH: u32 = 250;
+
+#[derive(serde::Deserialize, serde::Serialize)]
+struct VideoTranscode {
+    id: i32,
+    source: String,
+    name: String,
+}
+
+#[derive(serde::Serialize)]
+struct VideoProgress<'a> {
+    name: &'a str,
+    progress: Option<String>,
+    video_url: Option<String>,
+    thumb_url: Option<String>,
+    failed: bool,
+}
+
+/// Transcode a video with ffmpeg, producing the same outputs as Coconut.
+///
+/// Progress and completion are sent to the bot's internal video endpoint,
+/// where they become `VideoProgress` and `VideoComplete` events.
+#[tracing::instrument(skip(handler, job), fields(job_id = job.id()))]
+pub async fn process_video_transcode(
+    handler: Arc<Handler>,
+    job: faktory::Job,
+) -> Result<(), Error> {
+    let data = job
+        .args()
+        .iter()
+        .next()
+        .ok_or(Error::MissingData)?
+        .to_owned();
+    let transcode: VideoTranscode = serde_json::value::from_value(data)?;
+
+    tracing::info!(video_id = transcode.id, "starting local video transcode");
+
+    // Retried or restarted jobs may run at the same time as an earlier
+    // attempt for the same video, so each needs its own directory.
+    let dir = std::env::temp_dir().join(format!("foxbot-video-{}-{}", transcode.id, generate_id()));
+    tokio::fs::create_dir_all(&dir)
+        .await
+        .context("unable to create video directory")?;
+
+    let result = transcode_video(&handler, &transcode, &dir).await;
+
+    if let Err(err) = tokio::fs::remove_dir_all(&dir).await {
+        tracing::warn!("unable to remove video directory: {:?}", err);
+    }
+
+    let (video_url, thumb_url) = match result {
+        Ok(urls) => urls,
+        Err(err) => {
+            tracing::error!("unable to transcode video: {:?}", err);
+
+            // Let the users waiting on the video know it failed. If that
+            // isn't possible, fail the job so it gets tried again.
+            report_progress(
+                &handler,
+                &VideoProgress {
+                    name: &transcode.name,
+                    progress: None,
+                    video_url: None,
+                    thumb_url: None,
+                    failed: true,
+                },
+            )
+            .await
+            .map_err(|_| err)?;
+
+            return Ok(());
+        }
+    };
+
+    report_progress(
+        &handler,
+        &VideoProgress {
+            name: &transcode.name,
+            progress: None,
+            video_url: Some(video_url),
+            thumb_url: Some(thumb_url),
+            failed: false,
+        },
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Download, transcode, and upload a video, returning the URLs of the video
+/// and thumbnail.
+async fn transcode_video(
+    handler: &Handler,
+    transcode: &VideoTranscode,
+    dir: &Path,
+) -> anyhow::Result<(String, String)> {
+    let data = CheckFileSize::new(&transcode.source, MAX_VIDEO_SIZE)
+        .into_bytes()
+        .await
+        .context("unable to download video")?;
+
+    let input = dir.join("input");
+    tokio::fs::write(&input, &data)
+        .await
+        .context("unable to save video")?;
+
+    let duration = probe_duration(&input).await?;
+    let height = video_height(duration);
+    tracing::debug!(duration, height, "determined video output");
+
+    let max_duration = MAX_VIDEO_DURATION.to_string();
+    let scale = format!("scale=-2:{}", height);
+
+    let video = dir.join("video.mp4");
+    let mut child = tokio::process::Command::new("ffmpeg")
+        .args(&[
+            "-y",
+            "-nostats",
+            "-loglevel",
+            "error",
+            "-progress",
+            "pipe:1",
+        ])
+        .arg("-i")
+        .arg(&input)
+        .args(&[
+            "-t",
+            max_duration.as_str(),
+            "-vf",
+            scale.as_str(),
+            "-c:v",
+            "libx264",
+            "-preset",
+            "veryfast",
+            "-pix_fmt",
+            "yuv420p",
+            "-c:a",
+            "aac",
+            "-movflags",
+            "+faststart",
+        ])
+        .arg(&video)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("unable to start ffmpeg")?;
+
+    let stdout = child.stdout.take().context("ffmpeg was missing stdout")?;
+    let mut lines = tokio::io::BufReader::new(stdout).lines();
+
+    let output_duration = duration.min(MAX_VIDEO_DURATION);
+    let mut last_reported = 0;
+
+    while let Some(line) = lines.next_line().await? {
+        let percent = match progress_percent(&line, output_duration) {
+            Some(percent) if percent >= last_reported + 10 && percent < 100 => percent,
+            _ => continue,
+        };
+
+        last_reported = percent;
+
+        if let Err(err) = report_progress(
+            handler,
+            &VideoProgress {
+                name: &transcode.name,
+                progress: Some(format!("{}%", percent)),
+                video_url: None,
+                thumb_url: None,
+                failed: false,
+            },
+        )
+        .await
+        {
+            tracing::warn!("unable to report video progress: {:?}", err);
+        }
+    }
+
+    let output = child
+        .wait_with_output()
+        .await
+        .context("unable to wait for ffmpeg")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "ffmpeg failed to transcode video: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    let thumb_scale = format!("scale={}:-2", THUMBNAIL_WIDTH);
+
+    let thumb = dir.join("thumb.jpg");
+    let output = tokio::process::Command::new("ffmpeg")
+        .args(&["-y", "-loglevel", "error", "-i"])
+        .arg(&input)
+        .args(&["-frames:v", "1", "-vf", thumb_scale.as_str()])
+        .arg(&thumb)
+        .output()
+        .await
+        .context("unable to start ffmpeg")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "ffmpeg failed to create thumbnail: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    let video_url = upload_file(
+        handler,
+        &video,
+        format!("video/{}.mp4", transcode.name),
+        "video/mp4",
+    )
+    .await?;
+    let thumb_url = upload_file(
+        handler,
+        &thumb,
+        format!("thumbnail/{}.jpg", transcode.name),
+        "image/jpeg",
+    )
+    .await?;
+
+    Ok((video_url, thumb_url))
+}
+
+/// Extract the first frame of a video as a PNG image.
+pub(crate) async fn first_frame(data: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let dir = std::env::temp_dir().join(format!("foxbot-frame-{}", generate_id()));
+    tokio::fs::create_dir_all(&dir)
+        .await
+        .context("unable to create frame directory")?;
+
+    let result = extract_frame(&dir, data).await;
+
+    if let Err(err) = tokio::fs::remove_dir_all(&dir).await {
+        tracing::warn!("unable to remove frame directory: {:?}", err);
+    }
+
+    result
+}
+
+async fn extract_frame(dir: &Path, data: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let input = dir.join("input");
+    tokio::fs::write(&input, data)
+        .await
+        .context("unable to save video")?;
+
+    let frame = dir.join("frame.png");
+    let output = tokio::process::Command::new("ffmpeg")
+        .args(&["-y", "-loglevel", "error", "-i"])
+        .arg(&input)
+        .args(&["-frames:v", "1"])
+        .arg(&frame)
+        .output()
+        .await
+        .context("unable to start ffmpeg")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "ffmpeg failed to extract frame: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    tokio::fs::read(&frame)
+        .await
+        .context("unable to read frame")
+}
+
+/// Get the duration of a video, in seconds.
+async fn probe_duration(input: &Path) -> anyhow::Result<f64> {
+    let output = tokio::process::Command::new("ffprobe")
+        .args(&[
+            "-v",
+            "error",
+            "-show_entries",
+            "format=duration",
+            "-of",
+            "default=noprint_wrappers=1:nokey=1",
+        ])
+        .arg(input)
+        .output()
+        .await
+        .context("unable to start ffprobe")?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "ffprobe failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse()
+        .context("ffprobe returned invalid duration")
+}
+
+/// Select the output height for a video, matching the Coconut configuration
+/// of 720p for short videos, 480p for medium videos, and 360p otherwise.
+fn video_height(duration: f64) -> u32 {
+    if duration <= 60.0 {
+        720
+    } else if duration <= 120.0 {
+        480
+    } else {
+        360
+    }
+}
+
+/// Parse a line of ffmpeg progress output into a completion percentage.
+fn progress_percent(line: &str, duration: f64) -> Option<u32> {
+    // Despite the name, this value is in microseconds.
+    let out_time = line.strip_prefix("out_time_ms=")?.parse::<f64>().ok()?;
+
+    if duration <= 0.0 {
+        return None;
+    }
+
+    let percent = (out_time / 1_000_000.0 / duration * 100.0).clamp(0.0, 100.0);
+
+    Some(percent as u32)
+}
+
+/// Upload a file to storage, returning the public URL.
+async fn upload_file(
+    handler: &Handler,
+    path: &Path,
+    key: String,
+    content_type: &str,
+) -> anyhow::Result<String> {
+    let data = tokio::fs::read(path)
+        .await
+        .context("unable to read transcoded file")?;
+
+    handler
+        .storage
+        .put(&key, content_type, data.into())
+        .await
+        .context("unable to upload transcoded file")?;
+
+    Ok(handler.storage.public_url(&key))
+}
+
+/// Send video progress to the bot.
+async fn report_progress(handler: &Handler, progress: &VideoProgress<'_>) -> anyhow::Result<()> {
+    let internal_secret = handler
+        .config
+        .internal_secret
+        .as_deref()
+        .context("local video transcoding requires INTERNAL_SECRET")?;
+
+    reqwest::Client::new()
+        .post(&format!("{}/_/video", handler.config.internet_url))
+        .basic_auth("foxbot", Some(internal_secret))
+        .json(progress)
+        .send()
+        .await
+        .context("unable to send video progress")?
+        .error_for_status()
+        .context("bot rejected video progress")?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_video_height() {
+        use super::video_height;
+
+        assert_eq!(video_height(30.0), 720);
+        assert_eq!(video_height(60.0), 720);
+        assert_eq!(video_height(90.0), 480);
+        assert_eq!(video_height(600.0), 360);
+    }
+
+    #[test]
+    fn test_progress_percent() {
+        use super::progress_percent;
+
+        assert_eq!(progress_percent("out_time_ms=5000000", 10.0), Some(50));
+        assert_eq!(progress_percent("out_time_ms=20000000", 10.0), Some(100));
+        assert_eq!(progress_percent("out_time_ms=5000000", 0.0), None);
+        assert_eq!(progress_percent("frame=24", 10.0), None);
+        assert_eq!(progress_percent("out_time_ms=N/A", 10.0), None);
+    }
+}
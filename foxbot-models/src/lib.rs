@@ -89,14 +89,23 @@ impl Sites {
 
 pub struct UserConfig;
 
+#[derive(Clone, Copy)]
 pub enum UserConfigKey {
     SiteSortOrder,
+    SendOriginals,
+    InlineInfo,
+    InlineTags,
+    CaptionTemplate,
 }
 
 impl UserConfigKey {
     fn as_str(&self) -> &str {
         match self {
             UserConfigKey::SiteSortOrder => "site-sort-order",
+            UserConfigKey::SendOriginals => "send-originals",
+            UserConfigKey::InlineInfo => "inline-info",
+            UserConfigKey::InlineTags => "inline-tags",
+            UserConfigKey::CaptionTemplate => "caption-template",
         }
     }
 }
@@ -167,6 +176,7 @@ impl UserConfig {
 
 pub struct GroupConfig;
 
+#[derive(Clone, Copy)]
 pub enum GroupConfigKey {
     GroupAdd,
     GroupNoPreviews,
@@ -174,6 +184,24 @@ pub enum GroupConfigKey {
     HasDeletePermission,
     CanEditChannel,
     HasLinkedChat,
+    GroupCompactSources,
+    SourceDistance,
+    MaxSourceLinks,
+    NoisySourceRule,
+    AllowedSites,
+    SourceDelivery,
+    SourceDeleteAfter,
+    AutoMirror,
+    AutoMirrorSites,
+    RepostDetection,
+    RepostDistance,
+    RepostWindowHours,
+    SiteSortOrder,
+    LinkedChannelForwards,
+    LinkedChannelReplies,
+    ChannelEditStyle,
+    ChannelArtistCredit,
+    ChannelHashtags,
 }
 
 impl GroupConfigKey {
@@ -185,10 +213,123 @@ impl GroupConfigKey {
             GroupConfigKey::HasDeletePermission => "has_delete_permission",
             GroupConfigKey::CanEditChannel => "can_edit_channel",
             GroupConfigKey::HasLinkedChat => "has_linked_chat",
+            GroupConfigKey::GroupCompactSources => "group_compact_sources",
+            GroupConfigKey::SourceDistance => "source_distance",
+            GroupConfigKey::MaxSourceLinks => "max_source_links",
+            GroupConfigKey::NoisySourceRule => "noisy_source_rule",
+            GroupConfigKey::AllowedSites => "allowed_sites",
+            GroupConfigKey::SourceDelivery => "source_delivery",
+            GroupConfigKey::SourceDeleteAfter => "source_delete_after",
+            GroupConfigKey::AutoMirror => "auto_mirror",
+            GroupConfigKey::AutoMirrorSites => "auto_mirror_sites",
+            GroupConfigKey::RepostDetection => "repost_detection",
+            GroupConfigKey::RepostDistance => "repost_distance",
+            GroupConfigKey::RepostWindowHours => "repost_window_hours",
+            GroupConfigKey::SiteSortOrder => "site_sort_order",
+            GroupConfigKey::LinkedChannelForwards => "linked_channel_forwards",
+            GroupConfigKey::LinkedChannelReplies => "linked_channel_replies",
+            GroupConfigKey::ChannelEditStyle => "channel_edit_style",
+            GroupConfigKey::ChannelArtistCredit => "channel_artist_credit",
+            GroupConfigKey::ChannelHashtags => "channel_hashtags",
         }
     }
 }
 
+/// When a group image with many matches should be considered noise and not
+/// have sources posted.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoisySourceRule {
+    /// Always post sources.
+    Off,
+    /// Skip images that mostly matched Twitter, which is common for memes.
+    Twitter,
+    /// Skip images with many matches from any site.
+    Any,
+}
+
+impl Default for NoisySourceRule {
+    fn default() -> Self {
+        NoisySourceRule::Twitter
+    }
+}
+
+/// How automatic sources are delivered to a group.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupSourceDelivery {
+    /// Reply with a message listing each source.
+    Reply,
+    /// Reply with a short message with each source as a button.
+    Buttons,
+    /// Add source buttons to images sent through the bot's inline mode,
+    /// replying with buttons for other images.
+    Edit,
+}
+
+impl Default for GroupSourceDelivery {
+    fn default() -> Self {
+        GroupSourceDelivery::Reply
+    }
+}
+
+/// How a group handles images forwarded from a linked channel the bot can
+/// edit, which will get a source on the channel post.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkedForwardRule {
+    /// Never add sources to forwarded images.
+    Skip,
+    /// Always add sources to forwarded images.
+    Source,
+    /// Add sources to forwarded images once the post is older than the
+    /// given number of hours, as newer posts are likely still being sourced.
+    Wait { hours: u32 },
+}
+
+impl Default for LinkedForwardRule {
+    fn default() -> Self {
+        LinkedForwardRule::Wait { hours: 6 }
+    }
+}
+
+/// How sources are added to channel posts.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChannelEditStyle {
+    /// Add each source as an inline keyboard button.
+    Buttons,
+    /// Append each source link to the post's caption.
+    Caption,
+    /// Add buttons and append links to the caption.
+    Both,
+}
+
+impl Default for ChannelEditStyle {
+    fn default() -> Self {
+        ChannelEditStyle::Buttons
+    }
+}
+
+/// Which hashtags are appended to the caption of channel posts.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChannelHashtags {
+    Off,
+    /// A hashtag for each artist.
+    Artist,
+    /// A hashtag for each site with a source.
+    Site,
+    /// Hashtags for each artist and site.
+    Both,
+}
+
+impl Default for ChannelHashtags {
+    fn default() -> Self {
+        ChannelHashtags::Off
+    }
+}
+
 impl GroupConfig {
     pub async fn get<T: serde::de::DeserializeOwned>(
         conn: &sqlx::Pool<sqlx::Postgres>,
@@ -229,6 +370,24 @@ impl GroupConfig {
 
         Ok(())
     }
+
+    /// Delete a config item.
+    pub async fn delete(
+        conn: &sqlx::Pool<sqlx::Postgres>,
+        key: GroupConfigKey,
+        chat_id: i64,
+    ) -> anyhow::Result<()> {
+        sqlx::query!(
+            "DELETE FROM group_config
+            WHERE chat_id = lookup_chat_by_telegram_id($1) AND name = $2",
+            chat_id,
+            key.as_str()
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
 }
 
 /// A Twitter account, as stored within the database.
@@ -418,7 +577,81 @@ impl FileCache {
     }
 }
 
-#[derive(sqlx::FromRow)]
+/// File IDs for original files that were sent as documents.
+pub struct DocumentCache;
+
+impl DocumentCache {
+    fn key(url: &str) -> String {
+        format!("document:{}", url)
+    }
+
+    /// Look up the file ID of a document by the URL it was downloaded from.
+    pub async fn get(
+        redis: &redis::aio::ConnectionManager,
+        url: &str,
+    ) -> anyhow::Result<Option<String>> {
+        use redis::AsyncCommands;
+
+        let mut redis = redis.clone();
+        let file_id = redis.get(Self::key(url)).await?;
+
+        Ok(file_id)
+    }
+
+    pub async fn set(
+        redis: &redis::aio::ConnectionManager,
+        url: &str,
+        file_id: &str,
+    ) -> anyhow::Result<()> {
+        use redis::AsyncCommands;
+
+        let mut redis = redis.clone();
+        redis
+            .set_ex(Self::key(url), file_id, 60 * 60 * 24 * 30)
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// The processing state of a video.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VideoState {
+    /// The video has not been sent to the transcoder.
+    Pending,
+    /// The transcoder is working on the video.
+    Encoding,
+    /// The video was transcoded and has an mp4_url.
+    Done,
+    /// The transcoder did not complete after every attempt.
+    Failed,
+}
+
+impl VideoState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Encoding => "encoding",
+            Self::Done => "done",
+            Self::Failed => "failed",
+        }
+    }
+}
+
+impl std::str::FromStr for VideoState {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pending" => Ok(Self::Pending),
+            "encoding" => Ok(Self::Encoding),
+            "done" => Ok(Self::Done),
+            "failed" => Ok(Self::Failed),
+            _ => Err(anyhow::anyhow!("unknown video state: {}", s)),
+        }
+    }
+}
+
 pub struct Video {
     /// Database identifier of the video.
     pub id: i32,
@@ -437,8 +670,59 @@ pub struct Video {
     pub display_url: String,
     /// A unique display name representing the file's path and public ID.
     pub display_name: String,
-    /// A job ID, if one exists, from Coconut.
+    /// A job ID, if one exists, from the video transcoder.
     pub job_id: Option<i32>,
+    /// The current processing state.
+    pub state: VideoState,
+    /// How many times the video has been sent to the transcoder.
+    pub attempts: i32,
+    /// When the video was first seen.
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    /// When the state last changed or the transcoder last reported progress.
+    pub state_updated_at: chrono::DateTime<chrono::Utc>,
+    /// Telegram's file ID for the converted video, once it has been sent.
+    pub file_id: Option<String>,
+}
+
+/// A video as stored in the database, before the state has been parsed.
+struct VideoRow {
+    id: i32,
+    processed: bool,
+    source: String,
+    url: String,
+    mp4_url: Option<String>,
+    thumb_url: Option<String>,
+    display_url: String,
+    display_name: String,
+    job_id: Option<i32>,
+    state: String,
+    attempts: i32,
+    created_at: chrono::DateTime<chrono::Utc>,
+    state_updated_at: chrono::DateTime<chrono::Utc>,
+    file_id: Option<String>,
+}
+
+impl std::convert::TryFrom<VideoRow> for Video {
+    type Error = anyhow::Error;
+
+    fn try_from(row: VideoRow) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: row.id,
+            processed: row.processed,
+            source: row.source,
+            url: row.url,
+            mp4_url: row.mp4_url,
+            thumb_url: row.thumb_url,
+            display_url: row.display_url,
+            display_name: row.display_name,
+            job_id: row.job_id,
+            state: row.state.parse()?,
+            attempts: row.attempts,
+            created_at: row.created_at,
+            state_updated_at: row.state_updated_at,
+            file_id: row.file_id,
+        })
+    }
 }
 
 impl Video {
@@ -448,8 +732,8 @@ impl Video {
         display_name: &str,
     ) -> anyhow::Result<Option<Self>> {
         let video = sqlx::query_as!(
-            Video,
-            "SELECT id, processed, source, url, mp4_url, thumb_url, display_url, display_name, job_id
+            VideoRow,
+            "SELECT id, processed, source, url, mp4_url, thumb_url, display_url, display_name, job_id, state, attempts, created_at, state_updated_at, file_id
             FROM videos
             WHERE display_name = $1",
             display_name
@@ -457,7 +741,7 @@ impl Video {
         .fetch_optional(conn)
         .await?;
 
-        Ok(video)
+        video.map(std::convert::TryInto::try_into).transpose()
     }
 
     /// Lookup a video by the URL ID.
@@ -466,8 +750,8 @@ impl Video {
         url_id: &str,
     ) -> anyhow::Result<Option<Self>> {
         let video = sqlx::query_as!(
-            Video,
-            "SELECT id, processed, source, url, mp4_url, thumb_url, display_url, display_name, job_id
+            VideoRow,
+            "SELECT id, processed, source, url, mp4_url, thumb_url, display_url, display_name, job_id, state, attempts, created_at, state_updated_at, file_id
             FROM videos
             WHERE source = $1",
             url_id
@@ -475,7 +759,29 @@ impl Video {
         .fetch_optional(conn)
         .await?;
 
-        Ok(video)
+        video.map(std::convert::TryInto::try_into).transpose()
+    }
+
+    /// Find videos that have been encoding without any progress for longer
+    /// than the given number of seconds.
+    pub async fn stalled(
+        conn: &sqlx::Pool<sqlx::Postgres>,
+        timeout_secs: i64,
+    ) -> anyhow::Result<Vec<Self>> {
+        let videos = sqlx::query_as!(
+            VideoRow,
+            "SELECT id, processed, source, url, mp4_url, thumb_url, display_url, display_name, job_id, state, attempts, created_at, state_updated_at, file_id
+            FROM videos
+            WHERE state = 'encoding' AND state_updated_at < $1",
+            chrono::Utc::now() - chrono::Duration::seconds(timeout_secs)
+        )
+        .fetch_all(conn)
+        .await?;
+
+        videos
+            .into_iter()
+            .map(std::convert::TryInto::try_into)
+            .collect()
     }
 
     /// Insert a new media item with a given URL ID and media URL.
@@ -503,15 +809,62 @@ impl Video {
         Ok(row.display_name)
     }
 
-    /// Set the Coconut job ID for the video.
+    /// Set the transcoder job ID for the video, marking it as encoding and
+    /// counting the attempt.
     pub async fn set_job_id(
         conn: &sqlx::Pool<sqlx::Postgres>,
         id: i32,
         job_id: i32,
     ) -> anyhow::Result<()> {
-        sqlx::query!("UPDATE videos SET job_id = $1 WHERE id = $2", job_id, id)
-            .execute(conn)
-            .await?;
+        sqlx::query!(
+            "UPDATE videos
+            SET job_id = $1, state = 'encoding', attempts = attempts + 1, state_updated_at = current_timestamp
+            WHERE id = $2",
+            job_id,
+            id
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Claim a video that is pending or failed so it can be sent to the
+    /// transcoder, resetting its attempts. Returns false if it was already
+    /// claimed by someone else.
+    pub async fn claim_pending(conn: &sqlx::Pool<sqlx::Postgres>, id: i32) -> anyhow::Result<bool> {
+        let res = sqlx::query!(
+            "UPDATE videos
+            SET state = 'encoding', job_id = NULL, attempts = 0, state_updated_at = current_timestamp
+            WHERE id = $1 AND state IN ('pending', 'failed')",
+            id
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(res.rows_affected() > 0)
+    }
+
+    /// Record that the transcoder is still making progress on the video.
+    pub async fn touch_progress(conn: &sqlx::Pool<sqlx::Postgres>, id: i32) -> anyhow::Result<()> {
+        sqlx::query!(
+            "UPDATE videos SET state_updated_at = current_timestamp WHERE id = $1 AND state = 'encoding'",
+            id
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Mark the video as failed.
+    pub async fn set_failed(conn: &sqlx::Pool<sqlx::Postgres>, id: i32) -> anyhow::Result<()> {
+        sqlx::query!(
+            "UPDATE videos SET state = 'failed', state_updated_at = current_timestamp WHERE id = $1",
+            id
+        )
+        .execute(conn)
+        .await?;
 
         Ok(())
     }
@@ -524,7 +877,9 @@ impl Video {
         thumb_url: &str,
     ) -> anyhow::Result<()> {
         sqlx::query!(
-            "UPDATE videos SET processed = true, mp4_url = $1, thumb_url = $2 WHERE id = $3",
+            "UPDATE videos
+            SET processed = true, mp4_url = $1, thumb_url = $2, state = 'done', state_updated_at = current_timestamp
+            WHERE id = $3",
             mp4_url,
             thumb_url,
             id
@@ -535,6 +890,19 @@ impl Video {
         Ok(())
     }
 
+    /// Save the file ID Telegram assigned to the converted video.
+    pub async fn set_file_id(
+        conn: &sqlx::Pool<sqlx::Postgres>,
+        id: i32,
+        file_id: &str,
+    ) -> anyhow::Result<()> {
+        sqlx::query!("UPDATE videos SET file_id = $1 WHERE id = $2", file_id, id)
+            .execute(conn)
+            .await?;
+
+        Ok(())
+    }
+
     /// Add a new message associated with a video encoding job.
     pub async fn add_message_id(
         conn: &sqlx::Pool<sqlx::Postgres>,
@@ -595,6 +963,8 @@ pub struct CachedPost {
     pub thumb: bool,
     pub cdn_url: String,
     pub dimensions: (u32, u32),
+    /// Telegram's file ID for the image, once it has been sent.
+    pub file_id: Option<String>,
 }
 
 impl CachedPost {
@@ -604,7 +974,7 @@ impl CachedPost {
         thumb: bool,
     ) -> anyhow::Result<Option<Self>> {
         let post = sqlx::query!(
-            "SELECT id, post_url, thumb, cdn_url, width, height
+            "SELECT id, post_url, thumb, cdn_url, width, height, file_id
             FROM cached_post
             WHERE post_url = $1 AND thumb = $2",
             post_url,
@@ -624,9 +994,50 @@ impl CachedPost {
             thumb: post.thumb,
             cdn_url: post.cdn_url,
             dimensions: (post.width as u32, post.height as u32),
+            file_id: post.file_id,
+        }))
+    }
+
+    pub async fn get_by_id(
+        conn: &sqlx::Pool<sqlx::Postgres>,
+        id: i32,
+    ) -> anyhow::Result<Option<Self>> {
+        let post = sqlx::query!(
+            "SELECT id, post_url, thumb, cdn_url, width, height, file_id
+            FROM cached_post
+            WHERE id = $1",
+            id
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(post.map(|post| Self {
+            id: post.id,
+            post_url: post.post_url,
+            thumb: post.thumb,
+            cdn_url: post.cdn_url,
+            dimensions: (post.width as u32, post.height as u32),
+            file_id: post.file_id,
         }))
     }
 
+    /// Save the file ID Telegram assigned to the cached image.
+    pub async fn set_file_id(
+        conn: &sqlx::Pool<sqlx::Postgres>,
+        id: i32,
+        file_id: &str,
+    ) -> anyhow::Result<()> {
+        sqlx::query!(
+            "UPDATE cached_post SET file_id = $1 WHERE id = $2",
+            file_id,
+            id
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
     pub async fn save(
         conn: &sqlx::Pool<sqlx::Postgres>,
         post_url: &str,
@@ -755,6 +1166,18 @@ pub struct Subscription {
     pub hash: i64,
     pub message_id: Option<i32>,
     pub photo_id: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// A channel post that did not have a source when it was posted.
+pub struct ChannelSubscription {
+    pub chat_id: i64,
+    pub message_id: i32,
+    pub media_group_id: Option<String>,
+    pub hash: i64,
+    /// The post's caption, which is kept when adding sources to it.
+    pub caption: Option<String>,
+    pub caption_entities: Option<Vec<tgbotapi::MessageEntity>>,
 }
 
 impl Subscriptions {
@@ -801,7 +1224,7 @@ impl Subscriptions {
         hash: i64,
     ) -> anyhow::Result<Vec<Subscription>> {
         let subscriptions = sqlx::query!(
-            "SELECT account.telegram_id user_id, hash, message_id, photo_id
+            "SELECT account.telegram_id user_id, hash, message_id, photo_id, created_at
             FROM source_notification
             JOIN account ON account.id = source_notification.account_id
             WHERE hash <@ ($1, 3)",
@@ -812,88 +1235,432 @@ impl Subscriptions {
             hash: row.hash.unwrap(),
             message_id: row.message_id,
             photo_id: row.photo_id,
+            created_at: row.created_at.unwrap(),
         })
         .fetch_all(conn)
         .await?;
 
         Ok(subscriptions)
     }
-}
 
-#[derive(serde::Serialize)]
-pub struct MediaGroup {
-    pub id: i32,
-    pub inserted_at: chrono::DateTime<chrono::Utc>,
-    pub message: Message,
-    pub sources: Option<Vec<fuzzysearch::File>>,
-}
-
-impl MediaGroup {
-    /// Store a message as part of a media group, keeping track of when the
-    /// value was inserted.
-    pub async fn add_message(
+    /// Count how many pending subscriptions a user has.
+    pub async fn count_subscriptions(
         conn: &sqlx::Pool<sqlx::Postgres>,
-        message: &Message,
-    ) -> anyhow::Result<i32> {
-        let id = sqlx::query_scalar!(
-            "INSERT INTO media_group (media_group_id, inserted_at, message) VALUES ($1, $2, $3) RETURNING id",
-            message.media_group_id.as_ref().unwrap(),
-            chrono::Utc::now(),
-            serde_json::to_value(message)?,
+        user_id: i64,
+    ) -> anyhow::Result<i64> {
+        let count = sqlx::query_scalar!(
+            "SELECT count(*)
+            FROM source_notification
+            WHERE account_id = lookup_account_by_telegram_id($1)",
+            user_id
         )
         .fetch_one(conn)
         .await?;
-        Ok(id)
+
+        Ok(count.unwrap_or(0))
     }
 
-    /// Get the time that the last message was inserted to the media group.
-    pub async fn last_message(
+    /// Get a page of a user's subscriptions, newest first.
+    pub async fn list_subscriptions(
         conn: &sqlx::Pool<sqlx::Postgres>,
-        media_group_id: &str,
-    ) -> anyhow::Result<Option<chrono::DateTime<chrono::Utc>>> {
-        let last_inserted_at = sqlx::query_scalar!(
-            r#"SELECT max(inserted_at) FROM media_group WHERE media_group_id = $1"#,
-            media_group_id
+        user_id: i64,
+        limit: i64,
+        offset: i64,
+    ) -> anyhow::Result<Vec<Subscription>> {
+        let subscriptions = sqlx::query!(
+            "SELECT hash, message_id, photo_id, created_at
+            FROM source_notification
+            WHERE account_id = lookup_account_by_telegram_id($1)
+            ORDER BY created_at DESC, hash
+            LIMIT $2 OFFSET $3",
+            user_id,
+            limit,
+            offset
         )
-        .fetch_optional(conn)
+        .map(|row| Subscription {
+            user_id,
+            hash: row.hash,
+            message_id: row.message_id,
+            photo_id: row.photo_id,
+            created_at: row.created_at,
+        })
+        .fetch_all(conn)
         .await?;
-        Ok(last_inserted_at.flatten())
+
+        Ok(subscriptions)
     }
 
-    /// Get a specific stored message, if it exists.
-    pub async fn get_message(
+    /// Get a user's subscription for an exact hash.
+    pub async fn get_subscription(
         conn: &sqlx::Pool<sqlx::Postgres>,
-        id: i32,
-    ) -> anyhow::Result<Option<MediaGroup>> {
-        let message = sqlx::query!(
-            "SELECT id, inserted_at, message, sources FROM media_group WHERE id = $1",
-            id
+        user_id: i64,
+        hash: i64,
+    ) -> anyhow::Result<Option<Subscription>> {
+        let subscription = sqlx::query!(
+            "SELECT hash, message_id, photo_id, created_at
+            FROM source_notification
+            WHERE account_id = lookup_account_by_telegram_id($1) AND hash = $2",
+            user_id,
+            hash
         )
-        .map(|row| MediaGroup {
-            id: row.id,
-            inserted_at: row.inserted_at,
-            message: serde_json::from_value(row.message).unwrap(),
-            sources: row
-                .sources
-                .map(|sources| serde_json::from_value(sources).unwrap()),
+        .map(|row| Subscription {
+            user_id,
+            hash: row.hash,
+            message_id: row.message_id,
+            photo_id: row.photo_id,
+            created_at: row.created_at,
         })
         .fetch_optional(conn)
         .await?;
 
-        Ok(message)
+        Ok(subscription)
     }
 
-    pub async fn get_messages(
+    /// Remove all subscriptions older than the retention period, returning how
+    /// many were removed.
+    pub async fn expire_subscriptions(
         conn: &sqlx::Pool<sqlx::Postgres>,
-        media_group_id: &str,
-    ) -> anyhow::Result<Vec<MediaGroup>> {
-        let messages = sqlx::query!(
-            "SELECT id, inserted_at, message, sources FROM media_group WHERE media_group_id = $1",
-            media_group_id
+        retention_days: i32,
+    ) -> anyhow::Result<u64> {
+        let result = sqlx::query!(
+            "DELETE FROM source_notification WHERE created_at < now() - make_interval(days => $1)",
+            retention_days
         )
-        .map(|row| MediaGroup {
-            id: row.id,
-            inserted_at: row.inserted_at,
+        .execute(conn)
+        .await?;
+
+        let channel_result = sqlx::query!(
+            "DELETE FROM channel_source_notification WHERE created_at < now() - make_interval(days => $1)",
+            retention_days
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(result.rows_affected() + channel_result.rows_affected())
+    }
+
+    /// Remember a channel post without a source so it can be edited if a
+    /// source is found later.
+    pub async fn add_channel_subscription(
+        conn: &sqlx::Pool<sqlx::Postgres>,
+        chat_id: i64,
+        message_id: i32,
+        media_group_id: Option<&str>,
+        hash: i64,
+        caption: Option<&str>,
+        caption_entities: Option<&[tgbotapi::MessageEntity]>,
+    ) -> anyhow::Result<()> {
+        let caption_entities = caption_entities.map(serde_json::to_value).transpose()?;
+
+        sqlx::query!(
+            "INSERT INTO channel_source_notification (chat_id, message_id, media_group_id, hash, caption, caption_entities)
+                VALUES (lookup_chat_by_telegram_id($1), $2, $3, $4, $5, $6) ON CONFLICT DO NOTHING",
+            chat_id,
+            message_id,
+            media_group_id,
+            hash,
+            caption,
+            caption_entities,
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Find all channel posts with a hash similar to the provided hash.
+    pub async fn search_channel_subscriptions(
+        conn: &sqlx::Pool<sqlx::Postgres>,
+        hash: i64,
+    ) -> anyhow::Result<Vec<ChannelSubscription>> {
+        let rows = sqlx::query!(
+            "SELECT chat_telegram.telegram_id chat_id, message_id, media_group_id, hash, caption, caption_entities
+            FROM channel_source_notification
+            JOIN chat_telegram ON chat_telegram.chat_id = channel_source_notification.chat_id
+            WHERE hash <@ ($1, 3)",
+            hash
+        )
+        .fetch_all(conn)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| -> anyhow::Result<_> {
+                Ok(ChannelSubscription {
+                    chat_id: row.chat_id.unwrap(),
+                    message_id: row.message_id.unwrap(),
+                    media_group_id: row.media_group_id,
+                    hash: row.hash.unwrap(),
+                    caption: row.caption,
+                    caption_entities: row
+                        .caption_entities
+                        .map(serde_json::from_value)
+                        .transpose()
+                        .context("unable to decode caption entities")?,
+                })
+            })
+            .collect()
+    }
+
+    /// Get a channel post without a source, if it is still waiting for one.
+    pub async fn get_channel_subscription(
+        conn: &sqlx::Pool<sqlx::Postgres>,
+        chat_id: i64,
+        message_id: i32,
+    ) -> anyhow::Result<Option<ChannelSubscription>> {
+        let row = match sqlx::query!(
+            "SELECT media_group_id, hash, caption, caption_entities
+            FROM channel_source_notification
+            WHERE chat_id = lookup_chat_by_telegram_id($1) AND message_id = $2",
+            chat_id,
+            message_id
+        )
+        .fetch_optional(conn)
+        .await?
+        {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+
+        Ok(Some(ChannelSubscription {
+            chat_id,
+            message_id,
+            media_group_id: row.media_group_id,
+            hash: row.hash,
+            caption: row.caption,
+            caption_entities: row
+                .caption_entities
+                .map(serde_json::from_value)
+                .transpose()
+                .context("unable to decode caption entities")?,
+        }))
+    }
+
+    /// Keep the caption of a channel post without a source up to date after
+    /// the post was edited, so adding sources later doesn't revert the edit.
+    pub async fn update_channel_caption(
+        conn: &sqlx::Pool<sqlx::Postgres>,
+        chat_id: i64,
+        message_id: i32,
+        caption: Option<&str>,
+        caption_entities: Option<&[tgbotapi::MessageEntity]>,
+    ) -> anyhow::Result<()> {
+        let caption_entities = caption_entities.map(serde_json::to_value).transpose()?;
+
+        sqlx::query!(
+            "UPDATE channel_source_notification SET caption = $3, caption_entities = $4
+            WHERE chat_id = lookup_chat_by_telegram_id($1) AND message_id = $2",
+            chat_id,
+            message_id,
+            caption,
+            caption_entities,
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn remove_channel_subscription(
+        conn: &sqlx::Pool<sqlx::Postgres>,
+        chat_id: i64,
+        message_id: i32,
+    ) -> anyhow::Result<()> {
+        sqlx::query!(
+            "DELETE FROM channel_source_notification
+            WHERE chat_id = lookup_chat_by_telegram_id($1) AND message_id = $2",
+            chat_id,
+            message_id
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+}
+
+pub struct ArtistWatches;
+
+pub struct ArtistWatch {
+    pub site: Sites,
+    pub artist: String,
+}
+
+impl ArtistWatches {
+    /// Start watching an artist on a site. Artist names are compared without
+    /// case.
+    pub async fn add_watch(
+        conn: &sqlx::Pool<sqlx::Postgres>,
+        user_id: i64,
+        site: &Sites,
+        artist: &str,
+    ) -> anyhow::Result<()> {
+        sqlx::query!(
+            "INSERT INTO artist_watch (account_id, site, artist)
+                VALUES (lookup_account_by_telegram_id($1), $2, $3) ON CONFLICT DO NOTHING",
+            user_id,
+            site.as_str(),
+            artist.to_lowercase(),
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Stop watching an artist, returning if the artist was being watched.
+    pub async fn remove_watch(
+        conn: &sqlx::Pool<sqlx::Postgres>,
+        user_id: i64,
+        site: &Sites,
+        artist: &str,
+    ) -> anyhow::Result<bool> {
+        let result = sqlx::query!(
+            "DELETE FROM artist_watch
+            WHERE account_id = lookup_account_by_telegram_id($1) AND site = $2 AND artist = $3",
+            user_id,
+            site.as_str(),
+            artist.to_lowercase(),
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Get all artists a user is watching.
+    pub async fn list_watches(
+        conn: &sqlx::Pool<sqlx::Postgres>,
+        user_id: i64,
+    ) -> anyhow::Result<Vec<ArtistWatch>> {
+        let watches = sqlx::query!(
+            "SELECT site, artist
+            FROM artist_watch
+            WHERE account_id = lookup_account_by_telegram_id($1)
+            ORDER BY site, artist",
+            user_id
+        )
+        .fetch_all(conn)
+        .await?;
+
+        Ok(watches
+            .into_iter()
+            .filter_map(|row| {
+                Some(ArtistWatch {
+                    site: row.site.parse().ok()?,
+                    artist: row.artist,
+                })
+            })
+            .collect())
+    }
+
+    /// Check if anyone is watching any artist.
+    pub async fn any_watches(conn: &sqlx::Pool<sqlx::Postgres>) -> anyhow::Result<bool> {
+        let exists = sqlx::query_scalar!("SELECT EXISTS(SELECT 1 FROM artist_watch)")
+            .fetch_one(conn)
+            .await?;
+
+        Ok(exists.unwrap_or(false))
+    }
+
+    /// Find the users watching any of the given artists on a site, along with
+    /// the artist that was matched.
+    pub async fn find_watchers(
+        conn: &sqlx::Pool<sqlx::Postgres>,
+        site: &Sites,
+        artists: &[String],
+    ) -> anyhow::Result<Vec<(i64, String)>> {
+        let artists: Vec<String> = artists.iter().map(|artist| artist.to_lowercase()).collect();
+
+        let watchers = sqlx::query!(
+            "SELECT account.telegram_id user_id, artist
+            FROM artist_watch
+            JOIN account ON account.id = artist_watch.account_id
+            WHERE site = $1 AND artist = ANY($2)",
+            site.as_str(),
+            &artists
+        )
+        .map(|row| (row.user_id.unwrap(), row.artist.unwrap()))
+        .fetch_all(conn)
+        .await?;
+
+        Ok(watchers)
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct MediaGroup {
+    pub id: i32,
+    pub inserted_at: chrono::DateTime<chrono::Utc>,
+    pub message: Message,
+    pub sources: Option<Vec<fuzzysearch::File>>,
+}
+
+impl MediaGroup {
+    /// Store a message as part of a media group, keeping track of when the
+    /// value was inserted.
+    pub async fn add_message(
+        conn: &sqlx::Pool<sqlx::Postgres>,
+        message: &Message,
+    ) -> anyhow::Result<i32> {
+        let id = sqlx::query_scalar!(
+            "INSERT INTO media_group (media_group_id, inserted_at, message) VALUES ($1, $2, $3) RETURNING id",
+            message.media_group_id.as_ref().unwrap(),
+            chrono::Utc::now(),
+            serde_json::to_value(message)?,
+        )
+        .fetch_one(conn)
+        .await?;
+        Ok(id)
+    }
+
+    /// Get the time that the last message was inserted to the media group.
+    pub async fn last_message(
+        conn: &sqlx::Pool<sqlx::Postgres>,
+        media_group_id: &str,
+    ) -> anyhow::Result<Option<chrono::DateTime<chrono::Utc>>> {
+        let last_inserted_at = sqlx::query_scalar!(
+            r#"SELECT max(inserted_at) FROM media_group WHERE media_group_id = $1"#,
+            media_group_id
+        )
+        .fetch_optional(conn)
+        .await?;
+        Ok(last_inserted_at.flatten())
+    }
+
+    /// Get a specific stored message, if it exists.
+    pub async fn get_message(
+        conn: &sqlx::Pool<sqlx::Postgres>,
+        id: i32,
+    ) -> anyhow::Result<Option<MediaGroup>> {
+        let message = sqlx::query!(
+            "SELECT id, inserted_at, message, sources FROM media_group WHERE id = $1",
+            id
+        )
+        .map(|row| MediaGroup {
+            id: row.id,
+            inserted_at: row.inserted_at,
+            message: serde_json::from_value(row.message).unwrap(),
+            sources: row
+                .sources
+                .map(|sources| serde_json::from_value(sources).unwrap()),
+        })
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(message)
+    }
+
+    pub async fn get_messages(
+        conn: &sqlx::Pool<sqlx::Postgres>,
+        media_group_id: &str,
+    ) -> anyhow::Result<Vec<MediaGroup>> {
+        let messages = sqlx::query!(
+            "SELECT id, inserted_at, message, sources FROM media_group WHERE media_group_id = $1",
+            media_group_id
+        )
+        .map(|row| MediaGroup {
+            id: row.id,
+            inserted_at: row.inserted_at,
             message: serde_json::from_value(row.message).unwrap(),
             sources: row
                 .sources
@@ -976,3 +1743,610 @@ impl MediaGroup {
         Ok(())
     }
 }
+
+/// A post that was returned as an inline result, with enough information to
+/// send it again.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SharedPost {
+    /// Human readable name of the site.
+    pub site: String,
+    /// The site's ID for the post, if known.
+    pub url_id: Option<String>,
+    pub source_link: Option<String>,
+    /// URL of the media that was sent.
+    pub url: String,
+    pub thumb_url: Option<String>,
+    pub file_type: String,
+}
+
+/// Inline results that users have chosen to send.
+pub struct InlineHistory;
+
+impl InlineHistory {
+    /// How long to remember inline results while waiting for one to be chosen.
+    const PENDING_SECONDS: usize = 60 * 60;
+
+    /// How long to remember inline messages a user sent.
+    const SENT_SECONDS: usize = 60 * 5;
+
+    fn pending_key(result_id: &str) -> String {
+        format!("inline-result:{}", result_id)
+    }
+
+    fn sent_key(user_id: i64, url: &str) -> String {
+        format!("inline-sent:{}:{}", user_id, url)
+    }
+
+    /// Remember the inline message ID of a result a user sent, by the URL of
+    /// the media it contained, so it can be edited after it appears in a chat.
+    pub async fn set_sent_message(
+        redis: &redis::aio::ConnectionManager,
+        user_id: i64,
+        url: &str,
+        inline_message_id: &str,
+    ) -> anyhow::Result<()> {
+        use redis::AsyncCommands;
+
+        let mut redis = redis.clone();
+        redis
+            .set_ex(
+                Self::sent_key(user_id, url),
+                inline_message_id,
+                Self::SENT_SECONDS,
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Get the inline message ID of a result with the media URL a user
+    /// recently sent, if any. Each message is only returned once.
+    pub async fn take_sent_message(
+        redis: &redis::aio::ConnectionManager,
+        user_id: i64,
+        url: &str,
+    ) -> anyhow::Result<Option<String>> {
+        let key = Self::sent_key(user_id, url);
+
+        let mut redis = redis.clone();
+        let (inline_message_id,): (Option<String>,) = redis::pipe()
+            .atomic()
+            .get(&key)
+            .del(&key)
+            .ignore()
+            .query_async(&mut redis)
+            .await?;
+
+        Ok(inline_message_id)
+    }
+
+    /// Remember which post each inline result was created from, so it can be
+    /// recorded if the result is chosen.
+    pub async fn stash_results(
+        redis: &redis::aio::ConnectionManager,
+        results: &[(String, SharedPost)],
+    ) -> anyhow::Result<()> {
+        if results.is_empty() {
+            return Ok(());
+        }
+
+        let mut redis = redis.clone();
+        let mut pipe = redis::pipe();
+
+        for (result_id, post) in results {
+            pipe.set_ex(
+                Self::pending_key(result_id),
+                serde_json::to_string(post)?,
+                Self::PENDING_SECONDS,
+            )
+            .ignore();
+        }
+
+        pipe.query_async::<_, ()>(&mut redis).await?;
+
+        Ok(())
+    }
+
+    /// Get the post an inline result was created from, if it was stashed.
+    pub async fn get_stashed(
+        redis: &redis::aio::ConnectionManager,
+        result_id: &str,
+    ) -> anyhow::Result<Option<SharedPost>> {
+        use redis::AsyncCommands;
+
+        let mut redis = redis.clone();
+        let data: Option<String> = redis.get(Self::pending_key(result_id)).await?;
+
+        data.map(|data| serde_json::from_str(&data))
+            .transpose()
+            .map_err(Into::into)
+    }
+
+    /// Record that a user sent a post.
+    pub async fn record(
+        conn: &sqlx::Pool<sqlx::Postgres>,
+        user_id: i64,
+        post: &SharedPost,
+    ) -> anyhow::Result<()> {
+        sqlx::query!(
+            "INSERT INTO inline_history (account_id, site, url_id, source_link, url, thumb_url, file_type)
+                VALUES (lookup_account_by_telegram_id($1), $2, $3, $4, $5, $6, $7)",
+            user_id,
+            post.site,
+            post.url_id,
+            post.source_link,
+            post.url,
+            post.thumb_url,
+            post.file_type
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Get the posts a user most recently sent, without duplicates.
+    pub async fn recent(
+        conn: &sqlx::Pool<sqlx::Postgres>,
+        user_id: i64,
+        limit: i64,
+    ) -> anyhow::Result<Vec<SharedPost>> {
+        let posts = sqlx::query!(
+            "SELECT site, url_id, source_link, url, thumb_url, file_type FROM (
+                SELECT DISTINCT ON (url) site, url_id, source_link, url, thumb_url, file_type, chosen_at
+                FROM inline_history
+                WHERE account_id = lookup_account_by_telegram_id($1)
+                ORDER BY url, chosen_at DESC
+            ) recent
+            ORDER BY chosen_at DESC
+            LIMIT $2",
+            user_id,
+            limit
+        )
+        .map(|row| SharedPost {
+            site: row.site,
+            url_id: row.url_id,
+            source_link: row.source_link,
+            url: row.url,
+            thumb_url: row.thumb_url,
+            file_type: row.file_type,
+        })
+        .fetch_all(conn)
+        .await?;
+
+        Ok(posts)
+    }
+
+    /// Remove all of a user's history, returning how many items were removed.
+    pub async fn clear(conn: &sqlx::Pool<sqlx::Postgres>, user_id: i64) -> anyhow::Result<u64> {
+        let result = sqlx::query!(
+            "DELETE FROM inline_history WHERE account_id = lookup_account_by_telegram_id($1)",
+            user_id
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Remove history older than the retention period.
+    pub async fn expire(
+        conn: &sqlx::Pool<sqlx::Postgres>,
+        retention_days: i32,
+    ) -> anyhow::Result<u64> {
+        let result = sqlx::query!(
+            "DELETE FROM inline_history WHERE chosen_at < now() - make_interval(days => $1)",
+            retention_days
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+/// An earlier image in a group that was similar to a new image.
+pub struct RepostMatch {
+    pub message_id: i32,
+    pub posted_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Hashes of images recently posted in groups, used to detect reposts.
+pub struct GroupRepost;
+
+impl GroupRepost {
+    /// Remember an image posted in a group.
+    pub async fn record(
+        conn: &sqlx::Pool<sqlx::Postgres>,
+        chat_id: i64,
+        message_id: i32,
+        hash: i64,
+    ) -> anyhow::Result<()> {
+        sqlx::query!(
+            "INSERT INTO group_repost (chat_id, message_id, hash)
+                VALUES (lookup_chat_by_telegram_id($1), $2, $3) ON CONFLICT DO NOTHING",
+            chat_id,
+            message_id,
+            hash
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Find the earliest image in a group within the given distance that was
+    /// posted in the last number of hours.
+    pub async fn find(
+        conn: &sqlx::Pool<sqlx::Postgres>,
+        chat_id: i64,
+        hash: i64,
+        distance: i64,
+        window_hours: i32,
+    ) -> anyhow::Result<Option<RepostMatch>> {
+        let repost = sqlx::query!(
+            "SELECT message_id, posted_at
+            FROM group_repost
+            WHERE chat_id = lookup_chat_by_telegram_id($1)
+                AND hash <@ ($2, $3)
+                AND posted_at > now() - make_interval(hours => $4)
+            ORDER BY posted_at
+            LIMIT 1",
+            chat_id,
+            hash,
+            distance,
+            window_hours
+        )
+        .map(|row| RepostMatch {
+            message_id: row.message_id,
+            posted_at: row.posted_at,
+        })
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(repost)
+    }
+
+    /// Remove images older than the retention period.
+    pub async fn expire(
+        conn: &sqlx::Pool<sqlx::Postgres>,
+        retention_days: i32,
+    ) -> anyhow::Result<u64> {
+        let result = sqlx::query!(
+            "DELETE FROM group_repost WHERE posted_at < now() - make_interval(days => $1)",
+            retention_days
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+/// Something the bot did, or was unable to do, in a chat, which is shown to
+/// admins in their digest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ChatAction {
+    /// Sources were added to a channel post.
+    ChannelEdited,
+    /// No sources were found for a channel post.
+    ChannelUnsourced,
+    /// Sources were found for a channel post but it couldn't be edited.
+    ChannelEditFailed,
+    /// Sources were posted for a group image.
+    GroupSourced,
+    /// The bot lost a permission it needs to work in the chat.
+    PermissionLost,
+}
+
+impl ChatAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::ChannelEdited => "channel_edited",
+            Self::ChannelUnsourced => "channel_unsourced",
+            Self::ChannelEditFailed => "channel_edit_failed",
+            Self::GroupSourced => "group_sourced",
+            Self::PermissionLost => "permission_lost",
+        }
+    }
+}
+
+impl std::str::FromStr for ChatAction {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let action = match s {
+            "channel_edited" => Self::ChannelEdited,
+            "channel_unsourced" => Self::ChannelUnsourced,
+            "channel_edit_failed" => Self::ChannelEditFailed,
+            "group_sourced" => Self::GroupSourced,
+            "permission_lost" => Self::PermissionLost,
+            _ => return Err("unknown action"),
+        };
+
+        Ok(action)
+    }
+}
+
+/// Log of actions taken in chats.
+pub struct ChatActions;
+
+impl ChatActions {
+    /// Record an action in a chat, optionally about a specific message.
+    pub async fn record(
+        conn: &sqlx::Pool<sqlx::Postgres>,
+        chat_id: i64,
+        action: ChatAction,
+        message_id: Option<i32>,
+    ) -> anyhow::Result<()> {
+        sqlx::query!(
+            "INSERT INTO chat_action (chat_id, action, message_id)
+                VALUES (lookup_chat_by_telegram_id($1), $2, $3)",
+            chat_id,
+            action.as_str(),
+            message_id
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Count each kind of action in a chat since the given time.
+    pub async fn summary(
+        conn: &sqlx::Pool<sqlx::Postgres>,
+        chat_id: i64,
+        since: chrono::DateTime<chrono::Utc>,
+    ) -> anyhow::Result<std::collections::HashMap<ChatAction, i64>> {
+        let rows = sqlx::query!(
+            "SELECT action, count(*) count
+            FROM chat_action
+            WHERE chat_id = lookup_chat_by_telegram_id($1) AND created_at > $2
+            GROUP BY action",
+            chat_id,
+            since
+        )
+        .fetch_all(conn)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .filter_map(|row| Some((row.action.parse().ok()?, row.count.unwrap_or(0))))
+            .collect())
+    }
+
+    /// Remove actions older than the retention period.
+    pub async fn expire(
+        conn: &sqlx::Pool<sqlx::Postgres>,
+        retention_days: i32,
+    ) -> anyhow::Result<u64> {
+        let result = sqlx::query!(
+            "DELETE FROM chat_action WHERE created_at < now() - make_interval(days => $1)",
+            retention_days
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+/// A digest of a chat's actions that is due to be sent to an admin.
+#[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct DueDigest {
+    pub chat_id: i64,
+    pub user_id: i64,
+    /// Unix timestamp of when the previous digest was sent, or the digest
+    /// was enabled.
+    pub since: i64,
+    /// Language of the user when they enabled the digest.
+    #[serde(default)]
+    pub language_code: Option<String>,
+    /// If the bot was removed from the chat, making this the last digest.
+    #[serde(default)]
+    pub chat_gone: bool,
+}
+
+/// Admins who get a periodic digest of actions in their chats.
+pub struct ChatDigest;
+
+impl ChatDigest {
+    /// Send a chat's digest to a user, replacing any other user.
+    pub async fn enable(
+        conn: &sqlx::Pool<sqlx::Postgres>,
+        chat_id: i64,
+        user_id: i64,
+        language_code: Option<&str>,
+    ) -> anyhow::Result<()> {
+        sqlx::query!(
+            "INSERT INTO chat_digest (chat_id, account_id, language_code)
+                VALUES (lookup_chat_by_telegram_id($1), lookup_account_by_telegram_id($2), $3)
+                ON CONFLICT (chat_id) DO UPDATE SET account_id = EXCLUDED.account_id, language_code = EXCLUDED.language_code, last_sent_at = current_timestamp",
+            chat_id,
+            user_id,
+            language_code
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Stop sending a chat's digest, returning if it was enabled.
+    pub async fn disable(conn: &sqlx::Pool<sqlx::Postgres>, chat_id: i64) -> anyhow::Result<bool> {
+        let result = sqlx::query!(
+            "DELETE FROM chat_digest WHERE chat_id = lookup_chat_by_telegram_id($1)",
+            chat_id
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Get the user a chat's digest is sent to, if enabled.
+    pub async fn get_user(
+        conn: &sqlx::Pool<sqlx::Postgres>,
+        chat_id: i64,
+    ) -> anyhow::Result<Option<i64>> {
+        let user_id = sqlx::query_scalar!(
+            "SELECT account.telegram_id
+            FROM chat_digest
+            JOIN account ON account.id = chat_digest.account_id
+            WHERE chat_digest.chat_id = lookup_chat_by_telegram_id($1)",
+            chat_id
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(user_id)
+    }
+
+    /// Get a chat's digest as it would be sent now, if enabled.
+    pub async fn get(
+        conn: &sqlx::Pool<sqlx::Postgres>,
+        chat_id: i64,
+    ) -> anyhow::Result<Option<DueDigest>> {
+        let digest = sqlx::query!(
+            "SELECT account.telegram_id user_id, chat_digest.last_sent_at since, chat_digest.language_code
+            FROM chat_digest
+            JOIN account ON account.id = chat_digest.account_id
+            WHERE chat_digest.chat_id = lookup_chat_by_telegram_id($1)",
+            chat_id
+        )
+        .map(|row| DueDigest {
+            chat_id,
+            user_id: row.user_id,
+            since: row.since.timestamp(),
+            language_code: row.language_code,
+            chat_gone: false,
+        })
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(digest)
+    }
+
+    /// Find digests that haven't been sent in the given number of hours.
+    pub async fn due(
+        conn: &sqlx::Pool<sqlx::Postgres>,
+        interval_hours: i32,
+    ) -> anyhow::Result<Vec<DueDigest>> {
+        let digests = sqlx::query!(
+            "SELECT
+                (SELECT telegram_id FROM chat_telegram WHERE chat_telegram.chat_id = chat_digest.chat_id ORDER BY telegram_id LIMIT 1) chat_id,
+                account.telegram_id user_id,
+                chat_digest.last_sent_at since,
+                chat_digest.language_code
+            FROM chat_digest
+            JOIN account ON account.id = chat_digest.account_id
+            WHERE chat_digest.last_sent_at < now() - make_interval(hours => $1)",
+            interval_hours
+        )
+        .fetch_all(conn)
+        .await?;
+
+        Ok(digests
+            .into_iter()
+            .filter_map(|row| {
+                Some(DueDigest {
+                    chat_id: row.chat_id?,
+                    user_id: row.user_id,
+                    since: row.since.timestamp(),
+                    language_code: row.language_code,
+                    chat_gone: false,
+                })
+            })
+            .collect())
+    }
+
+    /// Remember that a chat's digest was just sent.
+    pub async fn mark_sent(conn: &sqlx::Pool<sqlx::Postgres>, chat_id: i64) -> anyhow::Result<()> {
+        sqlx::query!(
+            "UPDATE chat_digest SET last_sent_at = current_timestamp
+            WHERE chat_id = lookup_chat_by_telegram_id($1)",
+            chat_id
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+}
+
+/// A message in a group that was automatically forwarded from a post in its
+/// linked channel.
+#[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct LinkedForward {
+    pub chat_id: i64,
+    pub message_id: i32,
+}
+
+impl LinkedForward {
+    /// How long to remember forwards, in case the channel post is edited.
+    const FORWARD_SECONDS: usize = 60 * 60 * 24 * 30;
+
+    fn key(channel_id: i64, channel_message_id: i32) -> String {
+        format!("linked-forward:{}:{}", channel_id, channel_message_id)
+    }
+
+    /// Remember the group message a channel post was forwarded to.
+    pub async fn set(
+        redis: &redis::aio::ConnectionManager,
+        channel_id: i64,
+        channel_message_id: i32,
+        forward: &LinkedForward,
+    ) -> anyhow::Result<()> {
+        use redis::AsyncCommands;
+
+        let mut redis = redis.clone();
+        redis
+            .set_ex(
+                Self::key(channel_id, channel_message_id),
+                serde_json::to_string(forward)?,
+                Self::FORWARD_SECONDS,
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Get the group message a channel post was forwarded to, if known.
+    pub async fn get(
+        redis: &redis::aio::ConnectionManager,
+        channel_id: i64,
+        channel_message_id: i32,
+    ) -> anyhow::Result<Option<LinkedForward>> {
+        use redis::AsyncCommands;
+
+        let mut redis = redis.clone();
+        let forward: Option<String> = redis.get(Self::key(channel_id, channel_message_id)).await?;
+
+        forward
+            .map(|forward| serde_json::from_str(&forward))
+            .transpose()
+            .map_err(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ChatAction;
+
+    #[test]
+    fn test_chat_action_round_trip() {
+        let actions = [
+            ChatAction::ChannelEdited,
+            ChatAction::ChannelUnsourced,
+            ChatAction::ChannelEditFailed,
+            ChatAction::GroupSourced,
+            ChatAction::PermissionLost,
+        ];
+
+        for action in actions.iter() {
+            assert_eq!(action.as_str().parse::<ChatAction>(), Ok(*action));
+        }
+
+        assert!("unknown".parse::<ChatAction>().is_err());
+    }
+}
@@ -7,6 +7,12 @@ use thiserror::Error;
 
 use foxbot_models::{DisplayableErrorMessage, Twitter as TwitterModel};
 
+mod reverse_search;
+
+pub use reverse_search::{
+    new_fuzzysearch, serve_stand_in, BoxedReverseSearch, InMemoryReverseSearch, ReverseSearch,
+};
+
 /// User agent used with all HTTP requests to sites.
 const USER_AGENT: &str = concat!(
     "t.me/FoxBot Site Loader Version ",
@@ -45,6 +51,7 @@ pub struct PostInfo {
     pub artist_url: Option<String>,
     pub submission_title: Option<String>,
     pub tags: Option<Vec<String>>,
+    pub rating: Option<fuzzysearch::Rating>,
 }
 
 /// A basic attempt to get the extension from a given URL. It assumes the URL
@@ -78,7 +85,7 @@ pub trait Site {
 pub async fn get_all_sites(
     fa_a: String,
     fa_b: String,
-    fuzzysearch_apitoken: String,
+    reverse_search: BoxedReverseSearch,
     weasyl_apitoken: String,
     twitter_consumer_key: String,
     twitter_consumer_secret: String,
@@ -95,13 +102,13 @@ pub async fn get_all_sites(
             e621_api_key.clone(),
         )),
         Box::new(E621::new(E621Host::E926, e621_login, e621_api_key)),
-        Box::new(FurAffinity::new((fa_a, fa_b), fuzzysearch_apitoken.clone())),
+        Box::new(FurAffinity::new((fa_a, fa_b), reverse_search.clone())),
         Box::new(Weasyl::new(weasyl_apitoken)),
         Box::new(Twitter::new(twitter_consumer_key, twitter_consumer_secret, pool).await),
         Box::new(Inkbunny::new(inkbunny_username, inkbunny_password)),
         Box::new(Mastodon::default()),
         Box::new(DeviantArt::default()),
-        Box::new(Direct::new(fuzzysearch_apitoken)),
+        Box::new(Direct::new(reverse_search)),
     ]
 }
 
@@ -132,7 +139,7 @@ impl<U> OptionExt for Option<U> {
 /// extension, potentially blocking loaders that are more specific.
 pub struct Direct {
     client: reqwest::Client,
-    fautil: std::sync::Arc<fuzzysearch::FuzzySearch>,
+    reverse_search: BoxedReverseSearch,
 }
 
 impl Direct {
@@ -141,16 +148,17 @@ impl Direct {
     /// Mime types we should consider valid images.
     const TYPES: &'static [&'static str] = &["image/png", "image/jpeg", "image/gif"];
 
-    pub fn new(fuzzysearch_apitoken: String) -> Self {
-        let fautil = std::sync::Arc::new(fuzzysearch::FuzzySearch::new(fuzzysearch_apitoken));
-
+    pub fn new(reverse_search: BoxedReverseSearch) -> Self {
         let client = reqwest::Client::builder()
             .timeout(std::time::Duration::from_secs(2))
             .user_agent(USER_AGENT)
             .build()
             .expect("Unable to create client");
 
-        Self { client, fautil }
+        Self {
+            client,
+            reverse_search,
+        }
     }
 
     /// Attempt to download the image from the given URL and search the contents
@@ -171,12 +179,12 @@ impl Direct {
         };
 
         let results = self
-            .fautil
+            .reverse_search
             .image_search(&body, MatchType::Exact, Some(1))
             .await;
 
         match results {
-            Ok(results) => results.matches.into_iter().next(),
+            Ok(results) => results.into_iter().next(),
             Err(_) => None,
         }
     }
@@ -313,6 +321,7 @@ struct E621Post {
     file: E621PostFile,
     preview: E621PostPreview,
     tags: HashMap<String, Vec<String>>,
+    rating: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -333,6 +342,7 @@ struct E621Data {
     file_ext: String,
     preview_url: String,
     artists: Vec<String>,
+    rating: Option<fuzzysearch::Rating>,
 }
 
 impl E621 {
@@ -373,7 +383,7 @@ impl E621 {
                                 url: Some(preview_url),
                             },
                         tags,
-                        ..
+                        rating,
                     }),
             } => Some(E621Data {
                 id,
@@ -387,6 +397,12 @@ impl E621 {
                     .flatten()
                     .filter(|tag| !Self::INVALID_ARTISTS.contains(&&**tag))
                     .collect(),
+                rating: match rating.as_deref() {
+                    Some("s") => Some(fuzzysearch::Rating::General),
+                    Some("q") => Some(fuzzysearch::Rating::Mature),
+                    Some("e") => Some(fuzzysearch::Rating::Adult),
+                    _ => None,
+                },
             }),
             _ => None,
         }
@@ -420,6 +436,7 @@ impl E621 {
                 file_ext,
                 preview_url,
                 artists,
+                rating,
             } = match Self::get_urls(resp) {
                 Some(vals) => vals,
                 None => continue,
@@ -436,6 +453,7 @@ impl E621 {
                 } else {
                     None
                 },
+                rating,
                 ..Default::default()
             });
         }
@@ -515,6 +533,7 @@ impl Site for E621 {
             file_ext,
             preview_url,
             artists,
+            rating,
         } = match Self::get_urls(resp) {
             Some(vals) => vals,
             None => return Ok(None),
@@ -531,6 +550,7 @@ impl Site for E621 {
             } else {
                 None
             },
+            rating,
             ..Default::default()
         }]))
     }
@@ -779,18 +799,18 @@ fn get_best_video(media: &egg_mode::entities::MediaEntity) -> Option<&str> {
 ///
 /// It converts direct image URLs back into submission URLs using FuzzySearch.
 pub struct FurAffinity {
-    fapi: fuzzysearch::FuzzySearch,
+    reverse_search: BoxedReverseSearch,
     matcher: regex::Regex,
     fa: furaffinity_rs::FurAffinity,
 }
 
 impl FurAffinity {
-    pub fn new(cookies: (String, String), util_api: String) -> Self {
+    pub fn new(cookies: (String, String), reverse_search: BoxedReverseSearch) -> Self {
         let fa =
             furaffinity_rs::FurAffinity::new(cookies.0, cookies.1, USER_AGENT.to_string(), None);
 
         Self {
-            fapi: fuzzysearch::FuzzySearch::new(util_api),
+            reverse_search,
             fa,
             matcher: regex::Regex::new(
                 r#"(?:https?://)?(?:(?:www\.)?furaffinity\.net/(?:view|full)/(?P<id>\d+)/?|(?:d\.furaffinity\.net|d\.facdn\.net)/art/\w+/(?P<file_id>\d+)/(?P<file_name>\S+))"#,
@@ -802,7 +822,7 @@ impl FurAffinity {
     /// Attempt to resolve a direct image URL into a submission using
     /// FuzzySearch.
     async fn load_direct_url(&self, filename: &str, url: &str) -> anyhow::Result<Option<PostInfo>> {
-        let sub: fuzzysearch::File = match self.fapi.lookup_filename(filename).await {
+        let sub: fuzzysearch::File = match self.reverse_search.lookup_filename(filename).await {
             Ok(mut results) if !results.is_empty() => results.remove(0),
             _ => {
                 let ext = match get_file_ext(url) {
@@ -1060,6 +1080,7 @@ struct WeasylSubmission {
     owner: String,
     owner_login: String,
     tags: Vec<String>,
+    rating: Option<String>,
     media: WeasylMedia,
 }
 
@@ -1152,6 +1173,12 @@ impl Site for Weasyl {
         let tags = Some(resp.tags.clone());
         let artist_username = Some(resp.owner.clone());
         let artist_url = Some(format!("https://www.weasyl.com/~{}", resp.owner_login));
+        let rating = match resp.rating.as_deref() {
+            Some("general") => Some(fuzzysearch::Rating::General),
+            Some("mature") => Some(fuzzysearch::Rating::Mature),
+            Some("explicit") => Some(fuzzysearch::Rating::Adult),
+            _ => None,
+        };
 
         Ok(Some(
             resp.media
@@ -1172,6 +1199,7 @@ impl Site for Weasyl {
                         tags: tags.clone(),
                         artist_username: artist_username.clone(),
                         artist_url: artist_url.clone(),
+                        rating: rating.clone(),
                         ..Default::default()
                     })
                 })
@@ -1218,6 +1246,7 @@ pub struct InkbunnySubmission {
     title: String,
     files: Vec<InkbunnyFile>,
     keywords: Vec<InkbunnyKeyword>,
+    rating_id: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -1378,6 +1407,13 @@ impl Site for Inkbunny {
                 .map(|kw| kw.keyword_name.clone())
                 .collect();
 
+            let rating = match submission.rating_id.as_deref() {
+                Some("0") => Some(fuzzysearch::Rating::General),
+                Some("1") => Some(fuzzysearch::Rating::Mature),
+                Some("2") => Some(fuzzysearch::Rating::Adult),
+                _ => None,
+            };
+
             for file in submission.files {
                 let ext = match get_file_ext(&file.file_url_screen) {
                     Some(ext) => ext,
@@ -1394,6 +1430,7 @@ impl Site for Inkbunny {
                     tags: Some(tags.clone()),
                     artist_username: Some(submission.username.clone()),
                     artist_url: Some(format!("https://inkbunny.net/{}", submission.username)),
+                    rating: rating.clone(),
                     ..Default::default()
                 });
             }
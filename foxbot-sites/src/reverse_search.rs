@@ -0,0 +1,402 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+
+use anyhow::Context;
+use async_trait::async_trait;
+use fuzzysearch::{File, MatchType};
+use hyper::{Body, Method, Request, Response, StatusCode};
+
+/// A thread-safe and shared ReverseSearch.
+pub type BoxedReverseSearch = Arc<dyn ReverseSearch>;
+
+/// Distance used by FuzzySearch when none is provided.
+const DEFAULT_DISTANCE: i64 = 3;
+
+/// A service that can find the sources of images, such as FuzzySearch.
+#[async_trait]
+pub trait ReverseSearch: Send + Sync {
+    /// Find all files with a hash within distance of any provided hash.
+    async fn lookup_hashes(
+        &self,
+        hashes: &[i64],
+        distance: Option<i64>,
+    ) -> anyhow::Result<Vec<File>>;
+
+    /// Hash the image data and find all files matching it.
+    async fn image_search(
+        &self,
+        data: &[u8],
+        match_type: MatchType,
+        distance: Option<i64>,
+    ) -> anyhow::Result<Vec<File>>;
+
+    /// Find all files with the given filename.
+    async fn lookup_filename(&self, filename: &str) -> anyhow::Result<Vec<File>>;
+}
+
+#[async_trait]
+impl ReverseSearch for fuzzysearch::FuzzySearch {
+    async fn lookup_hashes(
+        &self,
+        hashes: &[i64],
+        distance: Option<i64>,
+    ) -> anyhow::Result<Vec<File>> {
+        fuzzysearch::FuzzySearch::lookup_hashes(self, hashes, distance)
+            .await
+            .context("unable to lookup hashes")
+    }
+
+    async fn image_search(
+        &self,
+        data: &[u8],
+        match_type: MatchType,
+        distance: Option<i64>,
+    ) -> anyhow::Result<Vec<File>> {
+        fuzzysearch::FuzzySearch::image_search(self, data, match_type, distance)
+            .await
+            .map(|results| results.matches)
+            .context("unable to search image")
+    }
+
+    async fn lookup_filename(&self, filename: &str) -> anyhow::Result<Vec<File>> {
+        fuzzysearch::FuzzySearch::lookup_filename(self, filename)
+            .await
+            .context("unable to lookup filename")
+    }
+}
+
+/// Create a FuzzySearch client, optionally using a different API endpoint
+/// such as the [`serve_stand_in`] server.
+pub fn new_fuzzysearch(api_key: String, endpoint: Option<String>) -> BoxedReverseSearch {
+    Arc::new(fuzzysearch::FuzzySearch::new_with_opts(
+        fuzzysearch::FuzzySearchOpts {
+            api_key,
+            endpoint,
+            client: None,
+        },
+    ))
+}
+
+/// A reverse search backed by an in-memory table of hashes to files, useful
+/// for running without access to FuzzySearch.
+#[derive(Default)]
+pub struct InMemoryReverseSearch {
+    files: RwLock<HashMap<i64, Vec<File>>>,
+}
+
+impl InMemoryReverseSearch {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Add a file with the given hash.
+    pub fn insert(&self, hash: i64, file: File) {
+        let file = File {
+            hash: Some(hash),
+            ..file
+        };
+
+        self.files
+            .write()
+            .unwrap()
+            .entry(hash)
+            .or_default()
+            .push(file);
+    }
+
+    fn find_hashes(&self, hashes: &[i64], distance: Option<i64>) -> Vec<File> {
+        let distance = distance.unwrap_or(DEFAULT_DISTANCE).max(0) as u32;
+        let files = self.files.read().unwrap();
+
+        let mut matches = Vec::new();
+
+        for (hash, known) in files.iter() {
+            let closest = hashes
+                .iter()
+                .map(|searched| (hash ^ searched).count_ones())
+                .min();
+
+            match closest {
+                Some(closest) if closest <= distance => {
+                    matches.extend(known.iter().map(|file| File {
+                        distance: Some(closest as u64),
+                        ..file.clone()
+                    }))
+                }
+                _ => continue,
+            }
+        }
+
+        matches
+    }
+
+    /// Hash image data and look up matching files, returning the computed
+    /// hash with the matches.
+    fn search_bytes(
+        &self,
+        data: &[u8],
+        match_type: MatchType,
+        distance: Option<i64>,
+    ) -> anyhow::Result<(i64, Vec<File>)> {
+        let hash = fuzzysearch::hash_bytes(data).context("unable to hash image")?;
+
+        let mut matches = self.find_hashes(&[hash], distance);
+        if matches!(match_type, MatchType::Exact) {
+            matches.retain(|file| file.distance == Some(0));
+        }
+
+        Ok((hash, matches))
+    }
+}
+
+#[async_trait]
+impl ReverseSearch for InMemoryReverseSearch {
+    async fn lookup_hashes(
+        &self,
+        hashes: &[i64],
+        distance: Option<i64>,
+    ) -> anyhow::Result<Vec<File>> {
+        Ok(self.find_hashes(hashes, distance))
+    }
+
+    async fn image_search(
+        &self,
+        data: &[u8],
+        match_type: MatchType,
+        distance: Option<i64>,
+    ) -> anyhow::Result<Vec<File>> {
+        self.search_bytes(data, match_type, distance)
+            .map(|(_hash, matches)| matches)
+    }
+
+    async fn lookup_filename(&self, filename: &str) -> anyhow::Result<Vec<File>> {
+        let files = self.files.read().unwrap();
+
+        Ok(files
+            .values()
+            .flatten()
+            .filter(|file| file.filename == filename)
+            .cloned()
+            .collect())
+    }
+}
+
+/// Start a local HTTP server that answers the FuzzySearch API endpoints used
+/// by the bot from an in-memory table.
+///
+/// Returns the address the server is listening on, which allows binding to
+/// port 0 in tests.
+pub fn serve_stand_in(
+    search: Arc<InMemoryReverseSearch>,
+    addr: SocketAddr,
+) -> anyhow::Result<SocketAddr> {
+    use hyper::service::{make_service_fn, service_fn};
+
+    let make_svc = make_service_fn(move |_conn| {
+        let search = search.clone();
+
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let search = search.clone();
+
+                async move { Ok::<_, Infallible>(stand_in_response(&search, req).await) }
+            }))
+        }
+    });
+
+    let server = hyper::Server::try_bind(&addr)
+        .context("unable to bind stand-in server")?
+        .serve(make_svc);
+    let addr = server.local_addr();
+
+    tracing::info!(%addr, "started reverse search stand-in");
+
+    tokio::spawn(async move {
+        if let Err(err) = server.await {
+            tracing::error!("reverse search stand-in stopped: {:?}", err);
+        }
+    });
+
+    Ok(addr)
+}
+
+async fn stand_in_response(search: &InMemoryReverseSearch, req: Request<Body>) -> Response<Body> {
+    let (parts, body) = req.into_parts();
+
+    let params: HashMap<String, String> =
+        url::form_urlencoded::parse(parts.uri.query().unwrap_or_default().as_bytes())
+            .into_owned()
+            .collect();
+    let distance = params
+        .get("distance")
+        .and_then(|distance| distance.parse().ok());
+
+    let resp: anyhow::Result<Vec<u8>> = match (&parts.method, parts.uri.path()) {
+        (&Method::GET, "/hashes") => {
+            let hashes: Vec<i64> = params
+                .get("hashes")
+                .map(|hashes| {
+                    hashes
+                        .split(',')
+                        .filter_map(|hash| hash.parse().ok())
+                        .collect()
+                })
+                .unwrap_or_default();
+
+            serde_json::to_vec(&search.find_hashes(&hashes, distance)).map_err(Into::into)
+        }
+        (&Method::GET, "/file") => {
+            let name = params.get("name").map(String::as_str).unwrap_or_default();
+
+            match search.lookup_filename(name).await {
+                Ok(files) => serde_json::to_vec(&files).map_err(Into::into),
+                Err(err) => Err(err),
+            }
+        }
+        (&Method::POST, "/image") => {
+            let match_type = match params.get("type").map(String::as_str) {
+                Some("exact") => MatchType::Exact,
+                Some("force") => MatchType::Force,
+                _ => MatchType::Close,
+            };
+
+            let content_type = parts
+                .headers
+                .get(hyper::header::CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default()
+                .to_string();
+
+            match hyper::body::to_bytes(body).await {
+                Ok(body) => multipart_file(&content_type, &body)
+                    .context("request was missing image")
+                    .and_then(|data| search.search_bytes(data, match_type, distance))
+                    .and_then(|(hash, matches)| {
+                        serde_json::to_vec(&serde_json::json!({
+                            "hash": hash,
+                            "distance": distance.unwrap_or(DEFAULT_DISTANCE),
+                            "matches": matches,
+                        }))
+                        .map_err(Into::into)
+                    }),
+                Err(err) => Err(err.into()),
+            }
+        }
+        _ => {
+            return Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Body::empty())
+                .unwrap()
+        }
+    };
+
+    match resp {
+        Ok(body) => Response::builder()
+            .header(hyper::header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body))
+            .unwrap(),
+        Err(err) => {
+            tracing::warn!("stand-in request failed: {:?}", err);
+
+            Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from(err.to_string()))
+                .unwrap()
+        }
+    }
+}
+
+/// Extract the contents of the first file in a multipart body. This only
+/// handles the simple single-part forms sent by the FuzzySearch client.
+fn multipart_file<'a>(content_type: &str, body: &'a [u8]) -> Option<&'a [u8]> {
+    let boundary = content_type
+        .split(';')
+        .filter_map(|part| part.trim().strip_prefix("boundary="))
+        .next()?
+        .trim_matches('"');
+    let delimiter = format!("\r\n--{}", boundary);
+
+    let headers_end = find_subslice(body, b"\r\n\r\n")? + 4;
+    let data = &body[headers_end..];
+    let data_end = find_subslice(data, delimiter.as_bytes())?;
+
+    Some(&data[..data_end])
+}
+
+fn find_subslice(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    fn file(site_id: i64, filename: &str) -> File {
+        File {
+            site_id,
+            filename: filename.to_string(),
+            site_info: Some(fuzzysearch::SiteInfo::FurAffinity(
+                fuzzysearch::FurAffinityFile { file_id: 123 },
+            )),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_lookup() {
+        let search = InMemoryReverseSearch::new();
+        search.insert(0b1111, file(1, "first.png"));
+        search.insert(0b1111_0000_0000, file(2, "second.png"));
+
+        let matches = search.lookup_hashes(&[0b0111], Some(3)).await.unwrap();
+        assert_eq!(matches.len(), 1, "only close hash should match");
+        assert_eq!(matches[0].site_id, 1);
+        assert_eq!(matches[0].distance, Some(1), "distance should be computed");
+
+        let matches = search.lookup_hashes(&[0b0111], Some(0)).await.unwrap();
+        assert!(matches.is_empty(), "distance should be respected");
+
+        let matches = search.lookup_filename("second.png").await.unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].hash, Some(0b1111_0000_0000));
+    }
+
+    #[test]
+    fn test_multipart_file() {
+        let body = b"--abc\r\nContent-Disposition: form-data; name=\"image\"\r\n\r\nimage data\r\n--abc--\r\n";
+
+        assert_eq!(
+            multipart_file("multipart/form-data; boundary=abc", body),
+            Some(&b"image data"[..])
+        );
+        assert_eq!(multipart_file("multipart/form-data", body), None);
+    }
+
+    #[tokio::test]
+    async fn test_stand_in_server() {
+        let search = Arc::new(InMemoryReverseSearch::new());
+        search.insert(42, file(1, "first.png"));
+
+        let addr = serve_stand_in(search, "127.0.0.1:0".parse().unwrap()).unwrap();
+        let client = new_fuzzysearch(String::new(), Some(format!("http://{}", addr)));
+
+        let matches = client.lookup_hashes(&[42], Some(3)).await.unwrap();
+        assert_eq!(matches.len(), 1, "stand-in should return known hash");
+        assert_eq!(matches[0].site_id, 1);
+
+        let matches = client.lookup_filename("first.png").await.unwrap();
+        assert_eq!(matches.len(), 1, "stand-in should return known filename");
+
+        let matches = client.lookup_hashes(&[-42], Some(3)).await.unwrap();
+        assert!(
+            matches.is_empty(),
+            "stand-in should not return unknown hash"
+        );
+    }
+}
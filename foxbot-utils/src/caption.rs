@@ -0,0 +1,449 @@
+use foxbot_sites::PostInfo;
+
+/// Telegram's maximum caption length, in UTF-16 code units after entities
+/// have been parsed.
+pub const MAX_CAPTION_LENGTH: usize = 1024;
+
+/// Placeholders that can be used in caption templates.
+pub const CAPTION_PLACEHOLDERS: &[&str] = &[
+    "title",
+    "artist",
+    "artist_link",
+    "site",
+    "tags",
+    "rating",
+    "source",
+];
+
+/// Template used when info is requested and the user has no template.
+pub const DEFAULT_INFO_TEMPLATE: &str = "Title: {title}\nArtist: {artist_link}";
+/// Template used when tags are requested and the user has no template.
+pub const DEFAULT_TAGS_TEMPLATE: &str = "Tags: {tags}";
+
+/// Escape text for use with Telegram's MarkdownV2 formatting.
+pub fn escape_markdown<S: AsRef<str>>(input: S) -> String {
+    let input = input.as_ref();
+    let mut escaped = String::with_capacity(input.len());
+
+    for c in input.chars() {
+        if matches!(
+            c,
+            '\\' | '_'
+                | '*'
+                | '['
+                | ']'
+                | '('
+                | ')'
+                | '~'
+                | '`'
+                | '>'
+                | '#'
+                | '+'
+                | '-'
+                | '='
+                | '|'
+                | '{'
+                | '}'
+                | '.'
+                | '!'
+        ) {
+            escaped.push('\\');
+        }
+
+        escaped.push(c);
+    }
+
+    escaped
+}
+
+/// Markers written before and after an entity's text in MarkdownV2, if it
+/// is a formatting entity.
+fn entity_markers(entity: &tgbotapi::MessageEntity) -> Option<(&'static str, String)> {
+    use tgbotapi::MessageEntityType::*;
+
+    let markers = match entity.entity_type {
+        Bold => ("*", "*".to_string()),
+        Italic => ("_", "_".to_string()),
+        Underline => ("__", "__".to_string()),
+        Strikethrough => ("~", "~".to_string()),
+        Code => ("`", "`".to_string()),
+        Pre => ("```\n", "\n```".to_string()),
+        TextLink => (
+            "[",
+            format!(
+                "]({})",
+                entity
+                    .url
+                    .as_deref()?
+                    .replace('\\', r"\\")
+                    .replace(')', r"\)")
+            ),
+        ),
+        // Everything else is detected by Telegram from the text itself.
+        _ => return None,
+    };
+
+    Some(markers)
+}
+
+/// Convert text with entities, such as an existing caption, into MarkdownV2
+/// so that it keeps its formatting when it is sent again.
+///
+/// Entities may be nested but are expected not to overlap, as Telegram
+/// never sends overlapping entities.
+pub fn entities_markdown(text: &str, entities: &[tgbotapi::MessageEntity]) -> String {
+    let mut formatting: Vec<_> = entities
+        .iter()
+        .filter_map(|entity| {
+            let (start, end) = entity_markers(entity)?;
+            let offset = entity.offset as usize;
+            Some((offset, offset + entity.length as usize, start, end))
+        })
+        .collect();
+    // Outer entities must be opened first when several start together.
+    formatting.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)));
+
+    let mut markdown = String::with_capacity(text.len());
+    let mut open: Vec<(usize, String)> = Vec::new();
+    let mut next = formatting.into_iter().peekable();
+    let mut pos = 0;
+
+    for c in text.chars() {
+        while matches!(open.last(), Some((end, _)) if *end <= pos) {
+            markdown.push_str(&open.pop().unwrap().1);
+        }
+
+        while let Some((_, end, start, close)) = next.next_if(|entity| entity.0 <= pos) {
+            markdown.push_str(start);
+            open.push((end, close));
+        }
+
+        markdown.push_str(&escape_markdown(c.encode_utf8(&mut [0; 4])));
+        pos += c.len_utf16();
+    }
+
+    while let Some((_, close)) = open.pop() {
+        markdown.push_str(&close);
+    }
+
+    markdown
+}
+
+/// Convert tags into hashtags Telegram can link, skipping tags that can
+/// never be valid.
+pub fn format_hashtags(tags: &[String]) -> String {
+    tags.iter()
+        // Tags can't be all numbers, and no transformation can fix that
+        .filter(|tag| tag.parse::<i64>().is_err())
+        .map(|tag| {
+            format!(
+                "#{}",
+                tag.replace(' ', "_")
+                    .replace('/', "_")
+                    .replace('(', "")
+                    .replace(')', "")
+                    .replace('-', "_")
+            )
+        })
+        .take(50)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// A problem found while validating a caption template.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TemplateError {
+    /// A placeholder that isn't in [`CAPTION_PLACEHOLDERS`].
+    UnknownPlaceholder(String),
+    /// An opening brace without a closing brace.
+    UnclosedBrace,
+}
+
+/// Check that a template only uses known placeholders and has matching
+/// braces, returning the first problem found.
+pub fn validate_caption_template(template: &str) -> Result<(), TemplateError> {
+    let parts = parse_template(template).ok_or(TemplateError::UnclosedBrace)?;
+
+    for segment in parts {
+        if let TemplatePart::Placeholder(name) = segment {
+            if !CAPTION_PLACEHOLDERS.contains(&name) {
+                return Err(TemplateError::UnknownPlaceholder(name.to_string()));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+enum TemplatePart<'a> {
+    Text(&'a str),
+    Placeholder(&'a str),
+}
+
+fn parse_template(line: &str) -> Option<Vec<TemplatePart>> {
+    let mut parts = Vec::new();
+    let mut rest = line;
+
+    while let Some(start) = rest.find('{') {
+        if start > 0 {
+            parts.push(TemplatePart::Text(&rest[..start]));
+        }
+
+        let end = rest[start..].find('}')?;
+        parts.push(TemplatePart::Placeholder(
+            rest[start + 1..start + end].trim(),
+        ));
+
+        rest = &rest[start + end + 1..];
+    }
+
+    if !rest.is_empty() {
+        parts.push(TemplatePart::Text(rest));
+    }
+
+    Some(parts)
+}
+
+/// A piece of rendered caption, before it is escaped.
+enum Segment {
+    Text(String),
+    Link(String, String),
+}
+
+impl Segment {
+    fn text_mut(&mut self) -> &mut String {
+        match self {
+            Segment::Text(text) => text,
+            Segment::Link(text, _url) => text,
+        }
+    }
+
+    fn escape(&self) -> String {
+        match self {
+            Segment::Text(text) => escape_markdown(text),
+            Segment::Link(text, url) => format!(
+                "[{}]({})",
+                escape_markdown(text),
+                url.replace('\\', r"\\").replace(')', r"\)")
+            ),
+        }
+    }
+}
+
+fn placeholder_value(name: &str, post: &PostInfo, rating: Option<&str>) -> Option<Segment> {
+    let text = |value: Option<&str>| value.map(|value| Segment::Text(value.to_string()));
+
+    match name {
+        "title" => text(post.title.as_deref()),
+        "artist" => text(post.artist_username.as_deref()),
+        "artist_link" => match (&post.artist_username, &post.artist_url) {
+            (Some(username), Some(url)) => Some(Segment::Link(username.clone(), url.clone())),
+            (Some(value), None) | (None, Some(value)) => Some(Segment::Text(value.clone())),
+            (None, None) => None,
+        },
+        "site" => Some(Segment::Text(post.site_name.to_string())),
+        "tags" => match &post.tags {
+            Some(tags) if !tags.is_empty() => Some(Segment::Text(format_hashtags(tags))),
+            _ => None,
+        },
+        "rating" => text(rating),
+        "source" => text(post.source_link.as_deref()),
+        _ => None,
+    }
+}
+
+/// Render a caption template for a post as MarkdownV2.
+///
+/// Placeholders are written as `{name}`. A line is left out if it contains
+/// placeholders and none of them had a value. The caption is cut short to
+/// fit within Telegram's caption length limit.
+pub fn render_caption(template: &str, post: &PostInfo, rating: Option<&str>) -> Option<String> {
+    let mut segments: Vec<Segment> = Vec::new();
+
+    for line in template.lines() {
+        let parts = match parse_template(line) {
+            Some(parts) => parts,
+            None => vec![TemplatePart::Text(line)],
+        };
+
+        let mut line_segments = Vec::with_capacity(parts.len());
+        let mut placeholders = 0;
+        let mut values = 0;
+
+        for part in parts {
+            match part {
+                TemplatePart::Text(text) => line_segments.push(Segment::Text(text.to_string())),
+                TemplatePart::Placeholder(name) => {
+                    placeholders += 1;
+
+                    if let Some(segment) = placeholder_value(name, post, rating) {
+                        values += 1;
+                        line_segments.push(segment);
+                    }
+                }
+            }
+        }
+
+        if placeholders > 0 && values == 0 {
+            continue;
+        }
+
+        if !segments.is_empty() {
+            segments.push(Segment::Text("\n".to_string()));
+        }
+
+        segments.extend(line_segments);
+    }
+
+    truncate_segments(&mut segments, MAX_CAPTION_LENGTH);
+
+    let caption: String = segments.iter().map(Segment::escape).collect();
+
+    if caption.trim().is_empty() {
+        None
+    } else {
+        Some(caption)
+    }
+}
+
+/// Shorten segments so their visible text fits within the limit, ending
+/// with an ellipsis if anything was removed.
+fn truncate_segments(segments: &mut Vec<Segment>, limit: usize) {
+    let total: usize = segments
+        .iter_mut()
+        .map(|segment| segment.text_mut().encode_utf16().count())
+        .sum();
+
+    if total <= limit {
+        return;
+    }
+
+    // Leave room for the ellipsis.
+    let mut remaining = limit - 1;
+    let mut end = segments.len();
+
+    for (idx, segment) in segments.iter_mut().enumerate() {
+        let text = segment.text_mut();
+        let len = text.encode_utf16().count();
+
+        if len <= remaining {
+            remaining -= len;
+            continue;
+        }
+
+        let mut cut = 0;
+
+        for (pos, c) in text.char_indices() {
+            if c.len_utf16() > remaining {
+                break;
+            }
+
+            remaining -= c.len_utf16();
+            cut = pos + c.len_utf8();
+        }
+
+        text.truncate(cut);
+        text.push('…');
+        end = idx + 1;
+
+        break;
+    }
+
+    segments.truncate(end);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn post() -> PostInfo {
+        PostInfo {
+            title: Some("A (Test) Title".to_string()),
+            artist_username: Some("Syfaro".to_string()),
+            artist_url: Some("https://www.furaffinity.net/user/syfaro/".to_string()),
+            site_name: "FurAffinity",
+            tags: Some(vec!["red fox".to_string(), "1234".to_string()]),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_escape_markdown() {
+        assert_eq!(escape_markdown("a_b*c"), r"a\_b\*c");
+        assert_eq!(escape_markdown(r"back\slash"), r"back\\slash");
+        assert_eq!(escape_markdown("plain text"), "plain text");
+    }
+
+    #[test]
+    fn test_entities_markdown() {
+        use tgbotapi::{MessageEntity, MessageEntityType};
+
+        let entity = |entity_type, offset, length, url: Option<&str>| MessageEntity {
+            entity_type,
+            offset,
+            length,
+            url: url.map(String::from),
+            user: None,
+        };
+
+        assert_eq!(entities_markdown("a.b", &[]), r"a\.b");
+
+        let text = "🦊 bold link!";
+        let entities = [
+            entity(MessageEntityType::Bold, 3, 9, None),
+            entity(MessageEntityType::TextLink, 8, 4, Some("https://e.com/(a)")),
+            entity(MessageEntityType::Url, 0, 2, None),
+        ];
+        assert_eq!(
+            entities_markdown(text, &entities),
+            r"🦊 *bold [link](https://e.com/(a\))*\!"
+        );
+    }
+
+    #[test]
+    fn test_render_caption() {
+        let post = post();
+
+        assert_eq!(
+            render_caption(DEFAULT_INFO_TEMPLATE, &post, None).as_deref(),
+            Some("Title: A \\(Test\\) Title\nArtist: [Syfaro](https://www.furaffinity.net/user/syfaro/)")
+        );
+        assert_eq!(
+            render_caption("{tags} on {site}", &post, None).as_deref(),
+            Some(r"\#red\_fox on FurAffinity")
+        );
+        assert_eq!(
+            render_caption("{title}\nRating: {rating}", &post, None).as_deref(),
+            Some(r"A \(Test\) Title"),
+            "lines with only missing placeholders should be removed"
+        );
+        assert_eq!(render_caption("{source}", &post, None), None);
+    }
+
+    #[test]
+    fn test_render_caption_truncates() {
+        let mut post = post();
+        post.title = Some("a".repeat(MAX_CAPTION_LENGTH * 2));
+
+        let caption = render_caption("{title}", &post, None).unwrap();
+        assert_eq!(caption.chars().count(), MAX_CAPTION_LENGTH);
+        assert!(caption.ends_with('…'));
+    }
+
+    #[test]
+    fn test_validate_caption_template() {
+        assert!(validate_caption_template("{title} by {artist}").is_ok());
+        assert_eq!(
+            validate_caption_template("{title} {nope}"),
+            Err(TemplateError::UnknownPlaceholder("nope".to_string()))
+        );
+        assert_eq!(
+            validate_caption_template("{title} {artist"),
+            Err(TemplateError::UnclosedBrace)
+        );
+        assert_eq!(
+            validate_caption_template("{nope} and {title}"),
+            Err(TemplateError::UnknownPlaceholder("nope".to_string()))
+        );
+    }
+}
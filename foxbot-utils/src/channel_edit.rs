@@ -0,0 +1,219 @@
+use anyhow::Context;
+
+use foxbot_models::{ChannelEditStyle, ChannelHashtags, GroupConfig, GroupConfigKey, Sites};
+
+use crate::{entities_markdown, escape_markdown, format_hashtags, get_message, Bundle};
+
+/// Stand-in for the artist link when formatting the artist credit, so the
+/// rest of the translated text can be escaped before the link is added.
+const ARTIST_PLACEHOLDER: &str = "\u{E000}";
+
+/// How a channel wants sources added to its posts.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ChannelEditPolicy {
+    pub style: ChannelEditStyle,
+    /// If the artist should be credited in the caption.
+    pub artist_credit: bool,
+    pub hashtags: ChannelHashtags,
+}
+
+impl ChannelEditPolicy {
+    /// Load a channel's policy, using defaults for anything not configured.
+    pub async fn load(conn: &sqlx::Pool<sqlx::Postgres>, chat_id: i64) -> anyhow::Result<Self> {
+        let defaults = Self::default();
+
+        let style = GroupConfig::get(conn, chat_id, GroupConfigKey::ChannelEditStyle)
+            .await
+            .context("unable to get channel edit style")?
+            .unwrap_or(defaults.style);
+        let artist_credit = GroupConfig::get(conn, chat_id, GroupConfigKey::ChannelArtistCredit)
+            .await
+            .context("unable to get channel artist credit")?
+            .unwrap_or(defaults.artist_credit);
+        let hashtags = GroupConfig::get(conn, chat_id, GroupConfigKey::ChannelHashtags)
+            .await
+            .context("unable to get channel hashtags")?
+            .unwrap_or(defaults.hashtags);
+
+        Ok(Self {
+            style,
+            artist_credit,
+            hashtags,
+        })
+    }
+
+    /// If source links should be added as buttons. Media groups can't have
+    /// buttons, so they always get links in the caption instead.
+    pub fn wants_buttons(&self, media_group: bool) -> bool {
+        !media_group && self.style != ChannelEditStyle::Caption
+    }
+
+    /// If source links should be appended to the caption.
+    pub fn wants_caption_links(&self, media_group: bool) -> bool {
+        media_group || self.style != ChannelEditStyle::Buttons
+    }
+}
+
+/// A line appended to a caption, with its visible text used to check the
+/// caption length and the MarkdownV2 that is sent.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CaptionLine {
+    pub text: String,
+    pub markdown: String,
+}
+
+impl CaptionLine {
+    /// A line of plain text.
+    pub fn text<S: Into<String>>(text: S) -> Self {
+        let text = text.into();
+
+        Self {
+            markdown: escape_markdown(&text),
+            text,
+        }
+    }
+
+    /// A line crediting the artists, linking to the source.
+    pub fn artist_credit(bundle: Bundle, artists: &[String], url: &str) -> Self {
+        let artists = artists.join(", ");
+
+        let credit = |artist: &str| {
+            let mut args = fluent::FluentArgs::new();
+            args.insert("artists", fluent::FluentValue::from(artist));
+
+            get_message(bundle, "channel-artist-credit", Some(args)).unwrap()
+        };
+
+        let link = format!(
+            "[{}]({})",
+            escape_markdown(&artists),
+            url.replace('\\', r"\\").replace(')', r"\)")
+        );
+
+        Self {
+            text: credit(&artists),
+            markdown: escape_markdown(credit(ARTIST_PLACEHOLDER))
+                .replace(ARTIST_PLACEHOLDER, &link),
+        }
+    }
+}
+
+/// Build the lines a channel wants added to a post's caption, in the order
+/// they should be kept if they don't all fit.
+pub fn channel_caption_lines(
+    bundle: Bundle,
+    policy: &ChannelEditPolicy,
+    media_group: bool,
+    firsts: &[(Sites, String)],
+    artists: &[String],
+) -> Vec<CaptionLine> {
+    let mut lines = Vec::with_capacity(firsts.len() + 2);
+
+    if policy.wants_caption_links(media_group) {
+        lines.extend(firsts.iter().map(|(_site, url)| CaptionLine::text(url)));
+    }
+
+    if policy.artist_credit && !artists.is_empty() {
+        if let Some((_site, url)) = firsts.first() {
+            lines.push(CaptionLine::artist_credit(bundle, artists, url));
+        }
+    }
+
+    let mut tags: Vec<String> = Vec::new();
+    if matches!(
+        policy.hashtags,
+        ChannelHashtags::Artist | ChannelHashtags::Both
+    ) {
+        tags.extend(artists.iter().cloned());
+    }
+    if matches!(
+        policy.hashtags,
+        ChannelHashtags::Site | ChannelHashtags::Both
+    ) {
+        tags.extend(firsts.iter().map(|(site, _url)| site.as_str().to_string()));
+    }
+
+    let hashtags = format_hashtags(&tags);
+    if !hashtags.is_empty() {
+        lines.push(CaptionLine::text(hashtags));
+    }
+
+    lines
+}
+
+/// Append lines to a post's existing caption, keeping its formatting.
+///
+/// Lines are removed from the end until the caption fits within Telegram's
+/// caption length limit. Returns `None` if no lines could be added.
+pub fn append_caption(
+    caption: Option<&str>,
+    entities: &[tgbotapi::MessageEntity],
+    mut lines: Vec<CaptionLine>,
+) -> Option<String> {
+    let existing = caption.filter(|caption| !caption.trim().is_empty());
+
+    // Existing text is separated from the new lines by a blank line.
+    let existing_len = existing
+        .map(|caption| caption.encode_utf16().count() + 2)
+        .unwrap_or(0);
+
+    let len = |lines: &[CaptionLine]| {
+        existing_len
+            + lines
+                .iter()
+                .map(|line| line.text.encode_utf16().count())
+                .sum::<usize>()
+            + lines.len().saturating_sub(1)
+    };
+
+    while !lines.is_empty() && len(&lines) > crate::MAX_CAPTION_LENGTH {
+        lines.pop();
+    }
+
+    if lines.is_empty() {
+        return None;
+    }
+
+    let added = lines
+        .iter()
+        .map(|line| line.markdown.as_str())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    Some(match existing {
+        Some(caption) => format!("{}\n\n{}", entities_markdown(caption, entities), added),
+        None => added,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_append_caption() {
+        let lines = vec![
+            CaptionLine::text("https://e621.net/posts/1"),
+            CaptionLine::text("#e621"),
+        ];
+
+        assert_eq!(
+            append_caption(None, &[], lines.clone()).as_deref(),
+            Some("https://e621\\.net/posts/1\n\\#e621")
+        );
+        assert_eq!(
+            append_caption(Some("Hi!"), &[], lines.clone()).as_deref(),
+            Some("Hi\\!\n\nhttps://e621\\.net/posts/1\n\\#e621")
+        );
+
+        let long = "a".repeat(crate::MAX_CAPTION_LENGTH - 30);
+        assert_eq!(
+            append_caption(Some(&long), &[], lines.clone()),
+            Some(format!("{}\n\nhttps://e621\\.net/posts/1", long)),
+            "lines that don't fit should be removed from the end"
+        );
+
+        let long = "a".repeat(crate::MAX_CAPTION_LENGTH);
+        assert_eq!(append_caption(Some(&long), &[], lines), None);
+    }
+}
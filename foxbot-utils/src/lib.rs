@@ -5,8 +5,24 @@ use std::{collections::HashSet, str::FromStr, sync::Arc};
 use tgbotapi::FileType;
 use tracing_futures::Instrument;
 
-use foxbot_models::{CachedPost, FileCache, Sites, UserConfig, UserConfigKey};
-use foxbot_sites::{BoxedSite, PostInfo};
+use foxbot_models::{
+    CachedPost, FileCache, GroupConfig, GroupConfigKey, Sites, UserConfig, UserConfigKey,
+};
+use foxbot_sites::{BoxedSite, PostInfo, ReverseSearch};
+
+mod caption;
+mod channel_edit;
+mod mirror;
+mod repost;
+mod source_policy;
+mod storage;
+
+pub use caption::*;
+pub use channel_edit::*;
+pub use mirror::*;
+pub use repost::*;
+pub use source_policy::*;
+pub use storage::*;
 
 /// Generates a random 24 character alphanumeric string.
 ///
@@ -128,14 +144,12 @@ pub struct ImageInfo {
 /// * Checks if URL already exists in cache, if so, returns that
 /// * Downloads image
 /// * Converts image to JPEG if not already and resizes if thumbnail
-/// * Uploads to S3 bucket
+/// * Uploads to storage
 /// * Saves in cache
-#[tracing::instrument(err, skip(conn, s3, s3_bucket, s3_url, data))]
+#[tracing::instrument(err, skip(conn, storage, data))]
 async fn upload_image(
     conn: &sqlx::Pool<sqlx::Postgres>,
-    s3: &rusoto_s3::S3Client,
-    s3_bucket: &str,
-    s3_url: &str,
+    storage: &dyn Storage,
     url: &str,
     thumb: bool,
     data: &bytes::Bytes,
@@ -196,20 +210,9 @@ async fn upload_image(
 
     let key = format!("{}/{}/{}_{}.jpg", &hash[0..2], &hash[2..4], name, &hash);
 
-    let put = rusoto_s3::PutObjectRequest {
-        acl: Some("public-read".into()),
-        bucket: s3_bucket.to_string(),
-        content_type: Some("image/jpeg".into()),
-        key: key.clone(),
-        body: Some(buf.to_vec().into()),
-        content_length: Some(buf.len() as i64),
-        ..Default::default()
-    };
-
-    use rusoto_s3::S3;
-    s3.put_object(put).await.unwrap();
+    storage.put(&key, "image/jpeg", buf).await?;
 
-    let cdn_url = format!("{}/{}/{}", s3_url, s3_bucket, key);
+    let cdn_url = storage.public_url(&key);
 
     if let Err(err) = CachedPost::save(conn, url, &cdn_url, thumb, dimensions).await {
         sentry::integrations::anyhow::capture_anyhow(&err);
@@ -251,25 +254,19 @@ pub async fn size_post(post: &PostInfo, data: &bytes::Bytes) -> anyhow::Result<P
 }
 
 /// Download URL from post, calculate image dimensions, convert to JPEG and
-/// generate thumbnail, and upload to S3 bucket. Returns a new PostInfo with
+/// generate thumbnail, and upload to storage. Returns a new PostInfo with
 /// the updated URLs and dimensions.
-#[tracing::instrument(err, skip(conn, s3, s3_bucket, s3_url, data, post), fields(post_url = %post.url))]
+#[tracing::instrument(err, skip(conn, storage, data, post), fields(post_url = %post.url))]
 pub async fn cache_post(
     conn: &sqlx::Pool<sqlx::Postgres>,
-    s3: &rusoto_s3::S3Client,
-    s3_bucket: &str,
-    s3_url: &str,
+    storage: &dyn Storage,
     post: &PostInfo,
     data: &bytes::Bytes,
 ) -> anyhow::Result<PostInfo> {
-    let image = upload_image(conn, s3, s3_bucket, s3_url, &post.url, false, data).await?;
+    let image = upload_image(conn, storage, &post.url, false, data).await?;
 
     let thumb = if let Some(thumb) = &post.thumb {
-        Some(
-            upload_image(conn, s3, s3_bucket, s3_url, thumb, true, data)
-                .await?
-                .url,
-        )
+        Some(upload_image(conn, storage, thumb, true, data).await?.url)
     } else {
         None
     };
@@ -289,6 +286,115 @@ pub fn find_best_photo(sizes: &[tgbotapi::PhotoSize]) -> Option<&tgbotapi::Photo
     sizes.iter().max_by_key(|size| size.height * size.width)
 }
 
+/// A file from a message that can be hashed to find its source.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HashableFile<'a> {
+    pub file_id: &'a str,
+    pub file_unique_id: &'a str,
+    pub file_size: Option<u64>,
+    /// If the file is a video that needs a frame extracted before it can be
+    /// hashed, instead of an image.
+    pub is_video: bool,
+}
+
+impl<'a> HashableFile<'a> {
+    fn image(file: &'a tgbotapi::PhotoSize) -> Self {
+        Self {
+            file_id: &file.file_id,
+            file_unique_id: &file.file_unique_id,
+            file_size: file.file_size.map(|size| size as u64),
+            is_video: false,
+        }
+    }
+
+    /// Use the thumbnail as a representative frame of a video, if it has
+    /// one, otherwise the video itself.
+    fn video(
+        thumb: Option<&'a tgbotapi::PhotoSize>,
+        file_id: &'a str,
+        file_unique_id: &'a str,
+        file_size: Option<u64>,
+    ) -> Self {
+        match thumb {
+            Some(thumb) => Self::image(thumb),
+            None => Self {
+                file_id,
+                file_unique_id,
+                file_size,
+                is_video: true,
+            },
+        }
+    }
+}
+
+/// Find the best file to hash from a message's photo, animation, video, or
+/// image or video document.
+pub fn hashable_file(message: &tgbotapi::Message) -> Option<HashableFile<'_>> {
+    if let Some(sizes) = &message.photo {
+        return find_best_photo(sizes).map(HashableFile::image);
+    }
+
+    if let Some(animation) = &message.animation {
+        return Some(HashableFile::video(
+            animation.thumb.as_ref(),
+            &animation.file_id,
+            &animation.file_unique_id,
+            animation.file_size.map(|size| size as u64),
+        ));
+    }
+
+    if let Some(video) = &message.video {
+        return Some(HashableFile::video(
+            video.thumb.as_ref(),
+            &video.file_id,
+            &video.file_unique_id,
+            video.file_size.map(|size| size as u64),
+        ));
+    }
+
+    let document = message.document.as_ref()?;
+    let mime_type = document.mime_type.as_deref().unwrap_or_default();
+    let file_size = document.file_size.map(|size| size as u64);
+
+    if hashable_document(mime_type)? {
+        Some(HashableFile::video(
+            document.thumb.as_ref(),
+            &document.file_id,
+            &document.file_unique_id,
+            file_size,
+        ))
+    } else {
+        Some(HashableFile {
+            file_id: &document.file_id,
+            file_unique_id: &document.file_unique_id,
+            file_size,
+            is_video: false,
+        })
+    }
+}
+
+/// Image formats that can be decoded for hashing.
+const HASHABLE_IMAGE_TYPES: &[&str] = &[
+    "image/jpeg",
+    "image/png",
+    "image/gif",
+    "image/webp",
+    "image/bmp",
+    "image/tiff",
+];
+
+/// Check if a document with a MIME type can be hashed, returning if it is a
+/// video that needs a frame extracted first.
+fn hashable_document(mime_type: &str) -> Option<bool> {
+    if HASHABLE_IMAGE_TYPES.contains(&mime_type) {
+        Some(false)
+    } else if mime_type.starts_with("video/") {
+        Some(true)
+    } else {
+        None
+    }
+}
+
 /// Get a message from the bundle with a language code, if provided.
 pub fn get_message(
     bundle: Bundle,
@@ -512,21 +618,31 @@ impl Drop for ContinuousAction {
 pub async fn match_image(
     bot: &tgbotapi::Telegram,
     redis: &redis::aio::ConnectionManager,
-    fapi: &fuzzysearch::FuzzySearch,
+    fapi: &dyn ReverseSearch,
     file: &tgbotapi::PhotoSize,
     distance: Option<i64>,
 ) -> anyhow::Result<(i64, Vec<fuzzysearch::File>)> {
-    if let Some(hash) = FileCache::get(redis, &file.file_unique_id)
+    let hash = hash_image(bot, redis, file).await?;
+
+    lookup_single_hash(fapi, hash, distance)
         .await
-        .context("unable to query file cache")?
-    {
-        return lookup_single_hash(fapi, hash, distance)
-            .await
-            .map(|files| (hash, files));
-    }
+        .map(|files| (hash, files))
+}
+
+/// Get the hash of an image, from the file cache if it was already hashed or
+/// by downloading and hashing it.
+pub async fn hash_image(
+    bot: &tgbotapi::Telegram,
+    redis: &redis::aio::ConnectionManager,
+    file: &tgbotapi::PhotoSize,
+) -> anyhow::Result<i64> {
+    hash_file(bot, redis, &file.file_id, &file.file_unique_id).await
+}
 
+/// Download a file from Telegram.
+pub async fn download_file(bot: &tgbotapi::Telegram, file_id: &str) -> anyhow::Result<Vec<u8>> {
     let get_file = tgbotapi::requests::GetFile {
-        file_id: file.file_id.clone(),
+        file_id: file_id.to_string(),
     };
 
     let file_info = bot
@@ -538,25 +654,44 @@ pub async fn match_image(
         .await
         .context("unable to download file from telegram")?;
 
+    Ok(data)
+}
+
+/// Get the hash of an image file, such as a document, from the file cache if
+/// it was already hashed or by downloading and hashing it.
+#[tracing::instrument(err, skip(bot, redis))]
+pub async fn hash_file(
+    bot: &tgbotapi::Telegram,
+    redis: &redis::aio::ConnectionManager,
+    file_id: &str,
+    file_unique_id: &str,
+) -> anyhow::Result<i64> {
+    if let Some(hash) = FileCache::get(redis, file_unique_id)
+        .await
+        .context("unable to query file cache")?
+    {
+        return Ok(hash);
+    }
+
+    let data = download_file(bot, file_id).await?;
+
     let hash = tokio::task::spawn_blocking(move || fuzzysearch::hash_bytes(&data))
         .instrument(tracing::debug_span!("hash_bytes"))
         .await
         .context("unable to spawn blocking")?
         .context("unable to hash bytes")?;
 
-    FileCache::set(redis, &file.file_unique_id, hash)
+    FileCache::set(redis, file_unique_id, hash)
         .await
         .context("unable to set file cache")?;
 
-    lookup_single_hash(fapi, hash, distance)
-        .await
-        .map(|files| (hash, files))
+    Ok(hash)
 }
 
 /// Lookup a single hash from FuzzySearch, ensuring that the distance has been
 /// calculated from the provided hash.
 pub async fn lookup_single_hash(
-    fapi: &fuzzysearch::FuzzySearch,
+    fapi: &dyn ReverseSearch,
     hash: i64,
     distance: Option<i64>,
 ) -> anyhow::Result<Vec<fuzzysearch::File>> {
@@ -615,6 +750,45 @@ pub async fn sort_results(
     Ok(())
 }
 
+/// Get the site order a group's admins have chosen, skipping any sites that
+/// are no longer known.
+pub async fn group_site_order(
+    conn: &sqlx::Pool<sqlx::Postgres>,
+    chat_id: i64,
+) -> anyhow::Result<Option<Vec<Sites>>> {
+    // The order is stored as null when it was reset.
+    let row: Option<Option<Vec<String>>> =
+        GroupConfig::get(conn, chat_id, GroupConfigKey::SiteSortOrder)
+            .await
+            .context("unable to get group site sort order")?;
+
+    Ok(row
+        .flatten()
+        .map(|row| row.iter().filter_map(|item| item.parse().ok()).collect()))
+}
+
+/// Sort match results for an image in a group based on the group's
+/// preferences, or the preferences of the user who sent it if the group has
+/// none.
+pub async fn sort_group_results(
+    conn: &sqlx::Pool<sqlx::Postgres>,
+    chat_id: i64,
+    user_id: i64,
+    results: &mut Vec<fuzzysearch::File>,
+) -> anyhow::Result<()> {
+    if results.len() <= 1 {
+        return Ok(());
+    }
+
+    match group_site_order(conn, chat_id).await? {
+        Some(sites) => {
+            sort_results_by(&sites, results, false);
+            Ok(())
+        }
+        None => sort_results(conn, user_id, results).await,
+    }
+}
+
 /// Sort match results with a given order.
 ///
 /// This expects that undesired results have already been filtered.
@@ -907,59 +1081,161 @@ pub fn get_rating_bundle_name(rating: &Option<fuzzysearch::Rating>) -> Option<&'
     }
 }
 
-/// Write a reply for matched sources.
-pub fn source_reply(matches: &[fuzzysearch::File], bundle: Bundle<'_>) -> String {
+/// Get the name of the localization describing how confident we are in a
+/// match, based on its distance from the searched image.
+pub fn get_confidence_bundle_name(distance: Option<u64>) -> &'static str {
+    match distance {
+        Some(0) => "confidence-exact",
+        Some(1..=3) => "confidence-high",
+        Some(4..=7) => "confidence-medium",
+        _ => "confidence-low",
+    }
+}
+
+/// Build the arguments describing a match: its link, the site name with the
+/// artist if known, the confidence label, and the rating if known.
+///
+/// Returns if a rating was added, as messages have separate variants for
+/// matches without a known rating.
+pub fn match_args(bundle: Bundle, file: &fuzzysearch::File) -> (fluent::FluentArgs<'static>, bool) {
+    let mut args = fluent::FluentArgs::new();
+    args.insert("link", file.url().into());
+
+    let site = match file.artists.as_deref() {
+        Some(artists) if !artists.is_empty() => {
+            let mut site_args = fluent::FluentArgs::new();
+            site_args.insert("site", file.site_name().into());
+            site_args.insert("artist", artists.join(", ").into());
+            get_message(bundle, "source-site-artist", Some(site_args)).unwrap()
+        }
+        _ => file.site_name().to_string(),
+    };
+    args.insert("site", site.into());
+
+    let confidence = get_message(bundle, get_confidence_bundle_name(file.distance), None).unwrap();
+    args.insert("confidence", confidence.into());
+
+    match get_rating_bundle_name(&file.rating) {
+        Some(rating) => {
+            let rating = get_message(bundle, rating, None).unwrap();
+            args.insert("rating", rating.into());
+            (args, true)
+        }
+        None => (args, false),
+    }
+}
+
+/// Get the matches that should be displayed in a source reply, the first
+/// match and any other matches with the same distance.
+fn best_matches(matches: &[fuzzysearch::File]) -> Vec<&fuzzysearch::File> {
     let first = match matches.first() {
         Some(result) => result,
-        None => return get_message(bundle, "reverse-no-results", None).unwrap(),
+        None => return vec![],
     };
 
-    let similar: Vec<&fuzzysearch::File> = matches
-        .iter()
-        .skip(1)
-        .take_while(|m| m.distance.unwrap() == first.distance.unwrap())
-        .collect();
     tracing::debug!(
         distance = first.distance.unwrap(),
         "discovered match distance"
     );
 
-    if similar.is_empty() {
-        let mut args = fluent::FluentArgs::new();
-        args.insert("link", first.url().into());
+    std::iter::once(first)
+        .chain(
+            matches
+                .iter()
+                .skip(1)
+                .take_while(|m| m.distance.unwrap() == first.distance.unwrap()),
+        )
+        .collect()
+}
 
-        if let Some(rating) = get_rating_bundle_name(&first.rating) {
-            let rating = get_message(bundle, rating, None).unwrap();
-            args.insert("rating", rating.into());
+/// Write a reply for matched sources.
+pub fn source_reply(matches: &[fuzzysearch::File], bundle: Bundle<'_>) -> String {
+    let best = best_matches(matches);
 
-            get_message(bundle, "reverse-result", Some(args)).unwrap()
-        } else {
-            get_message(bundle, "reverse-result-unknown", Some(args)).unwrap()
+    match best.as_slice() {
+        [] => get_message(bundle, "reverse-no-results", None).unwrap(),
+        [first] => {
+            let (args, has_rating) = match_args(bundle, first);
+
+            if has_rating {
+                get_message(bundle, "reverse-result", Some(args)).unwrap()
+            } else {
+                get_message(bundle, "reverse-result-unknown", Some(args)).unwrap()
+            }
         }
-    } else {
-        let mut items = Vec::with_capacity(2 + similar.len());
+        best => {
+            let mut items = Vec::with_capacity(1 + best.len());
 
-        let text = get_message(bundle, "reverse-multiple-results", None).unwrap();
-        items.push(text);
+            let text = get_message(bundle, "reverse-multiple-results", None).unwrap();
+            items.push(text);
 
-        for file in vec![first].into_iter().chain(similar) {
-            let mut args = fluent::FluentArgs::new();
-            args.insert("link", file.url().into());
+            for file in best {
+                let (args, has_rating) = match_args(bundle, file);
 
-            let result = if let Some(rating) = get_rating_bundle_name(&file.rating) {
-                let rating = get_message(bundle, rating, None).unwrap();
-                args.insert("rating", rating.into());
+                let result = if has_rating {
+                    get_message(bundle, "reverse-multiple-item", Some(args)).unwrap()
+                } else {
+                    get_message(bundle, "reverse-multiple-item-unknown", Some(args)).unwrap()
+                };
+
+                items.push(result);
+            }
+
+            items.join("\n")
+        }
+    }
+}
+
+/// Build inline keyboard buttons linking to each match, for use in place of
+/// listing links in the message text.
+pub fn source_buttons(
+    matches: &[&fuzzysearch::File],
+    bundle: Bundle<'_>,
+) -> Vec<Vec<tgbotapi::InlineKeyboardButton>> {
+    matches
+        .iter()
+        .map(|file| {
+            let (args, has_rating) = match_args(bundle, file);
 
-                get_message(bundle, "reverse-multiple-item", Some(args)).unwrap()
+            let text = if has_rating {
+                get_message(bundle, "source-button", Some(args)).unwrap()
             } else {
-                get_message(bundle, "reverse-multiple-item-unknown", Some(args)).unwrap()
+                get_message(bundle, "source-button-unknown", Some(args)).unwrap()
             };
 
-            items.push(result);
-        }
+            vec![tgbotapi::InlineKeyboardButton {
+                text,
+                url: Some(file.url()),
+                ..Default::default()
+            }]
+        })
+        .collect()
+}
 
-        items.join("\n")
+/// Write a compact reply for matched sources, where the links are placed on
+/// inline keyboard buttons.
+pub fn source_reply_compact(
+    matches: &[fuzzysearch::File],
+    bundle: Bundle<'_>,
+) -> (String, Option<tgbotapi::InlineKeyboardMarkup>) {
+    let best = best_matches(matches);
+
+    if best.is_empty() {
+        return (
+            get_message(bundle, "reverse-no-results", None).unwrap(),
+            None,
+        );
     }
+
+    let mut args = fluent::FluentArgs::new();
+    args.insert("count", best.len().into());
+    let text = get_message(bundle, "reverse-compact", Some(args)).unwrap();
+
+    let markup = tgbotapi::InlineKeyboardMarkup {
+        inline_keyboard: source_buttons(&best, bundle),
+    };
+
+    (text, Some(markup))
 }
 
 /// A wrapper around checking the size of a file at a given URL.
@@ -1103,6 +1379,45 @@ pub async fn resize_photo(url: &str, max_size: u64) -> anyhow::Result<tgbotapi::
     Ok(FileType::Bytes(format!("{}.jpg", generate_id()), bytes))
 }
 
+/// Largest file a bot can upload to Telegram.
+pub const MAX_DOCUMENT_SIZE: usize = 50_000_000;
+
+/// Download the untouched file at a URL so it can be sent as a document,
+/// failing if it is larger than Telegram allows bots to upload.
+#[tracing::instrument]
+pub async fn download_original(url: &str, file_type: &str) -> anyhow::Result<tgbotapi::FileType> {
+    let data = CheckFileSize::new(url, MAX_DOCUMENT_SIZE)
+        .into_bytes()
+        .await?;
+
+    Ok(FileType::Bytes(
+        original_file_name(url, file_type),
+        data.to_vec(),
+    ))
+}
+
+/// Get a file name from the last path segment of a URL, using a random name
+/// with the given extension if the URL does not have a usable name.
+pub fn original_file_name(url: &str, file_type: &str) -> String {
+    let name = url
+        .split(|c| c == '?' || c == '#')
+        .next()
+        .and_then(|path| path.rsplit('/').next())
+        .unwrap_or_default();
+
+    let is_usable = name.contains('.')
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '_' || c == '-');
+
+    if is_usable {
+        name.to_string()
+    } else {
+        format!("{}.{}", generate_id(), file_type)
+    }
+}
+
 /// Known resources for each language.
 pub static L10N_RESOURCES: &[&str] = &["foxbot.ftl"];
 /// Known languages.
@@ -1316,6 +1631,79 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_hashable_file() {
+        use super::{hashable_file, HashableFile};
+
+        let message = tgbotapi::Message {
+            photo: Some(vec![
+                tgbotapi::PhotoSize {
+                    file_id: "small".to_string(),
+                    file_unique_id: "small-unique".to_string(),
+                    width: 90,
+                    height: 90,
+                    ..Default::default()
+                },
+                tgbotapi::PhotoSize {
+                    file_id: "large".to_string(),
+                    file_unique_id: "large-unique".to_string(),
+                    width: 1280,
+                    height: 1280,
+                    file_size: Some(1000),
+                },
+            ]),
+            ..Default::default()
+        };
+        assert_eq!(
+            hashable_file(&message),
+            Some(HashableFile {
+                file_id: "large",
+                file_unique_id: "large-unique",
+                file_size: Some(1000),
+                is_video: false,
+            })
+        );
+
+        let message = tgbotapi::Message {
+            text: Some("no media".into()),
+            ..Default::default()
+        };
+        assert_eq!(hashable_file(&message), None);
+    }
+
+    #[test]
+    fn test_hashable_document() {
+        use super::hashable_document;
+
+        assert_eq!(hashable_document("image/jpeg"), Some(false));
+        assert_eq!(hashable_document("image/webp"), Some(false));
+        assert_eq!(hashable_document("video/mp4"), Some(true));
+        assert_eq!(hashable_document("image/svg+xml"), None);
+        assert_eq!(hashable_document("image/heic"), None);
+        assert_eq!(hashable_document("application/pdf"), None);
+        assert_eq!(hashable_document(""), None);
+    }
+
+    #[test]
+    fn test_original_file_name() {
+        use super::original_file_name;
+
+        assert_eq!(
+            original_file_name("https://d.furaffinity.net/art/a/1/1.a_b.png", "png"),
+            "1.a_b.png"
+        );
+        assert_eq!(
+            original_file_name("https://example.com/image.jpg?size=large", "jpg"),
+            "image.jpg"
+        );
+
+        let name = original_file_name("https://example.com/media/12345", "jpg");
+        assert!(name.ends_with(".jpg") && name.len() == 28);
+
+        let name = original_file_name("https://example.com/a%20b.png", "png");
+        assert_ne!(name, "a%20b.png", "unusual characters should not be used");
+    }
+
     #[tokio::test]
     async fn test_link_was_seen() {
         let finder = get_finder();
@@ -1407,4 +1795,59 @@ mod tests {
         sort_results_by(&order, &mut results, true);
         assert!(matches_are_sorted(&results));
     }
+
+    #[test]
+    fn test_source_reply() {
+        use super::{get_lang_bundle, source_reply, source_reply_compact};
+
+        let mut langs = std::collections::HashMap::new();
+        langs.insert(
+            "en-US".parse().unwrap(),
+            vec![include_str!("../../langs/en-US/foxbot.ftl").to_string()],
+        );
+        let bundle = get_lang_bundle(&langs, "en-US");
+
+        let matches = vec![
+            fuzzysearch::File {
+                site_id: 1,
+                distance: Some(0),
+                artists: Some(vec!["artist".to_string()]),
+                rating: Some(fuzzysearch::Rating::General),
+                site_info: Some(fuzzysearch::SiteInfo::FurAffinity(
+                    fuzzysearch::FurAffinityFile { file_id: 123 },
+                )),
+                ..Default::default()
+            },
+            fuzzysearch::File {
+                site_id: 2,
+                distance: Some(3),
+                site_info: Some(fuzzysearch::SiteInfo::Twitter),
+                ..Default::default()
+            },
+        ];
+
+        let text = source_reply(&matches, &bundle);
+        assert!(text.contains("furaffinity.net/view/1/"));
+        assert!(text.contains("artist"), "artist should be displayed");
+        assert!(
+            text.contains("exact match"),
+            "confidence should be displayed"
+        );
+        assert!(
+            !text.contains("twitter.com"),
+            "matches with greater distance should be excluded"
+        );
+
+        let (text, markup) = source_reply_compact(&matches, &bundle);
+        assert!(
+            !text.contains("furaffinity.net"),
+            "links should be on buttons"
+        );
+        let markup = markup.expect("compact reply should have buttons");
+        assert_eq!(markup.inline_keyboard.len(), 1);
+        assert_eq!(
+            markup.inline_keyboard[0][0].url.as_deref(),
+            Some("https://www.furaffinity.net/view/1/")
+        );
+    }
 }
@@ -0,0 +1,194 @@
+use anyhow::Context;
+use tgbotapi::{requests::*, *};
+
+use foxbot_models::{GroupConfig, GroupConfigKey};
+use foxbot_sites::{BoxedSite, PostInfo};
+
+use crate::{find_images, get_message, Bundle};
+
+/// Largest photo, in bytes, that will be sent when mirroring.
+const MAX_MIRROR_PHOTO_SIZE: u64 = 5_000_000;
+
+/// Site loaders that are only used for automatic mirroring when a group has
+/// explicitly allowed them.
+const AUTO_MIRROR_EXCLUDED: &[&str] = &["direct link"];
+
+/// Images found for links that should be mirrored.
+pub struct MirrorImages<'a> {
+    /// Images that were found, without sequential duplicates.
+    pub results: Vec<PostInfo>,
+    /// Links that appeared to contain no images.
+    pub missing: Vec<&'a str>,
+}
+
+/// Find the images to mirror from the given links.
+pub async fn find_mirror_images<'a>(
+    user: &User,
+    links: Vec<&'a str>,
+    sites: &mut [BoxedSite],
+) -> anyhow::Result<MirrorImages<'a>> {
+    let mut results: Vec<PostInfo> = Vec::with_capacity(links.len());
+
+    let missing = find_images(user, links, sites, &mut |info| {
+        results.extend(info.results);
+    })
+    .await?;
+
+    // This will only remove duplicate items if they are sequential. This
+    // will likely fix the most common issue of having a direct and source
+    // link next to each other.
+    results.dedup_by(|a, b| a.source_link == b.source_link && a.url == b.url);
+
+    Ok(MirrorImages { results, missing })
+}
+
+/// A request needed to send mirrored images to a chat.
+pub enum MirrorRequest {
+    Photo(SendPhoto),
+    Video(SendVideo),
+    MediaGroup(SendMediaGroup),
+}
+
+/// Build the requests to send mirrored images as a reply to a message.
+///
+/// A single image is sent by itself, otherwise images are sent as albums.
+/// Links for images that could not be prepared are added to missing.
+pub async fn mirror_requests<'a>(
+    chat_id: ChatID,
+    reply_to_message_id: i32,
+    results: &'a [PostInfo],
+    missing: &mut Vec<&'a str>,
+) -> Vec<MirrorRequest> {
+    if let [result] = results {
+        if result.file_type == "mp4" {
+            return vec![MirrorRequest::Video(SendVideo {
+                chat_id,
+                caption: result.source_link.clone(),
+                video: FileType::Url(result.url.clone()),
+                reply_to_message_id: Some(reply_to_message_id),
+                ..Default::default()
+            })];
+        }
+
+        return match crate::resize_photo(&result.url, MAX_MIRROR_PHOTO_SIZE).await {
+            Ok(file_type) => vec![MirrorRequest::Photo(SendPhoto {
+                chat_id,
+                caption: result.source_link.clone(),
+                photo: file_type,
+                reply_to_message_id: Some(reply_to_message_id),
+                ..Default::default()
+            })],
+            Err(_) => {
+                missing.push(result.source_link.as_deref().unwrap_or(&result.url));
+                vec![]
+            }
+        };
+    }
+
+    let mut requests = Vec::with_capacity(results.len() / 10 + 1);
+
+    for chunk in results.chunks(10) {
+        let mut media = Vec::with_capacity(chunk.len());
+
+        for result in chunk {
+            let input = match result.file_type.as_ref() {
+                "mp4" => InputMedia::Video(InputMediaVideo {
+                    media: FileType::Url(result.url.to_owned()),
+                    caption: result.source_link.clone(),
+                    ..Default::default()
+                }),
+                _ => {
+                    if let Ok(file_type) =
+                        crate::resize_photo(&result.url, MAX_MIRROR_PHOTO_SIZE).await
+                    {
+                        InputMedia::Photo(InputMediaPhoto {
+                            media: file_type,
+                            caption: result.source_link.clone(),
+                            ..Default::default()
+                        })
+                    } else {
+                        missing.push(result.source_link.as_deref().unwrap_or(&result.url));
+                        continue;
+                    }
+                }
+            };
+
+            media.push(input);
+        }
+
+        if media.is_empty() {
+            continue;
+        }
+
+        requests.push(MirrorRequest::MediaGroup(SendMediaGroup {
+            chat_id: chat_id.clone(),
+            reply_to_message_id: Some(reply_to_message_id),
+            media,
+            ..Default::default()
+        }));
+    }
+
+    requests
+}
+
+/// Build the message listing links where images could not be found.
+pub fn mirror_missing_message(bundle: Bundle, missing: &[&str]) -> String {
+    let links: Vec<String> = missing.iter().map(|item| format!("· {}", item)).collect();
+    let mut args = fluent::FluentArgs::new();
+    args.insert("links", fluent::FluentValue::from(links.join("\n")));
+
+    get_message(bundle, "mirror-missing", Some(args)).unwrap()
+}
+
+/// Get the names of the site loaders a group allows for automatic mirroring.
+pub async fn auto_mirror_sites(
+    conn: &sqlx::Pool<sqlx::Postgres>,
+    chat_id: i64,
+    sites: &[BoxedSite],
+) -> anyhow::Result<Vec<String>> {
+    let allowed: Option<Vec<String>> =
+        GroupConfig::get(conn, chat_id, GroupConfigKey::AutoMirrorSites)
+            .await
+            .context("unable to get group auto mirror sites")?;
+
+    Ok(allowed.unwrap_or_else(|| {
+        sites
+            .iter()
+            .map(|site| site.name())
+            .filter(|name| !AUTO_MIRROR_EXCLUDED.contains(name))
+            .map(String::from)
+            .collect()
+    }))
+}
+
+/// Keep only links where the first site loader supporting them is allowed.
+pub async fn allowed_mirror_links<'a>(
+    links: Vec<&'a str>,
+    sites: &mut [BoxedSite],
+    allowed: &[String],
+) -> Vec<&'a str> {
+    let mut wanted = Vec::with_capacity(links.len());
+
+    'link: for link in links {
+        for site in sites.iter_mut() {
+            if site.url_supported(link).await {
+                if allowed.iter().any(|name| name == site.name()) {
+                    wanted.push(link);
+                }
+
+                continue 'link;
+            }
+        }
+    }
+
+    wanted
+}
+
+/// If a message already contains media, in which case it shouldn't be
+/// automatically mirrored.
+pub fn has_media(message: &Message) -> bool {
+    message.photo.is_some()
+        || message.video.is_some()
+        || message.animation.is_some()
+        || message.document.is_some()
+}
@@ -0,0 +1,108 @@
+use anyhow::Context;
+
+use foxbot_models::{GroupConfig, GroupConfigKey};
+
+/// Default maximum hash distance for an image to be considered a repost.
+pub const DEFAULT_REPOST_DISTANCE: u64 = 2;
+/// Default number of hours an image is remembered for repost detection.
+pub const DEFAULT_REPOST_WINDOW_HOURS: u32 = 24 * 7;
+
+/// Repost distances a group may choose from.
+pub const REPOST_DISTANCE_CHOICES: &[u64] = &[0, 1, 2, 3, 5];
+/// Hours a group may choose to remember images for.
+pub const REPOST_WINDOW_CHOICES: &[u32] = &[24, 24 * 3, 24 * 7, 24 * 30];
+
+/// How a group wants reposted images to be detected.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RepostPolicy {
+    /// If repost detection is enabled.
+    pub enabled: bool,
+    /// Maximum hash distance for an image to be a repost.
+    pub max_distance: u64,
+    /// Number of hours an earlier image counts as a repost.
+    pub window_hours: u32,
+}
+
+impl Default for RepostPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_distance: DEFAULT_REPOST_DISTANCE,
+            window_hours: DEFAULT_REPOST_WINDOW_HOURS,
+        }
+    }
+}
+
+impl RepostPolicy {
+    /// Load a group's policy, using defaults for anything not configured.
+    pub async fn load(conn: &sqlx::Pool<sqlx::Postgres>, chat_id: i64) -> anyhow::Result<Self> {
+        let defaults = Self::default();
+
+        let enabled = GroupConfig::get(conn, chat_id, GroupConfigKey::RepostDetection)
+            .await
+            .context("unable to get group repost detection")?
+            .unwrap_or(defaults.enabled);
+        let max_distance = GroupConfig::get(conn, chat_id, GroupConfigKey::RepostDistance)
+            .await
+            .context("unable to get group repost distance")?
+            .unwrap_or(defaults.max_distance);
+        let window_hours = GroupConfig::get(conn, chat_id, GroupConfigKey::RepostWindowHours)
+            .await
+            .context("unable to get group repost window")?
+            .unwrap_or(defaults.window_hours);
+
+        Ok(Self {
+            enabled,
+            max_distance,
+            window_hours,
+        })
+    }
+}
+
+/// Get a link to a message in a chat, if the chat allows linking to messages.
+///
+/// Public chats are linked by username and private supergroups by their ID,
+/// which members are able to open. Basic groups have no message links.
+pub fn message_link(chat: &tgbotapi::Chat, message_id: i32) -> Option<String> {
+    if let Some(username) = &chat.username {
+        return Some(format!("https://t.me/{}/{}", username, message_id));
+    }
+
+    chat.id
+        .to_string()
+        .strip_prefix("-100")
+        .map(|id| format!("https://t.me/c/{}/{}", id, message_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_link() {
+        let chat = tgbotapi::Chat {
+            id: -1001234567890,
+            username: Some("foxbot".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            message_link(&chat, 42).as_deref(),
+            Some("https://t.me/foxbot/42")
+        );
+
+        let chat = tgbotapi::Chat {
+            id: -1001234567890,
+            ..Default::default()
+        };
+        assert_eq!(
+            message_link(&chat, 42).as_deref(),
+            Some("https://t.me/c/1234567890/42")
+        );
+
+        let chat = tgbotapi::Chat {
+            id: -1234567890,
+            ..Default::default()
+        };
+        assert_eq!(message_link(&chat, 42), None);
+    }
+}
@@ -0,0 +1,274 @@
+use anyhow::Context;
+
+use foxbot_models::{
+    GroupConfig, GroupConfigKey, GroupSourceDelivery, LinkedForwardRule, NoisySourceRule, Sites,
+};
+
+/// Default maximum hash distance for a match to be posted in a group.
+pub const DEFAULT_SOURCE_DISTANCE: u64 = 3;
+/// Default maximum number of links posted for a group image, where 0 means
+/// there is no limit.
+pub const DEFAULT_MAX_SOURCE_LINKS: usize = 0;
+/// Number of matches where an image is considered noisy.
+pub const NOISY_SOURCE_COUNT: usize = 4;
+
+/// Distances a group may choose from.
+pub const SOURCE_DISTANCE_CHOICES: &[u64] = &[0, 1, 2, 3, 5];
+/// Maximum numbers of links a group may choose from, where 0 means there is
+/// no limit.
+pub const MAX_SOURCE_LINKS_CHOICES: &[usize] = &[0, 1, 2, 3, 5, 10];
+/// Seconds after which source replies are deleted a group may choose from,
+/// where 0 means they are never deleted.
+pub const SOURCE_DELETE_AFTER_CHOICES: &[u64] = &[0, 60, 60 * 5, 60 * 60];
+/// Rules for linked channel forwards a group may choose from.
+pub const LINKED_FORWARD_CHOICES: &[LinkedForwardRule] = &[
+    LinkedForwardRule::Wait { hours: 1 },
+    LinkedForwardRule::Wait { hours: 6 },
+    LinkedForwardRule::Wait { hours: 24 },
+    LinkedForwardRule::Skip,
+    LinkedForwardRule::Source,
+];
+
+/// How a group wants matches for its images to be chosen and delivered.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GroupSourcePolicy {
+    /// Maximum hash distance for a match to be used.
+    pub max_distance: u64,
+    /// Maximum number of links to post, or 0 for no limit.
+    pub max_links: usize,
+    /// When matches should be considered noise.
+    pub noisy_rule: NoisySourceRule,
+    /// Sites matches may come from.
+    pub sites: Vec<Sites>,
+    /// How sources are sent to the group.
+    pub delivery: GroupSourceDelivery,
+    /// Seconds after which source replies are deleted, or 0 to keep them.
+    pub delete_after: u64,
+    /// How images forwarded from a linked channel are handled.
+    pub linked_forwards: LinkedForwardRule,
+    /// If sources added to linked channel posts should also be replied to
+    /// the forwarded messages.
+    pub linked_replies: bool,
+}
+
+impl Default for GroupSourcePolicy {
+    fn default() -> Self {
+        Self {
+            max_distance: DEFAULT_SOURCE_DISTANCE,
+            max_links: DEFAULT_MAX_SOURCE_LINKS,
+            noisy_rule: NoisySourceRule::default(),
+            sites: Sites::default_order(),
+            delivery: GroupSourceDelivery::default(),
+            delete_after: 0,
+            linked_forwards: LinkedForwardRule::default(),
+            linked_replies: false,
+        }
+    }
+}
+
+impl GroupSourcePolicy {
+    /// Load a group's policy, using defaults for anything not configured.
+    pub async fn load(conn: &sqlx::Pool<sqlx::Postgres>, chat_id: i64) -> anyhow::Result<Self> {
+        let defaults = Self::default();
+
+        let max_distance = GroupConfig::get(conn, chat_id, GroupConfigKey::SourceDistance)
+            .await
+            .context("unable to get group source distance")?
+            .unwrap_or(defaults.max_distance);
+        let max_links = GroupConfig::get(conn, chat_id, GroupConfigKey::MaxSourceLinks)
+            .await
+            .context("unable to get group max source links")?
+            .unwrap_or(defaults.max_links);
+        let noisy_rule = GroupConfig::get(conn, chat_id, GroupConfigKey::NoisySourceRule)
+            .await
+            .context("unable to get group noisy source rule")?
+            .unwrap_or(defaults.noisy_rule);
+
+        let sites: Option<Vec<String>> =
+            GroupConfig::get(conn, chat_id, GroupConfigKey::AllowedSites)
+                .await
+                .context("unable to get group allowed sites")?;
+        let sites = match sites {
+            Some(sites) => sites.iter().filter_map(|site| site.parse().ok()).collect(),
+            None => defaults.sites,
+        };
+
+        // Groups that turned on compact sources before delivery modes existed
+        // should keep getting buttons.
+        let delivery = match GroupConfig::get(conn, chat_id, GroupConfigKey::SourceDelivery)
+            .await
+            .context("unable to get group source delivery")?
+        {
+            Some(delivery) => delivery,
+            None => {
+                let compact = GroupConfig::get(conn, chat_id, GroupConfigKey::GroupCompactSources)
+                    .await
+                    .context("unable to get group compact sources")?
+                    .unwrap_or(false);
+
+                if compact {
+                    GroupSourceDelivery::Buttons
+                } else {
+                    defaults.delivery
+                }
+            }
+        };
+        let delete_after = GroupConfig::get(conn, chat_id, GroupConfigKey::SourceDeleteAfter)
+            .await
+            .context("unable to get group source delete after")?
+            .unwrap_or(defaults.delete_after);
+        let linked_forwards =
+            GroupConfig::get(conn, chat_id, GroupConfigKey::LinkedChannelForwards)
+                .await
+                .context("unable to get group linked channel forwards")?
+                .unwrap_or(defaults.linked_forwards);
+        let linked_replies = GroupConfig::get(conn, chat_id, GroupConfigKey::LinkedChannelReplies)
+            .await
+            .context("unable to get group linked channel replies")?
+            .unwrap_or(defaults.linked_replies);
+
+        Ok(Self {
+            max_distance,
+            max_links,
+            noisy_rule,
+            sites,
+            delivery,
+            delete_after,
+            linked_forwards,
+            linked_replies,
+        })
+    }
+
+    /// If an image forwarded from a linked channel should be skipped, given
+    /// how many hours ago it was posted.
+    pub fn skip_linked_forward(&self, hours_ago: i64) -> bool {
+        match self.linked_forwards {
+            LinkedForwardRule::Skip => true,
+            LinkedForwardRule::Source => false,
+            LinkedForwardRule::Wait { hours } => hours_ago < hours as i64,
+        }
+    }
+
+    /// Number of links that may be posted for an image.
+    pub fn link_limit(&self) -> usize {
+        match self.max_links {
+            0 => usize::MAX,
+            max_links => max_links,
+        }
+    }
+
+    /// Distance to use when searching for matches.
+    pub fn search_distance(&self) -> Option<i64> {
+        Some(self.max_distance as i64)
+    }
+
+    /// Matches that are close enough and from allowed sites.
+    pub fn filter<'a>(&self, matches: &'a [fuzzysearch::File]) -> Vec<&'a fuzzysearch::File> {
+        matches
+            .iter()
+            .filter(|m| m.distance.unwrap_or(u64::MAX) <= self.max_distance)
+            .filter(|m| self.sites.iter().any(|site| site.as_str() == m.site_name()))
+            .collect()
+    }
+
+    /// If the matches for an image are too noisy to post.
+    pub fn is_noisy(&self, matches: &[&fuzzysearch::File]) -> bool {
+        match self.noisy_rule {
+            NoisySourceRule::Off => false,
+            NoisySourceRule::Twitter => {
+                let twitter_matches = matches
+                    .iter()
+                    .filter(|m| matches!(m.site_info, Some(fuzzysearch::SiteInfo::Twitter)))
+                    .count();
+                let other_matches = matches.len() - twitter_matches;
+
+                other_matches <= 1 && twitter_matches >= NOISY_SOURCE_COUNT
+            }
+            NoisySourceRule::Any => matches.len() >= NOISY_SOURCE_COUNT,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(distance: u64, site_info: fuzzysearch::SiteInfo) -> fuzzysearch::File {
+        fuzzysearch::File {
+            distance: Some(distance),
+            site_info: Some(site_info),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_filter() {
+        let matches = vec![
+            file(0, fuzzysearch::SiteInfo::Twitter),
+            file(2, fuzzysearch::SiteInfo::Weasyl),
+            file(5, fuzzysearch::SiteInfo::Weasyl),
+        ];
+
+        let policy = GroupSourcePolicy::default();
+        assert_eq!(policy.filter(&matches).len(), 2);
+
+        let policy = GroupSourcePolicy {
+            max_distance: 5,
+            sites: vec![Sites::Weasyl],
+            ..Default::default()
+        };
+        let filtered = policy.filter(&matches);
+        assert_eq!(filtered.len(), 2);
+        assert!(filtered
+            .iter()
+            .all(|m| matches!(m.site_info, Some(fuzzysearch::SiteInfo::Weasyl))));
+    }
+
+    #[test]
+    fn test_skip_linked_forward() {
+        let mut policy = GroupSourcePolicy::default();
+        assert!(policy.skip_linked_forward(5));
+        assert!(!policy.skip_linked_forward(6));
+
+        policy.linked_forwards = LinkedForwardRule::Skip;
+        assert!(policy.skip_linked_forward(1000));
+
+        policy.linked_forwards = LinkedForwardRule::Source;
+        assert!(!policy.skip_linked_forward(0));
+    }
+
+    #[test]
+    fn test_is_noisy() {
+        let twitter: Vec<_> = (0..4)
+            .map(|_| file(0, fuzzysearch::SiteInfo::Twitter))
+            .collect();
+        let twitter: Vec<_> = twitter.iter().collect();
+
+        let mut policy = GroupSourcePolicy::default();
+        assert!(policy.is_noisy(&twitter));
+        assert!(!policy.is_noisy(&twitter[..3]));
+
+        policy.noisy_rule = NoisySourceRule::Off;
+        assert!(!policy.is_noisy(&twitter));
+
+        let weasyl: Vec<_> = (0..4)
+            .map(|_| file(0, fuzzysearch::SiteInfo::Weasyl))
+            .collect();
+        let weasyl: Vec<_> = weasyl.iter().collect();
+
+        policy.noisy_rule = NoisySourceRule::Twitter;
+        assert!(!policy.is_noisy(&weasyl));
+
+        policy.noisy_rule = NoisySourceRule::Any;
+        assert!(policy.is_noisy(&weasyl));
+    }
+
+    #[test]
+    fn test_link_limit() {
+        let mut policy = GroupSourcePolicy::default();
+        assert_eq!(policy.link_limit(), usize::MAX);
+
+        policy.max_links = 3;
+        assert_eq!(policy.link_limit(), 3);
+    }
+}
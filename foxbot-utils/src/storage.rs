@@ -0,0 +1,613 @@
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use async_trait::async_trait;
+use rusoto_s3::S3;
+
+/// A thread-safe and shared Storage.
+pub type BoxedStorage = Arc<dyn Storage>;
+
+/// Object storage for files that must be publicly accessible, such as cached
+/// images and transcoded videos.
+#[async_trait]
+pub trait Storage: Send + Sync {
+    /// Store data at a key, replacing any existing object.
+    async fn put(&self, key: &str, content_type: &str, data: bytes::Bytes) -> anyhow::Result<()>;
+
+    /// Store data that is only meant to be downloaded, such as media group
+    /// images. Backends without separate access controls store it normally.
+    async fn put_download(
+        &self,
+        key: &str,
+        content_type: &str,
+        data: bytes::Bytes,
+    ) -> anyhow::Result<()> {
+        self.put(key, content_type, data).await
+    }
+
+    /// Delete the object at a key. Deleting a missing object is not an error.
+    async fn delete(&self, key: &str) -> anyhow::Result<()>;
+
+    /// Check if an object exists at a key.
+    async fn exists(&self, key: &str) -> anyhow::Result<bool>;
+
+    /// Get the public URL for a key.
+    fn public_url(&self, key: &str) -> String;
+}
+
+/// Storage configuration, loaded separately from the rest of the config so
+/// each service can share it.
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct StorageConfig {
+    /// Which backend to use, one of `s3` (default), `b2`, or `local`.
+    pub storage_backend: Option<String>,
+
+    pub s3_endpoint: Option<String>,
+    pub s3_region: Option<String>,
+    pub s3_token: Option<String>,
+    pub s3_secret: Option<String>,
+    pub s3_bucket: Option<String>,
+    pub s3_url: Option<String>,
+
+    pub b2_account_id: Option<String>,
+    pub b2_app_key: Option<String>,
+    pub b2_bucket_id: Option<String>,
+
+    /// Directory for local storage.
+    pub storage_local_path: Option<String>,
+    pub internet_url: String,
+}
+
+impl StorageConfig {
+    /// Get the local storage directory, if the local backend is in use.
+    pub fn local_path(&self) -> Option<PathBuf> {
+        match self.storage_backend.as_deref() {
+            Some("local") => self.storage_local_path.as_ref().map(PathBuf::from),
+            _ => None,
+        }
+    }
+}
+
+/// Create the storage backend specified by the config.
+pub async fn get_storage(config: &StorageConfig) -> anyhow::Result<BoxedStorage> {
+    fn required<'a>(value: &'a Option<String>, name: &str) -> anyhow::Result<&'a str> {
+        value
+            .as_deref()
+            .with_context(|| format!("storage backend requires {}", name))
+    }
+
+    let storage: BoxedStorage = match config.storage_backend.as_deref() {
+        None | Some("s3") => Arc::new(S3Storage::new(
+            required(&config.s3_endpoint, "S3_ENDPOINT")?,
+            required(&config.s3_region, "S3_REGION")?,
+            required(&config.s3_token, "S3_TOKEN")?,
+            required(&config.s3_secret, "S3_SECRET")?,
+            required(&config.s3_bucket, "S3_BUCKET")?,
+            required(&config.s3_url, "S3_URL")?,
+        )),
+        Some("b2") => Arc::new(
+            B2Storage::new(
+                required(&config.b2_account_id, "B2_ACCOUNT_ID")?,
+                required(&config.b2_app_key, "B2_APP_KEY")?,
+                required(&config.b2_bucket_id, "B2_BUCKET_ID")?,
+            )
+            .await?,
+        ),
+        Some("local") => Arc::new(LocalStorage::new(
+            required(&config.storage_local_path, "STORAGE_LOCAL_PATH")?,
+            &format!("{}/storage", config.internet_url),
+        )),
+        Some(other) => anyhow::bail!("unknown storage backend: {}", other),
+    };
+
+    Ok(storage)
+}
+
+/// Storage in an S3 compatible bucket.
+pub struct S3Storage {
+    client: rusoto_s3::S3Client,
+    bucket: String,
+    url: String,
+}
+
+impl S3Storage {
+    pub fn new(
+        endpoint: &str,
+        region: &str,
+        token: &str,
+        secret: &str,
+        bucket: &str,
+        url: &str,
+    ) -> Self {
+        let region = rusoto_core::Region::Custom {
+            name: region.to_string(),
+            endpoint: endpoint.to_string(),
+        };
+
+        let client = rusoto_core::request::HttpClient::new().unwrap();
+        let provider =
+            rusoto_credential::StaticProvider::new_minimal(token.to_string(), secret.to_string());
+
+        Self {
+            client: rusoto_s3::S3Client::new_with(client, provider, region),
+            bucket: bucket.to_string(),
+            url: url.to_string(),
+        }
+    }
+
+    async fn put_object(
+        &self,
+        key: &str,
+        content_type: &str,
+        data: bytes::Bytes,
+        acl: &str,
+    ) -> anyhow::Result<()> {
+        let put = rusoto_s3::PutObjectRequest {
+            acl: Some(acl.into()),
+            bucket: self.bucket.clone(),
+            content_type: Some(content_type.into()),
+            key: key.to_string(),
+            content_length: Some(data.len() as i64),
+            body: Some(data.to_vec().into()),
+            ..Default::default()
+        };
+
+        self.client
+            .put_object(put)
+            .await
+            .context("unable to put object")?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Storage for S3Storage {
+    async fn put(&self, key: &str, content_type: &str, data: bytes::Bytes) -> anyhow::Result<()> {
+        self.put_object(key, content_type, data, "public-read")
+            .await
+    }
+
+    async fn put_download(
+        &self,
+        key: &str,
+        content_type: &str,
+        data: bytes::Bytes,
+    ) -> anyhow::Result<()> {
+        self.put_object(key, content_type, data, "download").await
+    }
+
+    async fn delete(&self, key: &str) -> anyhow::Result<()> {
+        let delete = rusoto_s3::DeleteObjectRequest {
+            bucket: self.bucket.clone(),
+            key: key.to_string(),
+            ..Default::default()
+        };
+
+        self.client
+            .delete_object(delete)
+            .await
+            .context("unable to delete object")?;
+
+        Ok(())
+    }
+
+    async fn exists(&self, key: &str) -> anyhow::Result<bool> {
+        let head = rusoto_s3::HeadObjectRequest {
+            bucket: self.bucket.clone(),
+            key: key.to_string(),
+            ..Default::default()
+        };
+
+        match self.client.head_object(head).await {
+            Ok(_) => Ok(true),
+            Err(rusoto_core::RusotoError::Service(rusoto_s3::HeadObjectError::NoSuchKey(_))) => {
+                Ok(false)
+            }
+            // HEAD responses have no body, so missing objects are usually
+            // only reported through the status code.
+            Err(rusoto_core::RusotoError::Unknown(resp)) if resp.status.as_u16() == 404 => {
+                Ok(false)
+            }
+            Err(err) => Err(err).context("unable to check object"),
+        }
+    }
+
+    fn public_url(&self, key: &str) -> String {
+        format!("{}/{}/{}", self.url, self.bucket, key)
+    }
+}
+
+/// Storage in a Backblaze B2 bucket, using the native B2 API.
+pub struct B2Storage {
+    client: reqwest::Client,
+    account_id: String,
+    app_key: String,
+    bucket_id: String,
+    bucket_name: String,
+    download_url: String,
+
+    auth: tokio::sync::RwLock<B2Auth>,
+}
+
+#[derive(Clone)]
+struct B2Auth {
+    api_url: String,
+    token: String,
+    authorized_at: Instant,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct B2AuthorizeResponse {
+    account_id: String,
+    authorization_token: String,
+    api_url: String,
+    download_url: String,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct B2Bucket {
+    bucket_name: String,
+}
+
+#[derive(serde::Deserialize)]
+struct B2ListBucketsResponse {
+    buckets: Vec<B2Bucket>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct B2UploadUrlResponse {
+    upload_url: String,
+    authorization_token: String,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct B2File {
+    file_name: String,
+    file_id: String,
+}
+
+#[derive(serde::Deserialize)]
+struct B2ListFilesResponse {
+    files: Vec<B2File>,
+}
+
+impl B2Storage {
+    const AUTHORIZE_URL: &'static str = "https://api.backblazeb2.com/b2api/v2/b2_authorize_account";
+    /// B2 tokens are valid for 24 hours, refresh them a little early.
+    const AUTH_LIFETIME: Duration = Duration::from_secs(60 * 60 * 23);
+
+    /// Authorize with B2 and look up the bucket name, which is needed for
+    /// public URLs.
+    pub async fn new(account_id: &str, app_key: &str, bucket_id: &str) -> anyhow::Result<Self> {
+        let client = reqwest::Client::new();
+
+        let (auth, resp) = Self::authorize(&client, account_id, app_key).await?;
+
+        let buckets: B2ListBucketsResponse = client
+            .post(&format!("{}/b2api/v2/b2_list_buckets", auth.api_url))
+            .header(reqwest::header::AUTHORIZATION, &auth.token)
+            .json(&serde_json::json!({
+                "accountId": resp.account_id,
+                "bucketId": bucket_id,
+            }))
+            .send()
+            .await?
+            .error_for_status()
+            .context("unable to list b2 buckets")?
+            .json()
+            .await?;
+
+        let bucket_name = buckets
+            .buckets
+            .into_iter()
+            .next()
+            .context("b2 bucket did not exist")?
+            .bucket_name;
+
+        Ok(Self {
+            client,
+            account_id: account_id.to_string(),
+            app_key: app_key.to_string(),
+            bucket_id: bucket_id.to_string(),
+            bucket_name,
+            download_url: resp.download_url,
+
+            auth: tokio::sync::RwLock::new(auth),
+        })
+    }
+
+    async fn authorize(
+        client: &reqwest::Client,
+        account_id: &str,
+        app_key: &str,
+    ) -> anyhow::Result<(B2Auth, B2AuthorizeResponse)> {
+        let resp: B2AuthorizeResponse = client
+            .get(Self::AUTHORIZE_URL)
+            .basic_auth(account_id, Some(app_key))
+            .send()
+            .await?
+            .error_for_status()
+            .context("unable to authorize with b2")?
+            .json()
+            .await?;
+
+        let auth = B2Auth {
+            api_url: resp.api_url.clone(),
+            token: resp.authorization_token.clone(),
+            authorized_at: Instant::now(),
+        };
+
+        Ok((auth, resp))
+    }
+
+    /// Get current authorization, refreshing it if it is about to expire.
+    async fn get_auth(&self) -> anyhow::Result<B2Auth> {
+        {
+            let auth = self.auth.read().await;
+            if auth.authorized_at.elapsed() < Self::AUTH_LIFETIME {
+                return Ok(auth.clone());
+            }
+        }
+
+        tracing::debug!("refreshing b2 authorization");
+
+        let (auth, _resp) = Self::authorize(&self.client, &self.account_id, &self.app_key).await?;
+        *self.auth.write().await = auth.clone();
+
+        Ok(auth)
+    }
+
+    /// Make a request to a B2 API endpoint with the current authorization.
+    async fn api_request<T: serde::de::DeserializeOwned>(
+        &self,
+        endpoint: &str,
+        body: serde_json::Value,
+    ) -> anyhow::Result<T> {
+        let auth = self.get_auth().await?;
+
+        let resp = self
+            .client
+            .post(&format!("{}/b2api/v2/{}", auth.api_url, endpoint))
+            .header(reqwest::header::AUTHORIZATION, &auth.token)
+            .json(&body)
+            .send()
+            .await?
+            .error_for_status()
+            .with_context(|| format!("b2 request to {} failed", endpoint))?
+            .json()
+            .await?;
+
+        Ok(resp)
+    }
+
+    /// Find the file with exactly the given name.
+    async fn find_file(&self, key: &str) -> anyhow::Result<Option<B2File>> {
+        let resp: B2ListFilesResponse = self
+            .api_request(
+                "b2_list_file_names",
+                serde_json::json!({
+                    "bucketId": self.bucket_id,
+                    "startFileName": key,
+                    "maxFileCount": 1,
+                }),
+            )
+            .await?;
+
+        Ok(resp.files.into_iter().find(|file| file.file_name == key))
+    }
+}
+
+/// Encode a file name for B2, which requires percent encoding everything
+/// except for slashes and unreserved characters.
+fn b2_encode_name(key: &str) -> String {
+    key.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[async_trait]
+impl Storage for B2Storage {
+    async fn put(&self, key: &str, content_type: &str, data: bytes::Bytes) -> anyhow::Result<()> {
+        let upload: B2UploadUrlResponse = self
+            .api_request(
+                "b2_get_upload_url",
+                serde_json::json!({ "bucketId": self.bucket_id }),
+            )
+            .await?;
+
+        self.client
+            .post(&upload.upload_url)
+            .header(reqwest::header::AUTHORIZATION, upload.authorization_token)
+            .header(reqwest::header::CONTENT_TYPE, content_type)
+            .header(reqwest::header::CONTENT_LENGTH, data.len())
+            .header("X-Bz-File-Name", b2_encode_name(key))
+            .header("X-Bz-Content-Sha1", "do_not_verify")
+            .body(data)
+            .send()
+            .await?
+            .error_for_status()
+            .context("unable to upload file to b2")?;
+
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> anyhow::Result<()> {
+        let file = match self.find_file(key).await? {
+            Some(file) => file,
+            None => return Ok(()),
+        };
+
+        let _: serde_json::Value = self
+            .api_request(
+                "b2_delete_file_version",
+                serde_json::json!({
+                    "fileName": file.file_name,
+                    "fileId": file.file_id,
+                }),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn exists(&self, key: &str) -> anyhow::Result<bool> {
+        Ok(self.find_file(key).await?.is_some())
+    }
+
+    fn public_url(&self, key: &str) -> String {
+        format!(
+            "{}/file/{}/{}",
+            self.download_url,
+            self.bucket_name,
+            b2_encode_name(key)
+        )
+    }
+}
+
+/// Storage in a local directory, served by the bot's web server.
+pub struct LocalStorage {
+    root: PathBuf,
+    url: String,
+}
+
+impl LocalStorage {
+    pub fn new<P: Into<PathBuf>>(root: P, url: &str) -> Self {
+        Self {
+            root: root.into(),
+            url: url.to_string(),
+        }
+    }
+
+    /// Get the path of a key within a root directory, rejecting any keys that
+    /// could refer to a path outside of it.
+    pub fn resolve(root: &Path, key: &str) -> Option<PathBuf> {
+        let key = Path::new(key);
+
+        if key.as_os_str().is_empty()
+            || !key
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return None;
+        }
+
+        Some(root.join(key))
+    }
+
+    fn path(&self, key: &str) -> anyhow::Result<PathBuf> {
+        Self::resolve(&self.root, key).with_context(|| format!("invalid storage key: {}", key))
+    }
+}
+
+#[async_trait]
+impl Storage for LocalStorage {
+    async fn put(&self, key: &str, _content_type: &str, data: bytes::Bytes) -> anyhow::Result<()> {
+        let path = self.path(key)?;
+
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .context("unable to create storage directory")?;
+        }
+
+        tokio::fs::write(&path, &data)
+            .await
+            .context("unable to write file")?;
+
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> anyhow::Result<()> {
+        match tokio::fs::remove_file(self.path(key)?).await {
+            Ok(_) => Ok(()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err).context("unable to delete file"),
+        }
+    }
+
+    async fn exists(&self, key: &str) -> anyhow::Result<bool> {
+        match tokio::fs::metadata(self.path(key)?).await {
+            Ok(metadata) => Ok(metadata.is_file()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(err).context("unable to check file"),
+        }
+    }
+
+    fn public_url(&self, key: &str) -> String {
+        format!("{}/{}", self.url, key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_resolve() {
+        let root = Path::new("/srv/storage");
+
+        assert_eq!(
+            LocalStorage::resolve(root, "mg/123/file.jpg"),
+            Some(PathBuf::from("/srv/storage/mg/123/file.jpg"))
+        );
+        assert_eq!(LocalStorage::resolve(root, "../etc/passwd"), None);
+        assert_eq!(LocalStorage::resolve(root, "mg/../../etc/passwd"), None);
+        assert_eq!(LocalStorage::resolve(root, "/etc/passwd"), None);
+        assert_eq!(LocalStorage::resolve(root, ""), None);
+    }
+
+    #[test]
+    fn test_b2_encode_name() {
+        assert_eq!(b2_encode_name("video/abc_123.mp4"), "video/abc_123.mp4");
+        assert_eq!(b2_encode_name("a b+c"), "a%20b%2Bc");
+    }
+
+    #[tokio::test]
+    async fn test_local_storage() {
+        let root = std::env::temp_dir().join(format!("foxbot-storage-{}", crate::generate_id()));
+        let storage = LocalStorage::new(&root, "http://localhost/storage");
+
+        assert!(!storage.exists("mg/1/file").await.unwrap());
+
+        storage
+            .put(
+                "mg/1/file",
+                "image/jpeg",
+                bytes::Bytes::from_static(b"data"),
+            )
+            .await
+            .unwrap();
+        assert!(storage.exists("mg/1/file").await.unwrap());
+        assert_eq!(std::fs::read(root.join("mg/1/file")).unwrap(), b"data");
+        assert_eq!(
+            storage.public_url("mg/1/file"),
+            "http://localhost/storage/mg/1/file"
+        );
+
+        storage.delete("mg/1/file").await.unwrap();
+        assert!(!storage.exists("mg/1/file").await.unwrap());
+        storage
+            .delete("mg/1/file")
+            .await
+            .expect("deleting missing file should succeed");
+
+        assert!(storage
+            .put("../escape", "", bytes::Bytes::new())
+            .await
+            .is_err());
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,523 @@
+use std::str::FromStr;
+
+use foxbot_models::Sites;
+
+/// Telegram's maximum length of callback data, in bytes.
+pub const MAX_CALLBACK_DATA: usize = 64;
+
+/// Callback data that could not be understood.
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub enum CallbackDataError {
+    #[error("callback data was {0} bytes, longer than allowed")]
+    TooLong(usize),
+    #[error("callback data was not in a known format: {0}")]
+    Malformed(String),
+}
+
+/// Settings that can be turned on or off from the settings menu.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SettingsToggle {
+    InlineInfo,
+    InlineTags,
+    SendOriginals,
+}
+
+impl SettingsToggle {
+    fn name(&self) -> &'static str {
+        match self {
+            SettingsToggle::InlineInfo => "info",
+            SettingsToggle::InlineTags => "tags",
+            SettingsToggle::SendOriginals => "originals",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "info" => Some(SettingsToggle::InlineInfo),
+            "tags" => Some(SettingsToggle::InlineTags),
+            "originals" => Some(SettingsToggle::SendOriginals),
+            _ => None,
+        }
+    }
+}
+
+/// Group settings that can be turned on or off by group admins.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GroupToggle {
+    Sourcing,
+    Previews,
+    Albums,
+    Mirroring,
+    Reposts,
+    LinkedReplies,
+}
+
+impl GroupToggle {
+    fn name(&self) -> &'static str {
+        match self {
+            GroupToggle::Sourcing => "source",
+            GroupToggle::Previews => "previews",
+            GroupToggle::Albums => "albums",
+            GroupToggle::Mirroring => "mirror",
+            GroupToggle::Reposts => "reposts",
+            GroupToggle::LinkedReplies => "linked",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "source" => Some(GroupToggle::Sourcing),
+            "previews" => Some(GroupToggle::Previews),
+            "albums" => Some(GroupToggle::Albums),
+            "mirror" => Some(GroupToggle::Mirroring),
+            "reposts" => Some(GroupToggle::Reposts),
+            "linked" => Some(GroupToggle::LinkedReplies),
+            _ => None,
+        }
+    }
+}
+
+/// Group settings that cycle through a set of values by group admins.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GroupCycle {
+    Distance,
+    MaxLinks,
+    NoisyRule,
+    Delivery,
+    DeleteAfter,
+    RepostDistance,
+    RepostWindow,
+    LinkedForwards,
+}
+
+impl GroupCycle {
+    fn name(&self) -> &'static str {
+        match self {
+            GroupCycle::Distance => "distance",
+            GroupCycle::MaxLinks => "links",
+            GroupCycle::NoisyRule => "noisy",
+            GroupCycle::Delivery => "delivery",
+            GroupCycle::DeleteAfter => "delete",
+            GroupCycle::RepostDistance => "rdistance",
+            GroupCycle::RepostWindow => "rwindow",
+            GroupCycle::LinkedForwards => "forwards",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "distance" => Some(GroupCycle::Distance),
+            "links" => Some(GroupCycle::MaxLinks),
+            "noisy" => Some(GroupCycle::NoisyRule),
+            "delivery" => Some(GroupCycle::Delivery),
+            "delete" => Some(GroupCycle::DeleteAfter),
+            "rdistance" => Some(GroupCycle::RepostDistance),
+            "rwindow" => Some(GroupCycle::RepostWindow),
+            "forwards" => Some(GroupCycle::LinkedForwards),
+            _ => None,
+        }
+    }
+}
+
+/// Channel settings that cycle through a set of values by channel admins.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChannelCycle {
+    EditStyle,
+    ArtistCredit,
+    Hashtags,
+}
+
+impl ChannelCycle {
+    fn name(&self) -> &'static str {
+        match self {
+            ChannelCycle::EditStyle => "style",
+            ChannelCycle::ArtistCredit => "credit",
+            ChannelCycle::Hashtags => "hashtags",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "style" => Some(ChannelCycle::EditStyle),
+            "credit" => Some(ChannelCycle::ArtistCredit),
+            "hashtags" => Some(ChannelCycle::Hashtags),
+            _ => None,
+        }
+    }
+}
+
+/// Data attached to an inline keyboard button, sent back to us when the
+/// button is pressed.
+///
+/// Every variant is encoded in a short, fixed format so that it fits within
+/// Telegram's limit. Formats must not change once buttons using them have
+/// been sent, as old messages keep their buttons.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CallbackData {
+    /// Delete the message the button was attached to.
+    Delete,
+    /// Subscribe to notifications when an image hash is found.
+    Notify { hash: i64 },
+    /// Show a page of the user's subscriptions.
+    SubscriptionsPage { page: i64 },
+    /// Show the image for a subscription.
+    SubscriptionView { hash: i64 },
+    /// Cancel a subscription, then show the page it was on.
+    SubscriptionCancel { hash: i64, page: i64 },
+    /// Show the site order settings.
+    SettingsOrder,
+    /// A site name in the site order settings was pressed.
+    SettingsOrderSite { site: Sites },
+    /// A site can't be moved any further.
+    SettingsOrderUnable { site: Sites },
+    /// Move a site to a new position.
+    SettingsOrderMove { site: Sites, pos: usize },
+    /// Turn a setting on or off.
+    SettingsToggle(SettingsToggle),
+    /// Explain caption templates.
+    SettingsCaption,
+    /// Show the group settings again, with current values.
+    GroupSettings,
+    /// Turn a group setting on or off.
+    GroupToggle(GroupToggle),
+    /// Change a group setting to its next value.
+    GroupCycle(GroupCycle),
+    /// Show the sites a group allows sources from.
+    GroupSites,
+    /// Allow or disallow sources from a site in a group.
+    GroupSiteToggle { site: Sites },
+    /// Show the order a group shows sources from sites in.
+    GroupOrder,
+    /// Move a site to a new position in a group's site order.
+    GroupOrderMove { site: Sites, pos: usize },
+    /// Stop using a group's site order, using each user's order instead.
+    GroupOrderReset,
+    /// Show the sites a group automatically mirrors links from.
+    GroupMirrorSites,
+    /// Allow or disallow automatically mirroring links for a site loader.
+    GroupMirrorSiteToggle { site: String },
+    /// Change a channel setting to its next value.
+    ChannelCycle(ChannelCycle),
+    /// Start or stop sending the pressing admin a digest of actions in a
+    /// group or channel.
+    ChatDigest,
+    /// Start adding a Twitter account.
+    TwitterAdd,
+    /// Remove the linked Twitter account.
+    TwitterRemove,
+    /// Start processing a video again after it failed.
+    VideoRetry { display_name: String },
+}
+
+impl CallbackData {
+    /// Name of the handler that should receive this callback.
+    pub fn handler(&self) -> &'static str {
+        match self {
+            CallbackData::Delete => "error_cleanup",
+            CallbackData::Notify { .. }
+            | CallbackData::SubscriptionsPage { .. }
+            | CallbackData::SubscriptionView { .. }
+            | CallbackData::SubscriptionCancel { .. } => "subscribe",
+            CallbackData::SettingsOrder
+            | CallbackData::SettingsOrderSite { .. }
+            | CallbackData::SettingsOrderUnable { .. }
+            | CallbackData::SettingsOrderMove { .. }
+            | CallbackData::SettingsToggle(_)
+            | CallbackData::SettingsCaption
+            | CallbackData::GroupSettings
+            | CallbackData::GroupToggle(_)
+            | CallbackData::GroupCycle(_)
+            | CallbackData::GroupSites
+            | CallbackData::GroupSiteToggle { .. }
+            | CallbackData::GroupOrder
+            | CallbackData::GroupOrderMove { .. }
+            | CallbackData::GroupOrderReset
+            | CallbackData::GroupMirrorSites
+            | CallbackData::GroupMirrorSiteToggle { .. }
+            | CallbackData::ChannelCycle(_)
+            | CallbackData::ChatDigest => "settings",
+            CallbackData::TwitterAdd | CallbackData::TwitterRemove => "twitter",
+            CallbackData::VideoRetry { .. } => "inline",
+        }
+    }
+
+    /// Encode the callback data for use in a button.
+    pub fn encode(&self) -> String {
+        let data = match self {
+            CallbackData::Delete => "delete".to_string(),
+            CallbackData::Notify { hash } => format!("notify-{}", hash),
+            CallbackData::SubscriptionsPage { page } => format!("subs:page:{}", page),
+            CallbackData::SubscriptionView { hash } => format!("subs:view:{}", hash),
+            CallbackData::SubscriptionCancel { hash, page } => {
+                format!("subs:cancel:{}:{}", hash, page)
+            }
+            CallbackData::SettingsOrder => "s:order:".to_string(),
+            CallbackData::SettingsOrderSite { site } => format!("s:order:{}:e", site.as_str()),
+            CallbackData::SettingsOrderUnable { site } => format!("s:order:{}:-", site.as_str()),
+            CallbackData::SettingsOrderMove { site, pos } => {
+                format!("s:order:{}:{}", site.as_str(), pos)
+            }
+            CallbackData::SettingsToggle(toggle) => format!("s:toggle:{}", toggle.name()),
+            CallbackData::SettingsCaption => "s:caption".to_string(),
+            CallbackData::GroupSettings => "g:menu".to_string(),
+            CallbackData::GroupToggle(toggle) => format!("g:toggle:{}", toggle.name()),
+            CallbackData::GroupCycle(cycle) => format!("g:cycle:{}", cycle.name()),
+            CallbackData::GroupSites => "g:sites".to_string(),
+            CallbackData::GroupSiteToggle { site } => format!("g:site:{}", site.as_str()),
+            CallbackData::GroupOrder => "g:order".to_string(),
+            CallbackData::GroupOrderMove { site, pos } => {
+                format!("g:order:{}:{}", site.as_str(), pos)
+            }
+            CallbackData::GroupOrderReset => "g:order:reset".to_string(),
+            CallbackData::GroupMirrorSites => "g:mirror".to_string(),
+            CallbackData::GroupMirrorSiteToggle { site } => format!("g:msite:{}", site),
+            CallbackData::ChannelCycle(cycle) => format!("c:cycle:{}", cycle.name()),
+            CallbackData::ChatDigest => "digest".to_string(),
+            CallbackData::TwitterAdd => "twitter-add".to_string(),
+            CallbackData::TwitterRemove => "twitter-remove".to_string(),
+            CallbackData::VideoRetry { display_name } => format!("video-retry:{}", display_name),
+        };
+
+        debug_assert!(
+            data.len() <= MAX_CALLBACK_DATA,
+            "callback data was too long: {}",
+            data
+        );
+
+        data
+    }
+
+    /// Create a button that sends this callback data when pressed.
+    pub fn button(&self, text: String) -> tgbotapi::InlineKeyboardButton {
+        tgbotapi::InlineKeyboardButton {
+            text,
+            callback_data: Some(self.encode()),
+            ..Default::default()
+        }
+    }
+}
+
+/// Parse one part of callback data, treating any error as malformed data.
+fn parse_part<T: FromStr>(value: &str, data: &str) -> Result<T, CallbackDataError> {
+    value
+        .parse()
+        .map_err(|_| CallbackDataError::Malformed(data.to_string()))
+}
+
+impl FromStr for CallbackData {
+    type Err = CallbackDataError;
+
+    fn from_str(data: &str) -> Result<Self, Self::Err> {
+        if data.len() > MAX_CALLBACK_DATA {
+            return Err(CallbackDataError::TooLong(data.len()));
+        }
+
+        let malformed = || CallbackDataError::Malformed(data.to_string());
+
+        if let Some(hash) = data.strip_prefix("notify-") {
+            return Ok(CallbackData::Notify {
+                hash: parse_part(hash, data)?,
+            });
+        }
+
+        if let Some(display_name) = data.strip_prefix("video-retry:") {
+            if display_name.is_empty() || !display_name.chars().all(|c| c.is_ascii_alphanumeric()) {
+                return Err(malformed());
+            }
+
+            return Ok(CallbackData::VideoRetry {
+                display_name: display_name.to_string(),
+            });
+        }
+
+        let parts: Vec<&str> = data.split(':').collect();
+
+        let callback = match parts.as_slice() {
+            ["delete"] => CallbackData::Delete,
+            ["twitter-add"] => CallbackData::TwitterAdd,
+            ["twitter-remove"] => CallbackData::TwitterRemove,
+            ["subs", "page", page] => CallbackData::SubscriptionsPage {
+                page: parse_part(page, data)?,
+            },
+            ["subs", "view", hash] => CallbackData::SubscriptionView {
+                hash: parse_part(hash, data)?,
+            },
+            ["subs", "cancel", hash, page] => CallbackData::SubscriptionCancel {
+                hash: parse_part(hash, data)?,
+                page: parse_part(page, data)?,
+            },
+            ["s", "order", ""] => CallbackData::SettingsOrder,
+            ["s", "order", site, action] => {
+                let site: Sites = parse_part(site, data)?;
+
+                match *action {
+                    "e" => CallbackData::SettingsOrderSite { site },
+                    "-" => CallbackData::SettingsOrderUnable { site },
+                    pos => {
+                        let pos: usize = parse_part(pos, data)?;
+
+                        if pos >= Sites::len() {
+                            return Err(malformed());
+                        }
+
+                        CallbackData::SettingsOrderMove { site, pos }
+                    }
+                }
+            }
+            ["s", "toggle", name] => {
+                CallbackData::SettingsToggle(SettingsToggle::from_name(name).ok_or_else(malformed)?)
+            }
+            ["s", "caption"] => CallbackData::SettingsCaption,
+            ["g", "menu"] => CallbackData::GroupSettings,
+            ["g", "toggle", name] => {
+                CallbackData::GroupToggle(GroupToggle::from_name(name).ok_or_else(malformed)?)
+            }
+            ["g", "cycle", name] => {
+                CallbackData::GroupCycle(GroupCycle::from_name(name).ok_or_else(malformed)?)
+            }
+            ["g", "sites"] => CallbackData::GroupSites,
+            ["g", "site", site] => CallbackData::GroupSiteToggle {
+                site: parse_part(site, data)?,
+            },
+            ["g", "order"] => CallbackData::GroupOrder,
+            ["g", "order", "reset"] => CallbackData::GroupOrderReset,
+            ["g", "order", site, pos] => {
+                let pos: usize = parse_part(pos, data)?;
+
+                if pos >= Sites::len() {
+                    return Err(malformed());
+                }
+
+                CallbackData::GroupOrderMove {
+                    site: parse_part(site, data)?,
+                    pos,
+                }
+            }
+            ["g", "mirror"] => CallbackData::GroupMirrorSites,
+            ["g", "msite", site] if !site.is_empty() => CallbackData::GroupMirrorSiteToggle {
+                site: site.to_string(),
+            },
+            ["c", "cycle", name] => {
+                CallbackData::ChannelCycle(ChannelCycle::from_name(name).ok_or_else(malformed)?)
+            }
+            ["digest"] => CallbackData::ChatDigest,
+            _ => return Err(malformed()),
+        };
+
+        Ok(callback)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// An example of every kind of callback.
+    pub(crate) fn example_callbacks() -> Vec<CallbackData> {
+        vec![
+            CallbackData::Delete,
+            CallbackData::Notify { hash: i64::MIN },
+            CallbackData::SubscriptionsPage { page: 3 },
+            CallbackData::SubscriptionView { hash: -42 },
+            CallbackData::SubscriptionCancel {
+                hash: i64::MIN,
+                page: i64::MAX,
+            },
+            CallbackData::SettingsOrder,
+            CallbackData::SettingsOrderSite {
+                site: Sites::FurAffinity,
+            },
+            CallbackData::SettingsOrderUnable { site: Sites::E621 },
+            CallbackData::SettingsOrderMove {
+                site: Sites::Weasyl,
+                pos: 2,
+            },
+            CallbackData::SettingsToggle(SettingsToggle::SendOriginals),
+            CallbackData::SettingsCaption,
+            CallbackData::GroupSettings,
+            CallbackData::GroupToggle(GroupToggle::Previews),
+            CallbackData::GroupCycle(GroupCycle::NoisyRule),
+            CallbackData::GroupCycle(GroupCycle::DeleteAfter),
+            CallbackData::GroupCycle(GroupCycle::RepostWindow),
+            CallbackData::GroupSites,
+            CallbackData::GroupSiteToggle { site: Sites::E621 },
+            CallbackData::GroupToggle(GroupToggle::Mirroring),
+            CallbackData::GroupOrder,
+            CallbackData::GroupOrderMove {
+                site: Sites::FurAffinity,
+                pos: 3,
+            },
+            CallbackData::GroupOrderReset,
+            CallbackData::GroupMirrorSites,
+            CallbackData::GroupMirrorSiteToggle {
+                site: "direct link".to_string(),
+            },
+            CallbackData::ChannelCycle(ChannelCycle::Hashtags),
+            CallbackData::ChatDigest,
+            CallbackData::TwitterAdd,
+            CallbackData::TwitterRemove,
+            CallbackData::VideoRetry {
+                display_name: foxbot_utils::generate_id(),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for callback in example_callbacks() {
+            let data = callback.encode();
+            assert!(data.len() <= MAX_CALLBACK_DATA, "{} was too long", data);
+            assert_eq!(data.parse(), Ok(callback));
+        }
+    }
+
+    #[test]
+    fn test_existing_formats() {
+        assert_eq!("notify-123".parse(), Ok(CallbackData::Notify { hash: 123 }));
+        assert_eq!(
+            "s:order:Twitter:1".parse(),
+            Ok(CallbackData::SettingsOrderMove {
+                site: Sites::Twitter,
+                pos: 1
+            })
+        );
+        assert_eq!(
+            "subs:cancel:5:0".parse(),
+            Ok(CallbackData::SubscriptionCancel { hash: 5, page: 0 })
+        );
+    }
+
+    #[test]
+    fn test_malformed() {
+        for data in &[
+            "",
+            "notify-",
+            "notify-abc",
+            "s:order:Unknown:1",
+            "s:order:Twitter:99",
+            "s:order:Twitter",
+            "s:toggle:nothing",
+            "g:toggle:nothing",
+            "g:cycle:nothing",
+            "g:site:Unknown",
+            "subs:page:1:2",
+            "video-retry:",
+            "video-retry:../a",
+            "delete:extra",
+        ] {
+            assert!(
+                matches!(
+                    data.parse::<CallbackData>(),
+                    Err(CallbackDataError::Malformed(_))
+                ),
+                "{} should be malformed",
+                data
+            );
+        }
+
+        assert_eq!(
+            "a".repeat(65).parse::<CallbackData>(),
+            Err(CallbackDataError::TooLong(65))
+        );
+    }
+}
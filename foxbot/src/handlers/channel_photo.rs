@@ -6,6 +6,7 @@ use super::{
     Status::{self, *},
 };
 use crate::MessageHandler;
+use foxbot_models::Subscriptions;
 use foxbot_utils::*;
 
 pub struct ChannelPhotoHandler;
@@ -22,9 +23,26 @@ impl Handler for ChannelPhotoHandler {
         update: &Update,
         _command: Option<&Command>,
     ) -> anyhow::Result<Status> {
-        // Ensure we have a channel_post Message and a photo within.
+        // Posts still waiting for a source keep their caption to restore
+        // when sources are added, so it has to follow any edits.
+        if let Some(message) = &update.edited_channel_post {
+            Subscriptions::update_channel_caption(
+                &handler.conn,
+                message.chat.id,
+                message.message_id,
+                message.caption.as_deref(),
+                message.caption_entities.as_deref(),
+            )
+            .await?;
+
+            return Ok(Completed);
+        }
+
+        // Ensure we have a channel_post Message with media we can hash.
         let message = needs_field!(update, channel_post);
-        needs_field!(&message, photo);
+        if hashable_file(message).is_none() {
+            return Ok(Ignored);
+        }
 
         potential_return!(initial_filter(message));
 
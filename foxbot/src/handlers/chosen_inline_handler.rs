@@ -1,13 +1,18 @@
 use async_trait::async_trait;
-use tgbotapi::*;
+use tgbotapi::{requests::*, *};
 
 use super::{
     Handler,
     Status::{self, *},
 };
 use crate::MessageHandler;
+use foxbot_models::{CachedPost, InlineHistory};
 use foxbot_utils::*;
 
+lazy_static::lazy_static! {
+    static ref CHOSEN_RESULTS: prometheus::CounterVec = prometheus::register_counter_vec!("foxbot_inline_chosen_total", "Number of chosen inline results by site", &["site"]).unwrap();
+}
+
 pub struct ChosenInlineHandler;
 
 #[async_trait]
@@ -18,15 +23,100 @@ impl Handler for ChosenInlineHandler {
 
     async fn handle(
         &self,
-        _handler: &MessageHandler,
+        handler: &MessageHandler,
         update: &Update,
         _command: Option<&Command>,
     ) -> anyhow::Result<Status> {
-        let _chosen_result = needs_field!(update, chosen_inline_result);
+        let chosen_result = needs_field!(update, chosen_inline_result);
+
+        if let Some(post) =
+            InlineHistory::get_stashed(&handler.redis, &chosen_result.result_id).await?
+        {
+            CHOSEN_RESULTS.with_label_values(&[post.site.as_str()]).inc();
 
-        // This doesn't need to do anything, returning completed will count it
-        // as a chosen inline handler already.
+            InlineHistory::record(&handler.conn, chosen_result.from.id, &post).await?;
+
+            // The message keeps the result's direct link button, which is how
+            // it can be matched to this result once it appears in a chat.
+            if let Some(inline_message_id) = &chosen_result.inline_message_id {
+                InlineHistory::set_sent_message(
+                    &handler.redis,
+                    chosen_result.from.id,
+                    &post.url,
+                    inline_message_id,
+                )
+                .await?;
+            }
+        }
+
+        if let Some(id) = cached_post_id(&chosen_result.result_id) {
+            save_file_id(handler, id).await?;
+        }
 
         Ok(Completed)
     }
 }
+
+/// Get the cached post ID from an inline result ID, if it was a cached image
+/// without a known file ID.
+fn cached_post_id(result_id: &str) -> Option<i32> {
+    result_id
+        .strip_prefix("cached-")?
+        .split('-')
+        .next()?
+        .parse()
+        .ok()
+}
+
+/// Telegram does not include any information about the sent file with chosen
+/// inline results, so send the image to the file cache chat to get a file ID
+/// that can be reused in future inline results.
+async fn save_file_id(handler: &MessageHandler, id: i32) -> anyhow::Result<()> {
+    let chat_id = match handler.config.file_cache_chat {
+        Some(chat_id) => chat_id,
+        None => return Ok(()),
+    };
+
+    let post = match CachedPost::get_by_id(&handler.conn, id).await? {
+        Some(post) if post.file_id.is_none() => post,
+        _ => return Ok(()),
+    };
+
+    tracing::debug!(cached_post_id = id, "sending cached image for file ID");
+
+    let message = handler
+        .make_request(&SendPhoto {
+            chat_id: chat_id.into(),
+            photo: FileType::Url(post.cdn_url),
+            ..Default::default()
+        })
+        .await?;
+
+    if let Some(photo) = message.photo.as_deref().and_then(find_best_photo) {
+        CachedPost::set_file_id(&handler.conn, id, &photo.file_id).await?;
+    }
+
+    if let Err(err) = handler
+        .make_request(&DeleteMessage {
+            chat_id: chat_id.into(),
+            message_id: message.message_id,
+        })
+        .await
+    {
+        tracing::warn!("unable to delete file cache message: {:?}", err);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_cached_post_id() {
+        use super::cached_post_id;
+
+        assert_eq!(cached_post_id("cached-42-abcdef"), Some(42));
+        assert_eq!(cached_post_id("abcdef"), None);
+        assert_eq!(cached_post_id("cached-abc-def"), None);
+    }
+}
@@ -7,17 +7,15 @@ use super::{
     Handler,
     Status::{self, *},
 };
-use crate::MessageHandler;
-use foxbot_models::{ChatAdmin, GroupConfig, GroupConfigKey};
-use foxbot_sites::PostInfo;
+use crate::{callback::CallbackData, registry::ParsedCommand, MessageHandler};
+use foxbot_models::{
+    GroupConfig, GroupConfigKey, GroupSourceDelivery, InlineHistory, UserConfig, UserConfigKey,
+};
+use foxbot_sites::{PostInfo, ReverseSearch};
 use foxbot_utils::*;
 
 // TODO: there's a lot of shared code between these commands.
 
-lazy_static::lazy_static! {
-    static ref USED_COMMANDS: prometheus::HistogramVec = prometheus::register_histogram_vec!("foxbot_commands_duration_seconds", "Processing duration for each command", &["command"]).unwrap();
-}
-
 pub struct CommandHandler;
 
 #[async_trait]
@@ -28,47 +26,35 @@ impl Handler for CommandHandler {
 
     async fn handle(
         &self,
-        handler: &MessageHandler,
-        update: &Update,
+        _handler: &MessageHandler,
+        _update: &Update,
         _command: Option<&Command>,
     ) -> anyhow::Result<Status> {
-        let message = needs_field!(update, message);
-
-        let command = match message.get_command() {
-            Some(command) => command,
-            None => return Ok(Ignored),
-        };
+        Ok(Ignored)
+    }
 
-        if let Some(username) = command.username {
-            let bot_username = handler.bot_user.username.as_ref().unwrap();
-            if username.to_lowercase() != bot_username.to_lowercase() {
-                tracing::debug!(?username, "got command for other bot");
-                return Ok(Ignored);
+    async fn handle_command(
+        &self,
+        handler: &MessageHandler,
+        message: &Message,
+        command: &ParsedCommand,
+    ) -> anyhow::Result<()> {
+        match command.name() {
+            "help" => handler.handle_welcome(message, "/help").await,
+            "mirror" => {
+                self.handle_mirror(handler, message, command.args.has_flag("file"))
+                    .await
             }
+            "source" => self.handle_source(handler, message).await,
+            "alts" => self.handle_alts(handler, message).await,
+            "error" => Err(anyhow::anyhow!("a test error message")),
+            "groupsource" => self.enable_group_source(handler, message).await,
+            "grouppreviews" => self.group_nopreviews(handler, message).await,
+            "groupalbums" => self.group_noalbums(handler, message).await,
+            "groupcompact" => self.group_compact(handler, message).await,
+            "clearhistory" => self.clear_history(handler, message).await,
+            _ => Ok(()),
         }
-
-        let _hist = USED_COMMANDS
-            .get_metric_with_label_values(&[command.name.as_ref()])
-            .unwrap()
-            .start_timer();
-        tracing::debug!(command = ?command.name, "got command");
-
-        match command.name.as_ref() {
-            "/help" | "/start" => handler.handle_welcome(message, &command.name).await,
-            "/mirror" => self.handle_mirror(handler, message).await,
-            "/source" => self.handle_source(handler, message).await,
-            "/alts" => self.handle_alts(handler, message).await,
-            "/error" => Err(anyhow::anyhow!("a test error message")),
-            "/groupsource" => self.enable_group_source(handler, message).await,
-            "/grouppreviews" => self.group_nopreviews(handler, message).await,
-            "/groupalbums" => self.group_noalbums(handler, message).await,
-            _ => {
-                tracing::info!(command = ?command.name, "unknown command");
-                return Ok(Ignored);
-            }
-        }?;
-
-        Ok(Completed)
     }
 }
 
@@ -77,9 +63,17 @@ impl CommandHandler {
         &self,
         handler: &MessageHandler,
         message: &Message,
+        wants_file: bool,
     ) -> anyhow::Result<()> {
         let from = message.from.as_ref().unwrap();
 
+        // Originals are sent as documents when requested with `/mirror file`
+        // or when the user has enabled it by default.
+        let as_document = wants_file
+            || UserConfig::get(&handler.conn, UserConfigKey::SendOriginals, from.id)
+                .await?
+                .unwrap_or(false);
+
         let action = continuous_action(
             handler.bot.clone(),
             6,
@@ -105,14 +99,12 @@ impl CommandHandler {
             return Ok(());
         }
 
-        let mut results: Vec<PostInfo> = Vec::with_capacity(links.len());
-
-        let mut missing = {
+        let MirrorImages {
+            results,
+            mut missing,
+        } = {
             let mut sites = handler.sites.lock().await;
-            find_images(from, links, &mut sites, &mut |info| {
-                results.extend(info.results);
-            })
-            .await?
+            find_mirror_images(from, links, &mut sites).await?
         };
 
         drop(action);
@@ -124,12 +116,43 @@ impl CommandHandler {
             return Ok(());
         }
 
-        // This will only remove duplicate items if they are sequential. This
-        // will likely fix the most common issue of having a direct and source
-        // link next to each other.
-        results.dedup_by(|a, b| a.source_link == b.source_link && a.url == b.url);
+        if as_document {
+            for result in &results {
+                let action = continuous_action(
+                    handler.bot.clone(),
+                    6,
+                    message.chat_id(),
+                    message.from.clone(),
+                    ChatAction::UploadDocument,
+                );
+
+                let keyboard = handler
+                    .post_keyboard(
+                        from.language_code.as_deref(),
+                        &result.url,
+                        result.source_link.as_deref(),
+                        result.site_name,
+                    )
+                    .await;
+
+                let sent = handler
+                    .send_original(
+                        message.chat_id(),
+                        &result.url,
+                        &result.file_type,
+                        Some(ReplyMarkup::InlineKeyboardMarkup(keyboard)),
+                        Some(message.message_id),
+                    )
+                    .await;
+
+                drop(action);
 
-        if results.len() == 1 {
+                if let Err(err) = sent {
+                    tracing::warn!("unable to send original: {:?}", err);
+                    missing.push(result.source_link.as_deref().unwrap_or(&result.url));
+                }
+            }
+        } else {
             let action = continuous_action(
                 handler.bot.clone(),
                 6,
@@ -138,92 +161,35 @@ impl CommandHandler {
                 ChatAction::UploadPhoto,
             );
 
-            let result = results.get(0).unwrap();
-
-            if result.file_type == "mp4" {
-                let video = SendVideo {
-                    chat_id: message.chat_id(),
-                    caption: result.source_link.clone(),
-                    video: FileType::Url(result.url.clone()),
-                    reply_to_message_id: Some(message.message_id),
-                    ..Default::default()
-                };
-
-                drop(action);
-
-                handler.make_request(&video).await?;
-            } else if let Ok(file_type) = resize_photo(&result.url, 5_000_000).await {
-                let photo = SendPhoto {
-                    chat_id: message.chat_id(),
-                    caption: result.source_link.clone(),
-                    photo: file_type,
-                    reply_to_message_id: Some(message.message_id),
-                    ..Default::default()
-                };
-
-                drop(action);
-
-                handler.make_request(&photo).await?;
-            } else {
-                missing.push(result.source_link.as_deref().unwrap_or(&result.url));
-            }
-        } else {
-            for chunk in results.chunks(10) {
-                let action = continuous_action(
-                    handler.bot.clone(),
-                    6,
-                    message.chat_id(),
-                    message.from.clone(),
-                    ChatAction::UploadPhoto,
-                );
+            let requests = mirror_requests(
+                message.chat_id(),
+                message.message_id,
+                &results,
+                &mut missing,
+            )
+            .await;
 
-                let mut media = Vec::with_capacity(chunk.len());
-
-                for result in chunk {
-                    let input = match result.file_type.as_ref() {
-                        "mp4" => InputMedia::Video(InputMediaVideo {
-                            media: FileType::Url(result.url.to_owned()),
-                            caption: result.source_link.clone(),
-                            ..Default::default()
-                        }),
-                        _ => {
-                            if let Ok(file_type) = resize_photo(&result.url, 5_000_000).await {
-                                InputMedia::Photo(InputMediaPhoto {
-                                    media: file_type,
-                                    caption: result.source_link.clone(),
-                                    ..Default::default()
-                                })
-                            } else {
-                                missing.push(result.source_link.as_deref().unwrap_or(&result.url));
-                                continue;
-                            }
-                        }
-                    };
-
-                    media.push(input);
+            for request in requests {
+                match request {
+                    MirrorRequest::Photo(photo) => {
+                        handler.make_request(&photo).await?;
+                    }
+                    MirrorRequest::Video(video) => {
+                        handler.make_request(&video).await?;
+                    }
+                    MirrorRequest::MediaGroup(media_group) => {
+                        handler.make_request(&media_group).await?;
+                    }
                 }
-
-                let media_group = SendMediaGroup {
-                    chat_id: message.chat_id(),
-                    reply_to_message_id: Some(message.message_id),
-                    media,
-                    ..Default::default()
-                };
-
-                handler.make_request(&media_group).await?;
-
-                drop(action);
             }
+
+            drop(action);
         }
 
         if !missing.is_empty() {
-            let links: Vec<String> = missing.iter().map(|item| format!("· {}", item)).collect();
-            let mut args = fluent::FluentArgs::new();
-            args.insert("links", fluent::FluentValue::from(links.join("\n")));
-
             let text = handler
                 .get_fluent_bundle(from.language_code.as_deref(), |bundle| {
-                    get_message(bundle, "mirror-missing", Some(args)).unwrap()
+                    mirror_missing_message(bundle, &missing)
                 })
                 .await;
 
@@ -318,26 +284,50 @@ impl CommandHandler {
         }
 
         let best_photo = find_best_photo(photo).unwrap();
-        let mut matches = match_image(
+        let (hash, mut matches) = match_image(
             &handler.bot,
             &handler.redis,
             &handler.fapi,
             best_photo,
             Some(3),
         )
-        .await?
-        .1;
-        sort_results(
-            &handler.conn,
-            message.from.as_ref().unwrap().id,
-            &mut matches,
-        )
         .await?;
 
-        let text = handler
+        let user_id = message.from.as_ref().unwrap().id;
+        if message.chat.chat_type.is_group() {
+            sort_group_results(&handler.conn, message.chat.id, user_id, &mut matches).await?;
+        } else {
+            sort_results(&handler.conn, user_id, &mut matches).await?;
+        }
+
+        let compact = GroupSourcePolicy::load(&handler.conn, message.chat.id)
+            .await?
+            .delivery
+            != GroupSourceDelivery::Reply;
+
+        let (text, reply_markup) = handler
             .get_fluent_bundle(
                 message.from.as_ref().unwrap().language_code.as_deref(),
-                |bundle| source_reply(&matches, bundle),
+                |bundle| {
+                    // Allow anyone in the group to be notified if a source
+                    // for this image is found later.
+                    if matches.is_empty() {
+                        return (
+                            source_reply(&matches, bundle),
+                            Some(InlineKeyboardMarkup {
+                                inline_keyboard: vec![vec![CallbackData::Notify { hash }.button(
+                                    get_message(bundle, "reverse-subscribe", None).unwrap(),
+                                )]],
+                            }),
+                        );
+                    }
+
+                    if compact {
+                        source_reply_compact(&matches, bundle)
+                    } else {
+                        (source_reply(&matches, bundle), None)
+                    }
+                },
             )
             .await;
 
@@ -356,6 +346,7 @@ impl CommandHandler {
             text,
             disable_web_page_preview: Some(disable_preview),
             reply_to_message_id: Some(reply_to_id),
+            reply_markup: reply_markup.map(ReplyMarkup::InlineKeyboardMarkup),
             ..Default::default()
         };
 
@@ -454,28 +445,7 @@ impl CommandHandler {
             return Ok(());
         }
 
-        let mut results: HashMap<Vec<String>, Vec<fuzzysearch::File>> = HashMap::new();
-
-        let matches: Vec<fuzzysearch::File> = matches
-            .into_iter()
-            .map(|m| fuzzysearch::File {
-                artists: Some(
-                    m.artists
-                        .unwrap_or_else(Vec::new)
-                        .iter()
-                        .map(|artist| artist.to_lowercase())
-                        .collect(),
-                ),
-                ..m
-            })
-            .collect();
-
-        for m in matches {
-            let v = results
-                .entry(m.artists.clone().unwrap_or_else(Vec::new))
-                .or_default();
-            v.push(m);
-        }
+        let mut results = group_alternates(matches);
 
         let items = results
             .iter()
@@ -508,28 +478,13 @@ impl CommandHandler {
 
         let sent = handler.make_request(&send_message).await?;
 
-        let matches = handler.fapi.lookup_hashes(&used_hashes, Some(10)).await?;
-
-        if matches.is_empty() {
+        if !expand_alternates(&handler.fapi, &mut results, &used_hashes, searched_hash).await? {
             handler
                 .send_generic_reply(message, "reverse-no-results")
                 .await?;
             return Ok(());
         }
 
-        for m in matches {
-            if let Some(artist) = results.get_mut(&m.artists.clone().unwrap()) {
-                artist.push(fuzzysearch::File {
-                    distance: hamming::distance_fast(
-                        &m.hash.unwrap().to_be_bytes(),
-                        &searched_hash.to_be_bytes(),
-                    )
-                    .ok(),
-                    ..m
-                });
-            }
-        }
-
         let items = results
             .iter()
             .map(|item| (item.0, item.1))
@@ -561,96 +516,11 @@ impl CommandHandler {
             .map_err(Into::into)
     }
 
-    async fn is_valid_admin_group(
-        &self,
-        handler: &MessageHandler,
-        message: &Message,
-        bot_needs_admin: bool,
-    ) -> anyhow::Result<bool> {
-        use tgbotapi::ChatMemberStatus::*;
-
-        if !message.chat.chat_type.is_group() {
-            handler
-                .send_generic_reply(message, "automatic-enable-not-group")
-                .await?;
-            return Ok(false);
-        }
-
-        let user = message.from.as_ref().unwrap();
-
-        // As of the Bot API 5.1, Telegram can now proactively send updates
-        // about user information. There is another handler that listens for
-        // these changes and saves them to the database. When possible we should
-        // use these saved values instead of making more requests.
-
-        let user_is_admin =
-            match ChatAdmin::is_admin(&handler.conn, user.id, message.chat.id).await? {
-                Some(is_admin) => is_admin,
-                _ => {
-                    let get_chat_member = GetChatMember {
-                        chat_id: message.chat_id(),
-                        user_id: user.id,
-                    };
-                    let chat_member = handler.make_request(&get_chat_member).await?;
-
-                    matches!(chat_member.status, Administrator | Creator)
-                }
-            };
-
-        if !user_is_admin {
-            handler
-                .send_generic_reply(message, "automatic-enable-not-admin")
-                .await?;
-            return Ok(false);
-        }
-
-        if !bot_needs_admin {
-            return Ok(true);
-        }
-
-        let bot_is_admin =
-            match ChatAdmin::is_admin(&handler.conn, handler.bot_user.id, message.chat.id).await? {
-                Some(is_admin) => is_admin,
-                _ => {
-                    let get_chat_member = GetChatMember {
-                        chat_id: message.chat_id(),
-                        user_id: handler.bot_user.id,
-                    };
-                    let bot_member = handler.make_request(&get_chat_member).await?;
-
-                    // Already fetching it, should save it for trying to delete summoning
-                    // messages.
-                    GroupConfig::set(
-                        &handler.conn,
-                        GroupConfigKey::HasDeletePermission,
-                        message.chat.id,
-                        bot_member.can_delete_messages.unwrap_or(false),
-                    )
-                    .await?;
-
-                    matches!(bot_member.status, Administrator | Creator)
-                }
-            };
-
-        if !bot_is_admin {
-            handler
-                .send_generic_reply(message, "automatic-enable-bot-not-admin")
-                .await?;
-            return Ok(false);
-        }
-
-        Ok(true)
-    }
-
     async fn enable_group_source(
         &self,
         handler: &MessageHandler,
         message: &Message,
     ) -> anyhow::Result<()> {
-        if !self.is_valid_admin_group(handler, message, true).await? {
-            return Ok(());
-        }
-
         let result = GroupConfig::get(&handler.conn, message.chat.id, GroupConfigKey::GroupAdd)
             .await?
             .unwrap_or(false);
@@ -679,10 +549,6 @@ impl CommandHandler {
         handler: &MessageHandler,
         message: &Message,
     ) -> anyhow::Result<()> {
-        if !self.is_valid_admin_group(handler, message, false).await? {
-            return Ok(());
-        }
-
         let result = GroupConfig::get(
             &handler.conn,
             message.chat.id,
@@ -715,10 +581,6 @@ impl CommandHandler {
         handler: &MessageHandler,
         message: &Message,
     ) -> anyhow::Result<()> {
-        if !self.is_valid_admin_group(handler, message, false).await? {
-            return Ok(());
-        }
-
         let result = GroupConfig::get(
             &handler.conn,
             message.chat.id,
@@ -745,4 +607,183 @@ impl CommandHandler {
 
         Ok(())
     }
+
+    async fn group_compact(
+        &self,
+        handler: &MessageHandler,
+        message: &Message,
+    ) -> anyhow::Result<()> {
+        let current = GroupSourcePolicy::load(&handler.conn, message.chat.id)
+            .await?
+            .delivery;
+
+        // Editing messages is only available from the group settings, so
+        // don't replace it with one of the modes this command switches between.
+        let (delivery, name) = match current {
+            GroupSourceDelivery::Reply => {
+                (GroupSourceDelivery::Buttons, "automatic-compact-enable")
+            }
+            GroupSourceDelivery::Buttons => {
+                (GroupSourceDelivery::Reply, "automatic-compact-disable")
+            }
+            GroupSourceDelivery::Edit => {
+                handler
+                    .send_generic_reply(message, "automatic-compact-edit")
+                    .await?;
+
+                return Ok(());
+            }
+        };
+
+        GroupConfig::set(
+            &handler.conn,
+            GroupConfigKey::SourceDelivery,
+            message.chat.id,
+            delivery,
+        )
+        .await?;
+
+        handler.send_generic_reply(message, name).await?;
+
+        Ok(())
+    }
+
+    async fn clear_history(
+        &self,
+        handler: &MessageHandler,
+        message: &Message,
+    ) -> anyhow::Result<()> {
+        let from = message.from.as_ref().unwrap();
+
+        let count = InlineHistory::clear(&handler.conn, from.id).await?;
+        tracing::debug!(count, "cleared inline history");
+
+        handler
+            .send_generic_reply(message, "history-cleared")
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// Group matches by their lowercased artist names so alternate versions from
+/// the same artist are displayed together.
+fn group_alternates(
+    matches: Vec<fuzzysearch::File>,
+) -> HashMap<Vec<String>, Vec<fuzzysearch::File>> {
+    let mut results: HashMap<Vec<String>, Vec<fuzzysearch::File>> = HashMap::new();
+
+    let matches: Vec<fuzzysearch::File> = matches
+        .into_iter()
+        .map(|m| fuzzysearch::File {
+            artists: Some(
+                m.artists
+                    .unwrap_or_else(Vec::new)
+                    .iter()
+                    .map(|artist| artist.to_lowercase())
+                    .collect(),
+            ),
+            ..m
+        })
+        .collect();
+
+    for m in matches {
+        let v = results
+            .entry(m.artists.clone().unwrap_or_else(Vec::new))
+            .or_default();
+        v.push(m);
+    }
+
+    results
+}
+
+/// Look up files similar to the hashes used in the alternate response and add
+/// them to their artist's group, with distances from the searched hash.
+///
+/// Returns false if the lookup found no files.
+async fn expand_alternates(
+    fapi: &dyn ReverseSearch,
+    results: &mut HashMap<Vec<String>, Vec<fuzzysearch::File>>,
+    used_hashes: &[i64],
+    searched_hash: i64,
+) -> anyhow::Result<bool> {
+    let matches = fapi.lookup_hashes(used_hashes, Some(10)).await?;
+
+    if matches.is_empty() {
+        return Ok(false);
+    }
+
+    for m in matches {
+        if let Some(artist) = results.get_mut(&m.artists.clone().unwrap()) {
+            artist.push(fuzzysearch::File {
+                distance: hamming::distance_fast(
+                    &m.hash.unwrap().to_be_bytes(),
+                    &searched_hash.to_be_bytes(),
+                )
+                .ok(),
+                ..m
+            });
+        }
+    }
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use foxbot_sites::{InMemoryReverseSearch, ReverseSearch};
+    use foxbot_utils::{build_alternate_response, get_lang_bundle, lookup_single_hash};
+
+    fn file(site_id: i64) -> fuzzysearch::File {
+        fuzzysearch::File {
+            site_id,
+            artists: Some(vec!["artist".to_string()]),
+            site_info: Some(fuzzysearch::SiteInfo::FurAffinity(
+                fuzzysearch::FurAffinityFile { file_id: 123 },
+            )),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_alternates() {
+        use super::{expand_alternates, group_alternates};
+
+        let mut langs = std::collections::HashMap::new();
+        langs.insert(
+            "en-US".parse().unwrap(),
+            vec![include_str!("../../../langs/en-US/foxbot.ftl").to_string()],
+        );
+        let bundle = get_lang_bundle(&langs, "en-US");
+
+        let search = InMemoryReverseSearch::new();
+        search.insert(0, file(1));
+        search.insert(0b1111_1111, file(2));
+        // Too far from the searched hash, but close to the second file.
+        search.insert(0b1111_1111_1111, file(3));
+
+        let matches = lookup_single_hash(&search, 0, Some(10)).await.unwrap();
+        assert_eq!(matches.len(), 2, "only nearby files should match");
+
+        let mut results = group_alternates(matches);
+        assert_eq!(results.len(), 1, "files should be grouped by artist");
+
+        let items = results.iter().collect::<Vec<_>>();
+        let (text, used_hashes) = build_alternate_response(&bundle, items);
+        assert!(text.contains("furaffinity.net/view/2/"));
+        assert!(!text.contains("furaffinity.net/view/3/"));
+        assert_eq!(used_hashes.len(), 2);
+
+        let search: &dyn ReverseSearch = &search;
+        assert!(expand_alternates(search, &mut results, &used_hashes, 0)
+            .await
+            .unwrap());
+
+        let items = results.iter().collect::<Vec<_>>();
+        let (updated_text, _used_hashes) = build_alternate_response(&bundle, items);
+        assert!(
+            updated_text.contains("furaffinity.net/view/3/"),
+            "alternates should include files similar to displayed results"
+        );
+    }
 }
@@ -6,7 +6,7 @@ use super::{
     Handler,
     Status::{self, *},
 };
-use crate::MessageHandler;
+use crate::{callback::CallbackData, MessageHandler};
 use foxbot_utils::*;
 
 pub struct ErrorCleanup;
@@ -19,15 +19,21 @@ impl Handler for ErrorCleanup {
 
     async fn handle(
         &self,
-        handler: &MessageHandler,
-        update: &Update,
+        _handler: &MessageHandler,
+        _update: &Update,
         _command: Option<&Command>,
     ) -> anyhow::Result<Status> {
-        let callback_query = needs_field!(update, callback_query);
-        let data = needs_field!(callback_query, data);
+        Ok(Ignored)
+    }
 
-        if data != "delete" {
-            return Ok(Ignored);
+    async fn handle_callback(
+        &self,
+        handler: &MessageHandler,
+        callback_query: &CallbackQuery,
+        data: &CallbackData,
+    ) -> anyhow::Result<()> {
+        if data != &CallbackData::Delete {
+            return Ok(());
         }
 
         let message = match &callback_query.message {
@@ -51,7 +57,7 @@ impl Handler for ErrorCleanup {
                     .await
                     .context("Unable to answer callback query for missing message")?;
 
-                return Ok(Completed);
+                return Ok(());
             }
         };
 
@@ -92,6 +98,6 @@ impl Handler for ErrorCleanup {
             .await
             .context("Unable to answer callback query after deleting message")?;
 
-        Ok(Completed)
+        Ok(())
     }
 }
@@ -0,0 +1,62 @@
+use async_trait::async_trait;
+use tgbotapi::{Command, Update};
+
+use super::{
+    Handler,
+    Status::{self, Completed, Ignored},
+};
+use crate::MessageHandler;
+use foxbot_models::{GroupConfig, GroupConfigKey};
+use foxbot_utils::{extract_links, get_faktory_custom, has_media, needs_field};
+
+pub struct GroupMirrorHandler;
+
+#[async_trait]
+impl Handler for GroupMirrorHandler {
+    fn name(&self) -> &'static str {
+        "group_mirror"
+    }
+
+    async fn handle(
+        &self,
+        handler: &MessageHandler,
+        update: &Update,
+        command: Option<&Command>,
+    ) -> anyhow::Result<Status> {
+        let message = needs_field!(update, message);
+
+        if command.is_some()
+            || !message.chat.chat_type.is_group()
+            || message.via_bot.is_some()
+            || has_media(message)
+            || extract_links(message).is_empty()
+        {
+            return Ok(Ignored);
+        }
+
+        if !GroupConfig::get(&handler.conn, message.chat.id, GroupConfigKey::AutoMirror)
+            .await?
+            .unwrap_or(false)
+        {
+            return Ok(Ignored);
+        }
+
+        tracing::debug!("passing group links to background worker");
+
+        let custom = get_faktory_custom();
+
+        let faktory = handler.faktory.clone();
+        let message = message.to_owned();
+        tokio::task::spawn_blocking(move || {
+            let mut faktory = faktory.lock().unwrap();
+            let message = serde_json::to_value(&message).unwrap();
+            let mut job =
+                faktory::Job::new("group_mirror", vec![message]).on_queue("foxbot_background");
+            job.custom = custom;
+
+            faktory.enqueue(job).unwrap();
+        });
+
+        Ok(Completed)
+    }
+}
@@ -0,0 +1,1049 @@
+use anyhow::Context;
+use tgbotapi::{
+    requests::{
+        AnswerCallbackQuery, DeleteMessage, EditMessageText, GetChatMember, ReplyMarkup,
+        SendMessage,
+    },
+    CallbackQuery, Chat, ChatType, InlineKeyboardButton, InlineKeyboardMarkup, Message, User,
+};
+
+use super::settings::answer_callback;
+use crate::{
+    callback::{CallbackData, ChannelCycle, GroupCycle, GroupToggle},
+    MessageHandler,
+};
+use foxbot_models::{
+    ChannelEditStyle, ChannelHashtags, ChatDigest, GroupConfig, GroupConfigKey,
+    GroupSourceDelivery, LinkedForwardRule, NoisySourceRule, Sites,
+};
+use foxbot_utils::{
+    auto_mirror_sites, get_message, group_site_order, ChannelEditPolicy, GroupSourcePolicy,
+    RepostPolicy, LINKED_FORWARD_CHOICES, MAX_SOURCE_LINKS_CHOICES, REPOST_DISTANCE_CHOICES,
+    REPOST_WINDOW_CHOICES, SOURCE_DELETE_AFTER_CHOICES, SOURCE_DISTANCE_CHOICES,
+};
+
+/// Group settings that can be turned on or off, with the key they are
+/// stored as, the name of their label, and if a stored value of true means
+/// the feature is turned off.
+const GROUP_TOGGLES: &[(GroupToggle, GroupConfigKey, &str, bool)] = &[
+    (
+        GroupToggle::Sourcing,
+        GroupConfigKey::GroupAdd,
+        "group-settings-sourcing",
+        false,
+    ),
+    (
+        GroupToggle::Previews,
+        GroupConfigKey::GroupNoPreviews,
+        "group-settings-previews",
+        true,
+    ),
+    (
+        GroupToggle::Albums,
+        GroupConfigKey::GroupNoAlbums,
+        "group-settings-albums",
+        true,
+    ),
+    (
+        GroupToggle::Mirroring,
+        GroupConfigKey::AutoMirror,
+        "group-settings-mirroring",
+        false,
+    ),
+    (
+        GroupToggle::Reposts,
+        GroupConfigKey::RepostDetection,
+        "group-settings-reposts",
+        false,
+    ),
+    (
+        GroupToggle::LinkedReplies,
+        GroupConfigKey::LinkedChannelReplies,
+        "group-settings-linked-replies",
+        false,
+    ),
+];
+
+/// Send the group settings. The user must already be known to be an admin.
+pub async fn send_group_settings(
+    handler: &MessageHandler,
+    message: &Message,
+) -> anyhow::Result<()> {
+    let lang = message
+        .from
+        .as_ref()
+        .and_then(|user| user.language_code.as_deref());
+
+    let (text, keyboard) = group_settings_message(handler, lang, message.chat.id).await?;
+
+    handler
+        .make_request(&SendMessage {
+            chat_id: message.chat_id(),
+            text,
+            reply_to_message_id: Some(message.message_id),
+            reply_markup: Some(ReplyMarkup::InlineKeyboardMarkup(keyboard)),
+            ..Default::default()
+        })
+        .await
+        .context("unable to send group settings message")?;
+
+    Ok(())
+}
+
+/// Send the channel settings to a channel, removing the command. Anyone
+/// able to post in a channel is an admin, so there is nothing to check.
+pub async fn send_channel_settings(
+    handler: &MessageHandler,
+    message: &Message,
+) -> anyhow::Result<()> {
+    let (text, keyboard) = channel_settings_message(handler, None, message.chat.id).await?;
+
+    handler
+        .make_request(&SendMessage {
+            chat_id: message.chat_id(),
+            text,
+            reply_markup: Some(ReplyMarkup::InlineKeyboardMarkup(keyboard)),
+            ..Default::default()
+        })
+        .await
+        .context("unable to send channel settings message")?;
+
+    let delete_message = DeleteMessage {
+        chat_id: message.chat_id(),
+        message_id: message.message_id,
+    };
+
+    if let Err(err) = handler.make_request(&delete_message).await {
+        tracing::warn!("unable to delete channel settings command: {:?}", err);
+    }
+
+    Ok(())
+}
+
+/// Handle a button press on the group settings, after checking the user is
+/// allowed to change them.
+pub async fn handle_callback(
+    handler: &MessageHandler,
+    callback_query: &CallbackQuery,
+    data: &CallbackData,
+) -> anyhow::Result<()> {
+    let reply_message = match &callback_query.message {
+        Some(message) => message,
+        None => return Ok(()),
+    };
+    let chat_id = reply_message.chat.id;
+
+    if !handler
+        .is_chat_admin(chat_id, callback_query.from.id)
+        .await?
+    {
+        return answer_callback(handler, callback_query, "group-settings-not-admin", None).await;
+    }
+
+    let problem = match data {
+        CallbackData::GroupToggle(toggle) => group_toggle(handler, chat_id, *toggle).await?,
+        CallbackData::GroupCycle(cycle) => {
+            group_cycle(handler, chat_id, *cycle).await?;
+            None
+        }
+        CallbackData::GroupSiteToggle { site } => group_site_toggle(handler, chat_id, site).await?,
+        CallbackData::GroupMirrorSiteToggle { site } => {
+            group_mirror_site_toggle(handler, chat_id, site).await?;
+            None
+        }
+        CallbackData::GroupOrderMove { site, pos } => {
+            group_move_site(handler, chat_id, site, *pos).await?;
+            None
+        }
+        CallbackData::ChannelCycle(cycle) => {
+            channel_cycle(handler, chat_id, *cycle).await?;
+            None
+        }
+        CallbackData::ChatDigest => {
+            chat_digest_toggle(handler, &reply_message.chat, &callback_query.from).await?
+        }
+        CallbackData::GroupOrderReset => {
+            GroupConfig::delete(&handler.conn, GroupConfigKey::SiteSortOrder, chat_id)
+                .await
+                .context("unable to reset group site order")?;
+            None
+        }
+        _ => None,
+    };
+
+    if let Some(name) = problem {
+        return answer_callback(handler, callback_query, name, None).await;
+    }
+
+    let lang = callback_query.from.language_code.as_deref();
+    let (text, keyboard) = match data {
+        CallbackData::GroupSites | CallbackData::GroupSiteToggle { .. } => {
+            group_sites_message(handler, lang, chat_id).await?
+        }
+        CallbackData::GroupOrder
+        | CallbackData::GroupOrderMove { .. }
+        | CallbackData::GroupOrderReset => group_order_message(handler, lang, chat_id).await?,
+        CallbackData::GroupMirrorSites | CallbackData::GroupMirrorSiteToggle { .. } => {
+            group_mirror_sites_message(handler, lang, chat_id).await?
+        }
+        CallbackData::ChannelCycle(_) => channel_settings_message(handler, lang, chat_id).await?,
+        CallbackData::ChatDigest if reply_message.chat.chat_type == ChatType::Channel => {
+            channel_settings_message(handler, lang, chat_id).await?
+        }
+        _ => group_settings_message(handler, lang, chat_id).await?,
+    };
+
+    let edit_message = EditMessageText {
+        message_id: Some(reply_message.message_id),
+        chat_id: reply_message.chat_id(),
+        text,
+        reply_markup: Some(ReplyMarkup::InlineKeyboardMarkup(keyboard)),
+        ..Default::default()
+    };
+
+    let answer = AnswerCallbackQuery {
+        callback_query_id: callback_query.id.clone(),
+        ..Default::default()
+    };
+
+    // Editing fails when nothing changed, such as refreshing settings that
+    // are already up to date, so only answering the query matters.
+    if let Err(err) = handler.make_request(&edit_message).await {
+        tracing::debug!("unable to edit group settings: {:?}", err);
+    }
+
+    handler
+        .make_request(&answer)
+        .await
+        .context("unable to answer callback query")?;
+
+    Ok(())
+}
+
+/// Flip a group setting. Returns the name of a message explaining why the
+/// setting could not be changed, if it was not.
+async fn group_toggle(
+    handler: &MessageHandler,
+    chat_id: i64,
+    toggle: GroupToggle,
+) -> anyhow::Result<Option<&'static str>> {
+    let key = match GROUP_TOGGLES.iter().find(|item| item.0 == toggle) {
+        Some((_toggle, key, _label, _inverted)) => *key,
+        None => return Ok(None),
+    };
+
+    let value: bool = GroupConfig::get(&handler.conn, chat_id, key)
+        .await
+        .context("unable to query group toggle")?
+        .unwrap_or(false);
+
+    // Automatic sourcing needs to see every message, which Telegram only
+    // allows for admins.
+    if toggle == GroupToggle::Sourcing
+        && !value
+        && !handler.is_chat_admin(chat_id, handler.bot_user.id).await?
+    {
+        return Ok(Some("automatic-enable-bot-not-admin"));
+    }
+
+    GroupConfig::set(&handler.conn, key, chat_id, !value)
+        .await
+        .context("unable to set group toggle")?;
+
+    Ok(None)
+}
+
+/// Change a group setting to its next value, starting over after the last.
+async fn group_cycle(
+    handler: &MessageHandler,
+    chat_id: i64,
+    cycle: GroupCycle,
+) -> anyhow::Result<()> {
+    let policy = GroupSourcePolicy::load(&handler.conn, chat_id).await?;
+    let repost_policy = RepostPolicy::load(&handler.conn, chat_id).await?;
+
+    match cycle {
+        GroupCycle::Distance => {
+            let value = next_choice(SOURCE_DISTANCE_CHOICES, &policy.max_distance);
+            GroupConfig::set(
+                &handler.conn,
+                GroupConfigKey::SourceDistance,
+                chat_id,
+                value,
+            )
+            .await
+        }
+        GroupCycle::MaxLinks => {
+            let value = next_choice(MAX_SOURCE_LINKS_CHOICES, &policy.max_links);
+            GroupConfig::set(
+                &handler.conn,
+                GroupConfigKey::MaxSourceLinks,
+                chat_id,
+                value,
+            )
+            .await
+        }
+        GroupCycle::NoisyRule => {
+            let value = next_choice(
+                &[
+                    NoisySourceRule::Off,
+                    NoisySourceRule::Twitter,
+                    NoisySourceRule::Any,
+                ],
+                &policy.noisy_rule,
+            );
+            GroupConfig::set(
+                &handler.conn,
+                GroupConfigKey::NoisySourceRule,
+                chat_id,
+                value,
+            )
+            .await
+        }
+        GroupCycle::Delivery => {
+            let value = next_choice(
+                &[
+                    GroupSourceDelivery::Reply,
+                    GroupSourceDelivery::Buttons,
+                    GroupSourceDelivery::Edit,
+                ],
+                &policy.delivery,
+            );
+            GroupConfig::set(
+                &handler.conn,
+                GroupConfigKey::SourceDelivery,
+                chat_id,
+                value,
+            )
+            .await
+        }
+        GroupCycle::DeleteAfter => {
+            let value = next_choice(SOURCE_DELETE_AFTER_CHOICES, &policy.delete_after);
+            GroupConfig::set(
+                &handler.conn,
+                GroupConfigKey::SourceDeleteAfter,
+                chat_id,
+                value,
+            )
+            .await
+        }
+        GroupCycle::LinkedForwards => {
+            let value = next_choice(LINKED_FORWARD_CHOICES, &policy.linked_forwards);
+            GroupConfig::set(
+                &handler.conn,
+                GroupConfigKey::LinkedChannelForwards,
+                chat_id,
+                value,
+            )
+            .await
+        }
+        GroupCycle::RepostDistance => {
+            let value = next_choice(REPOST_DISTANCE_CHOICES, &repost_policy.max_distance);
+            GroupConfig::set(
+                &handler.conn,
+                GroupConfigKey::RepostDistance,
+                chat_id,
+                value,
+            )
+            .await
+        }
+        GroupCycle::RepostWindow => {
+            let value = next_choice(REPOST_WINDOW_CHOICES, &repost_policy.window_hours);
+            GroupConfig::set(
+                &handler.conn,
+                GroupConfigKey::RepostWindowHours,
+                chat_id,
+                value,
+            )
+            .await
+        }
+    }
+    .context("unable to set group value")
+}
+
+/// Change a channel setting to its next value, starting over after the last.
+async fn channel_cycle(
+    handler: &MessageHandler,
+    chat_id: i64,
+    cycle: ChannelCycle,
+) -> anyhow::Result<()> {
+    let policy = ChannelEditPolicy::load(&handler.conn, chat_id).await?;
+
+    match cycle {
+        ChannelCycle::EditStyle => {
+            let value = next_choice(
+                &[
+                    ChannelEditStyle::Buttons,
+                    ChannelEditStyle::Caption,
+                    ChannelEditStyle::Both,
+                ],
+                &policy.style,
+            );
+            GroupConfig::set(
+                &handler.conn,
+                GroupConfigKey::ChannelEditStyle,
+                chat_id,
+                value,
+            )
+            .await
+        }
+        ChannelCycle::ArtistCredit => {
+            GroupConfig::set(
+                &handler.conn,
+                GroupConfigKey::ChannelArtistCredit,
+                chat_id,
+                !policy.artist_credit,
+            )
+            .await
+        }
+        ChannelCycle::Hashtags => {
+            let value = next_choice(
+                &[
+                    ChannelHashtags::Off,
+                    ChannelHashtags::Artist,
+                    ChannelHashtags::Site,
+                    ChannelHashtags::Both,
+                ],
+                &policy.hashtags,
+            );
+            GroupConfig::set(
+                &handler.conn,
+                GroupConfigKey::ChannelHashtags,
+                chat_id,
+                value,
+            )
+            .await
+        }
+    }
+    .context("unable to set channel value")
+}
+
+/// Stop sending a chat's digest if the user already gets it, otherwise send
+/// it to them instead of anyone else. Returns the name of a message
+/// explaining why it could not be sent to the user, if it was not.
+async fn chat_digest_toggle(
+    handler: &MessageHandler,
+    chat: &Chat,
+    user: &User,
+) -> anyhow::Result<Option<&'static str>> {
+    let recipient = ChatDigest::get_user(&handler.conn, chat.id)
+        .await
+        .context("unable to query chat digest")?;
+
+    if recipient == Some(user.id) {
+        ChatDigest::disable(&handler.conn, chat.id)
+            .await
+            .context("unable to disable chat digest")?;
+
+        return Ok(None);
+    }
+
+    let name = if recipient.is_some() {
+        "digest-taken-over"
+    } else {
+        "digest-enabled"
+    };
+
+    let text = handler
+        .get_fluent_bundle(user.language_code.as_deref(), |bundle| {
+            let mut args = fluent::FluentArgs::new();
+            args.insert(
+                "chat",
+                chat.title
+                    .clone()
+                    .unwrap_or_else(|| chat.id.to_string())
+                    .into(),
+            );
+
+            get_message(bundle, name, Some(args)).unwrap()
+        })
+        .await;
+
+    // Bots can only message users who have started them, so make sure the
+    // digest can be delivered before enabling it.
+    let send_message = SendMessage {
+        chat_id: user.id.into(),
+        text,
+        ..Default::default()
+    };
+
+    if let Err(err) = handler.make_request(&send_message).await {
+        tracing::debug!("unable to message user about digest: {:?}", err);
+        return Ok(Some("digest-start-bot"));
+    }
+
+    ChatDigest::enable(
+        &handler.conn,
+        chat.id,
+        user.id,
+        user.language_code.as_deref(),
+    )
+    .await
+    .context("unable to enable chat digest")?;
+
+    Ok(None)
+}
+
+/// Get the name of the admin a chat's digest is sent to, if it is enabled.
+async fn chat_digest_recipient(
+    handler: &MessageHandler,
+    chat_id: i64,
+) -> anyhow::Result<Option<String>> {
+    let user_id = match ChatDigest::get_user(&handler.conn, chat_id)
+        .await
+        .context("unable to query chat digest")?
+    {
+        Some(user_id) => user_id,
+        None => return Ok(None),
+    };
+
+    let get_chat_member = GetChatMember {
+        chat_id: chat_id.into(),
+        user_id,
+    };
+
+    let name = match handler.make_request(&get_chat_member).await {
+        Ok(member) => member.user.first_name,
+        Err(err) => {
+            tracing::debug!("unable to get digest recipient: {:?}", err);
+            user_id.to_string()
+        }
+    };
+
+    Ok(Some(name))
+}
+
+/// Button to change a chat's digest, showing who it is sent to.
+fn chat_digest_button(
+    bundle: &fluent::concurrent::FluentBundle<fluent::FluentResource>,
+    recipient: Option<&str>,
+) -> InlineKeyboardButton {
+    let mut args = fluent::FluentArgs::new();
+    args.insert(
+        "name",
+        get_message(bundle, "settings-digest", None).unwrap().into(),
+    );
+
+    let text = match recipient {
+        Some(recipient) => {
+            args.insert("recipient", recipient.to_string().into());
+            get_message(bundle, "settings-digest-recipient", Some(args)).unwrap()
+        }
+        None => get_message(bundle, "settings-toggle-off", Some(args)).unwrap(),
+    };
+
+    CallbackData::ChatDigest.button(text)
+}
+
+/// Get the choice after the current value, or the first choice if the
+/// current value is last or not a choice.
+fn next_choice<T: Copy + PartialEq>(choices: &[T], current: &T) -> T {
+    choices
+        .iter()
+        .position(|choice| choice == current)
+        .and_then(|pos| choices.get(pos + 1))
+        .copied()
+        .unwrap_or(choices[0])
+}
+
+/// Allow or disallow a site. Returns the name of a message explaining why
+/// the site could not be changed, if it was not.
+async fn group_site_toggle(
+    handler: &MessageHandler,
+    chat_id: i64,
+    site: &Sites,
+) -> anyhow::Result<Option<&'static str>> {
+    let mut sites = GroupSourcePolicy::load(&handler.conn, chat_id).await?.sites;
+
+    if let Some(pos) = sites.iter().position(|item| item == site) {
+        if sites.len() == 1 {
+            return Ok(Some("group-settings-sites-empty"));
+        }
+
+        sites.remove(pos);
+    } else {
+        sites.push(site.clone());
+    }
+
+    GroupConfig::set(&handler.conn, GroupConfigKey::AllowedSites, chat_id, sites)
+        .await
+        .context("unable to set group allowed sites")?;
+
+    Ok(None)
+}
+
+/// Move a site to a new position in the group's site order, starting from
+/// the default order if the group has not chosen one.
+async fn group_move_site(
+    handler: &MessageHandler,
+    chat_id: i64,
+    site: &Sites,
+    pos: usize,
+) -> anyhow::Result<()> {
+    let mut sites = group_site_order(&handler.conn, chat_id)
+        .await?
+        .filter(|sites| sites.len() == Sites::len())
+        .unwrap_or_else(Sites::default_order);
+
+    if let Some(existing_pos) = sites.iter().position(|item| item == site) {
+        sites.remove(existing_pos);
+    }
+
+    sites.insert(pos.min(sites.len()), site.clone());
+
+    GroupConfig::set(&handler.conn, GroupConfigKey::SiteSortOrder, chat_id, sites)
+        .await
+        .context("unable to set group site order")
+}
+
+/// Build the text and keyboard for choosing the order a group shows sources
+/// from sites in.
+async fn group_order_message(
+    handler: &MessageHandler,
+    lang: Option<&str>,
+    chat_id: i64,
+) -> anyhow::Result<(String, InlineKeyboardMarkup)> {
+    let order = group_site_order(&handler.conn, chat_id)
+        .await?
+        .filter(|sites| sites.len() == Sites::len());
+
+    let message = handler
+        .get_fluent_bundle(lang, |bundle| {
+            let name = if order.is_some() {
+                "group-settings-order-main"
+            } else {
+                "group-settings-order-members"
+            };
+            let text = get_message(bundle, name, None).unwrap();
+
+            let sites = order.clone().unwrap_or_else(Sites::default_order);
+            let mut buttons = Vec::with_capacity(sites.len() + 2);
+
+            for (idx, site) in sites.iter().enumerate() {
+                let up = if idx == 0 {
+                    CallbackData::SettingsOrderUnable { site: site.clone() }
+                } else {
+                    CallbackData::GroupOrderMove {
+                        site: site.clone(),
+                        pos: idx - 1,
+                    }
+                };
+
+                let down = if idx == sites.len() - 1 {
+                    CallbackData::SettingsOrderUnable { site: site.clone() }
+                } else {
+                    CallbackData::GroupOrderMove {
+                        site: site.clone(),
+                        pos: idx + 1,
+                    }
+                };
+
+                buttons.push(vec![
+                    CallbackData::SettingsOrderSite { site: site.clone() }
+                        .button(site.as_str().into()),
+                    up.button("⬆".into()),
+                    down.button("⬇".into()),
+                ]);
+            }
+
+            if order.is_some() {
+                buttons.push(vec![CallbackData::GroupOrderReset.button(
+                    get_message(bundle, "group-settings-order-reset", None).unwrap(),
+                )]);
+            }
+
+            buttons.push(vec![CallbackData::GroupSettings
+                .button(get_message(bundle, "group-settings-back", None).unwrap())]);
+
+            (
+                text,
+                InlineKeyboardMarkup {
+                    inline_keyboard: buttons,
+                },
+            )
+        })
+        .await;
+
+    Ok(message)
+}
+
+/// Allow or disallow automatically mirroring links for a site loader.
+async fn group_mirror_site_toggle(
+    handler: &MessageHandler,
+    chat_id: i64,
+    site: &str,
+) -> anyhow::Result<()> {
+    let mut allowed = {
+        let sites = handler.sites.lock().await;
+
+        // Ignore names of loaders that don't exist, such as ones that were
+        // removed after the button was sent.
+        if !sites.iter().any(|loader| loader.name() == site) {
+            return Ok(());
+        }
+
+        auto_mirror_sites(&handler.conn, chat_id, &sites).await?
+    };
+
+    if let Some(pos) = allowed.iter().position(|name| name == site) {
+        allowed.remove(pos);
+    } else {
+        allowed.push(site.to_string());
+    }
+
+    GroupConfig::set(
+        &handler.conn,
+        GroupConfigKey::AutoMirrorSites,
+        chat_id,
+        allowed,
+    )
+    .await
+    .context("unable to set group auto mirror sites")
+}
+
+/// Build the text and keyboard for choosing which sites a group
+/// automatically mirrors links from.
+async fn group_mirror_sites_message(
+    handler: &MessageHandler,
+    lang: Option<&str>,
+    chat_id: i64,
+) -> anyhow::Result<(String, InlineKeyboardMarkup)> {
+    let (names, allowed) = {
+        let sites = handler.sites.lock().await;
+        let names: Vec<&'static str> = sites.iter().map(|site| site.name()).collect();
+        let allowed = auto_mirror_sites(&handler.conn, chat_id, &sites).await?;
+
+        (names, allowed)
+    };
+
+    let message = handler
+        .get_fluent_bundle(lang, |bundle| {
+            let text = get_message(bundle, "group-settings-mirror-main", None).unwrap();
+
+            let mut buttons: Vec<_> = names
+                .into_iter()
+                .map(|name| {
+                    let state = if allowed.iter().any(|site| site == name) {
+                        "settings-toggle-on"
+                    } else {
+                        "settings-toggle-off"
+                    };
+
+                    let mut args = fluent::FluentArgs::new();
+                    args.insert("name", name.into());
+
+                    vec![CallbackData::GroupMirrorSiteToggle {
+                        site: name.to_string(),
+                    }
+                    .button(get_message(bundle, state, Some(args)).unwrap())]
+                })
+                .collect();
+
+            buttons.push(vec![CallbackData::GroupSettings
+                .button(get_message(bundle, "group-settings-back", None).unwrap())]);
+
+            (
+                text,
+                InlineKeyboardMarkup {
+                    inline_keyboard: buttons,
+                },
+            )
+        })
+        .await;
+
+    Ok(message)
+}
+
+/// Build the text and keyboard for choosing which sites a group allows.
+async fn group_sites_message(
+    handler: &MessageHandler,
+    lang: Option<&str>,
+    chat_id: i64,
+) -> anyhow::Result<(String, InlineKeyboardMarkup)> {
+    let allowed = GroupSourcePolicy::load(&handler.conn, chat_id).await?.sites;
+
+    let message = handler
+        .get_fluent_bundle(lang, |bundle| {
+            let text = get_message(bundle, "group-settings-sites-main", None).unwrap();
+
+            let mut buttons: Vec<_> = Sites::default_order()
+                .into_iter()
+                .map(|site| {
+                    let state = if allowed.contains(&site) {
+                        "settings-toggle-on"
+                    } else {
+                        "settings-toggle-off"
+                    };
+
+                    let mut args = fluent::FluentArgs::new();
+                    args.insert("name", site.as_str().into());
+
+                    vec![CallbackData::GroupSiteToggle { site }
+                        .button(get_message(bundle, state, Some(args)).unwrap())]
+                })
+                .collect();
+
+            buttons.push(vec![CallbackData::GroupSettings
+                .button(get_message(bundle, "group-settings-back", None).unwrap())]);
+
+            (
+                text,
+                InlineKeyboardMarkup {
+                    inline_keyboard: buttons,
+                },
+            )
+        })
+        .await;
+
+    Ok(message)
+}
+
+/// Build the group settings text, including warnings about missing
+/// permissions, and a keyboard showing the current values.
+async fn group_settings_message(
+    handler: &MessageHandler,
+    lang: Option<&str>,
+    chat_id: i64,
+) -> anyhow::Result<(String, InlineKeyboardMarkup)> {
+    let mut values = Vec::with_capacity(GROUP_TOGGLES.len());
+    for (_toggle, key, _label, inverted) in GROUP_TOGGLES {
+        let value: bool = GroupConfig::get(&handler.conn, chat_id, *key)
+            .await
+            .context("unable to query group toggle")?
+            .unwrap_or(false);
+        values.push(value != *inverted);
+    }
+
+    let policy = GroupSourcePolicy::load(&handler.conn, chat_id).await?;
+    let repost_policy = RepostPolicy::load(&handler.conn, chat_id).await?;
+
+    let digest = chat_digest_recipient(handler, chat_id).await?;
+
+    let warnings = permission_warnings(handler, chat_id).await?;
+
+    let message = handler
+        .get_fluent_bundle(lang, |bundle| {
+            let mut text = get_message(bundle, "group-settings-main", None).unwrap();
+
+            for warning in warnings {
+                text.push_str("\n\n");
+                text.push_str(&get_message(bundle, warning, None).unwrap());
+            }
+
+            let mut buttons = Vec::with_capacity(GROUP_TOGGLES.len() + 13);
+
+            for ((toggle, _key, label, _inverted), enabled) in GROUP_TOGGLES.iter().zip(values) {
+                let state = if enabled {
+                    "settings-toggle-on"
+                } else {
+                    "settings-toggle-off"
+                };
+
+                let mut args = fluent::FluentArgs::new();
+                args.insert("name", get_message(bundle, label, None).unwrap().into());
+
+                buttons.push(vec![CallbackData::GroupToggle(*toggle)
+                    .button(get_message(bundle, state, Some(args)).unwrap())]);
+            }
+
+            let mut args = fluent::FluentArgs::new();
+            args.insert("distance", policy.max_distance.into());
+            buttons.push(vec![CallbackData::GroupCycle(GroupCycle::Distance).button(
+                get_message(bundle, "group-settings-distance", Some(args)).unwrap(),
+            )]);
+
+            let mut args = fluent::FluentArgs::new();
+            args.insert("count", policy.max_links.into());
+            buttons.push(vec![CallbackData::GroupCycle(GroupCycle::MaxLinks).button(
+                get_message(bundle, "group-settings-max-links", Some(args)).unwrap(),
+            )]);
+
+            let rule = match policy.noisy_rule {
+                NoisySourceRule::Off => "group-settings-noisy-off",
+                NoisySourceRule::Twitter => "group-settings-noisy-twitter",
+                NoisySourceRule::Any => "group-settings-noisy-any",
+            };
+            let mut args = fluent::FluentArgs::new();
+            args.insert("rule", get_message(bundle, rule, None).unwrap().into());
+            buttons.push(vec![CallbackData::GroupCycle(GroupCycle::NoisyRule)
+                .button(
+                    get_message(bundle, "group-settings-noisy", Some(args)).unwrap(),
+                )]);
+
+            let delivery = match policy.delivery {
+                GroupSourceDelivery::Reply => "group-settings-delivery-reply",
+                GroupSourceDelivery::Buttons => "group-settings-delivery-buttons",
+                GroupSourceDelivery::Edit => "group-settings-delivery-edit",
+            };
+            let mut args = fluent::FluentArgs::new();
+            args.insert("mode", get_message(bundle, delivery, None).unwrap().into());
+            buttons.push(vec![CallbackData::GroupCycle(GroupCycle::Delivery).button(
+                get_message(bundle, "group-settings-delivery", Some(args)).unwrap(),
+            )]);
+
+            let mut args = fluent::FluentArgs::new();
+            args.insert("minutes", (policy.delete_after / 60).into());
+            buttons.push(vec![CallbackData::GroupCycle(GroupCycle::DeleteAfter)
+                .button(
+                    get_message(bundle, "group-settings-delete-after", Some(args)).unwrap(),
+                )]);
+
+            let mut args = fluent::FluentArgs::new();
+            args.insert("enabled", policy.sites.len().into());
+            args.insert("total", Sites::len().into());
+            buttons.push(vec![CallbackData::GroupSites.button(
+                get_message(bundle, "group-settings-sites", Some(args)).unwrap(),
+            )]);
+
+            let forwards = match policy.linked_forwards {
+                LinkedForwardRule::Skip => {
+                    get_message(bundle, "group-settings-forwards-skip", None).unwrap()
+                }
+                LinkedForwardRule::Source => {
+                    get_message(bundle, "group-settings-forwards-source", None).unwrap()
+                }
+                LinkedForwardRule::Wait { hours } => {
+                    let mut args = fluent::FluentArgs::new();
+                    args.insert("hours", hours.into());
+                    get_message(bundle, "group-settings-forwards-wait", Some(args)).unwrap()
+                }
+            };
+            let mut args = fluent::FluentArgs::new();
+            args.insert("rule", forwards.into());
+            buttons.push(vec![CallbackData::GroupCycle(GroupCycle::LinkedForwards)
+                .button(
+                    get_message(bundle, "group-settings-forwards", Some(args)).unwrap(),
+                )]);
+
+            let mut args = fluent::FluentArgs::new();
+            args.insert("distance", repost_policy.max_distance.into());
+            buttons.push(vec![CallbackData::GroupCycle(GroupCycle::RepostDistance)
+                .button(
+                    get_message(bundle, "group-settings-repost-distance", Some(args)).unwrap(),
+                )]);
+
+            let mut args = fluent::FluentArgs::new();
+            args.insert("days", (repost_policy.window_hours / 24).into());
+            buttons.push(vec![CallbackData::GroupCycle(GroupCycle::RepostWindow)
+                .button(
+                    get_message(bundle, "group-settings-repost-window", Some(args)).unwrap(),
+                )]);
+
+            buttons.push(vec![CallbackData::GroupOrder
+                .button(get_message(bundle, "group-settings-order", None).unwrap())]);
+
+            buttons.push(vec![CallbackData::GroupMirrorSites.button(
+                get_message(bundle, "group-settings-mirror-sites", None).unwrap(),
+            )]);
+
+            buttons.push(vec![chat_digest_button(bundle, digest.as_deref())]);
+
+            buttons.push(vec![CallbackData::GroupSettings.button(
+                get_message(bundle, "group-settings-refresh", None).unwrap(),
+            )]);
+
+            (
+                text,
+                InlineKeyboardMarkup {
+                    inline_keyboard: buttons,
+                },
+            )
+        })
+        .await;
+
+    Ok(message)
+}
+
+/// Build the channel settings text and a keyboard showing the current
+/// values.
+async fn channel_settings_message(
+    handler: &MessageHandler,
+    lang: Option<&str>,
+    chat_id: i64,
+) -> anyhow::Result<(String, InlineKeyboardMarkup)> {
+    let policy = ChannelEditPolicy::load(&handler.conn, chat_id).await?;
+    let digest = chat_digest_recipient(handler, chat_id).await?;
+
+    let message = handler
+        .get_fluent_bundle(lang, |bundle| {
+            let text = get_message(bundle, "channel-settings-main", None).unwrap();
+
+            let style = match policy.style {
+                ChannelEditStyle::Buttons => "channel-settings-style-buttons",
+                ChannelEditStyle::Caption => "channel-settings-style-caption",
+                ChannelEditStyle::Both => "channel-settings-style-both",
+            };
+            let mut args = fluent::FluentArgs::new();
+            args.insert("style", get_message(bundle, style, None).unwrap().into());
+            let style_button = CallbackData::ChannelCycle(ChannelCycle::EditStyle)
+                .button(get_message(bundle, "channel-settings-style", Some(args)).unwrap());
+
+            let state = if policy.artist_credit {
+                "settings-toggle-on"
+            } else {
+                "settings-toggle-off"
+            };
+            let mut args = fluent::FluentArgs::new();
+            args.insert(
+                "name",
+                get_message(bundle, "channel-settings-credit", None)
+                    .unwrap()
+                    .into(),
+            );
+            let credit_button = CallbackData::ChannelCycle(ChannelCycle::ArtistCredit)
+                .button(get_message(bundle, state, Some(args)).unwrap());
+
+            let hashtags = match policy.hashtags {
+                ChannelHashtags::Off => "channel-settings-hashtags-off",
+                ChannelHashtags::Artist => "channel-settings-hashtags-artist",
+                ChannelHashtags::Site => "channel-settings-hashtags-site",
+                ChannelHashtags::Both => "channel-settings-hashtags-both",
+            };
+            let mut args = fluent::FluentArgs::new();
+            args.insert("tags", get_message(bundle, hashtags, None).unwrap().into());
+            let hashtags_button = CallbackData::ChannelCycle(ChannelCycle::Hashtags)
+                .button(get_message(bundle, "channel-settings-hashtags", Some(args)).unwrap());
+
+            (
+                text,
+                InlineKeyboardMarkup {
+                    inline_keyboard: vec![
+                        vec![style_button],
+                        vec![credit_button],
+                        vec![hashtags_button],
+                        vec![chat_digest_button(bundle, digest.as_deref())],
+                    ],
+                },
+            )
+        })
+        .await;
+
+    Ok(message)
+}
+
+/// Check the bot's current permissions in a group, returning the names of
+/// messages explaining what features won't work.
+async fn permission_warnings(
+    handler: &MessageHandler,
+    chat_id: i64,
+) -> anyhow::Result<Vec<&'static str>> {
+    use tgbotapi::ChatMemberStatus::*;
+
+    let bot_member = handler
+        .make_request(&GetChatMember {
+            chat_id: chat_id.into(),
+            user_id: handler.bot_user.id,
+        })
+        .await
+        .context("unable to get bot permissions")?;
+
+    let mut warnings = Vec::new();
+
+    if !matches!(bot_member.status, Administrator | Creator) {
+        warnings.push("group-settings-warning-admin");
+    } else if !bot_member.can_delete_messages.unwrap_or(false) {
+        warnings.push("group-settings-warning-delete");
+    }
+
+    Ok(warnings)
+}
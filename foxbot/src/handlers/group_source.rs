@@ -6,6 +6,7 @@ use super::{
     Status::{self, Completed, Ignored},
 };
 use crate::MessageHandler;
+use foxbot_models::{GroupConfig, GroupConfigKey, GroupSourceDelivery};
 use foxbot_utils::{get_faktory_custom, needs_field};
 
 pub struct GroupSourceHandler;
@@ -25,8 +26,19 @@ impl Handler for GroupSourceHandler {
         let message = needs_field!(update, message);
         needs_field!(message, photo);
 
+        // Images sent through the bot already have sources, so they only
+        // need to be looked at if the group wants sources added to them.
         if matches!(message.via_bot, Some(tgbotapi::User { id, .. }) if id == handler.bot_user.id) {
-            return Ok(Ignored);
+            let delivery = GroupConfig::get(
+                &handler.conn,
+                message.chat.id,
+                GroupConfigKey::SourceDelivery,
+            )
+            .await?;
+
+            if delivery != Some(GroupSourceDelivery::Edit) {
+                return Ok(Ignored);
+            }
         }
 
         tracing::debug!("passing group photo to background worker");
@@ -7,14 +7,22 @@ use super::{
     Handler,
     Status::{self, *},
 };
-use crate::{MessageHandler, ServiceData};
-use foxbot_models::{DisplayableErrorMessage, Video};
+use crate::{callback::CallbackData, registry::ParsedCommand, MessageHandler, ServiceData};
+use foxbot_models::{
+    CachedPost, DisplayableErrorMessage, DocumentCache, InlineHistory, SharedPost, UserConfig,
+    UserConfigKey, Video, VideoState,
+};
 use foxbot_sites::PostInfo;
 use foxbot_utils::*;
 
 /// Telegram allows inline results up to 5MB.
 static MAX_IMAGE_SIZE: usize = 5_000_000;
 
+/// How long a video may go without progress before the job is restarted.
+const VIDEO_STALL_SECONDS: i64 = 60 * 10;
+/// How many times a video is sent to the transcoder before giving up.
+const VIDEO_MAX_ATTEMPTS: i32 = 3;
+
 pub struct InlineHandler;
 
 #[derive(PartialEq)]
@@ -46,14 +54,7 @@ impl InlineHandler {
             .await?
             .expect("missing video");
 
-        if video.job_id.is_none() {
-            let job_id = handler
-                .coconut
-                .start_video(&video.url, &video.display_name)
-                .await?;
-
-            Video::set_job_id(&handler.conn, video.id, job_id).await?;
-        }
+        start_video(handler, &video).await?;
 
         let lang = message
             .from
@@ -91,6 +92,8 @@ impl InlineHandler {
             .await?
             .context("Video was missing")?;
         tracing::Span::current().record("video_id", &video.id);
+        Video::touch_progress(&handler.conn, video.id).await?;
+
         let messages = Video::associated_messages(&handler.conn, video.id).await?;
 
         let msg = handler
@@ -174,13 +177,6 @@ impl InlineHandler {
         let message = handler.make_request(&send_video).await?;
         drop(action);
 
-        if messages.is_empty() {
-            return Ok(());
-        }
-
-        // Now that we've sent the first message, send each additional message
-        // with the returned video ID.
-
         let file = if let Some(video) = message.video {
             SentAs::Video(video.file_id)
         } else if let Some(animation) = message.animation {
@@ -191,6 +187,18 @@ impl InlineHandler {
             anyhow::bail!("Sent video was missing all known file IDs");
         };
 
+        // Only videos can be returned as cached inline results.
+        if let SentAs::Video(ref file_id) = file {
+            Video::set_file_id(&handler.conn, video.id, file_id).await?;
+        }
+
+        if messages.is_empty() {
+            return Ok(());
+        }
+
+        // Now that we've sent the first message, send each additional message
+        // with the returned video ID.
+
         tracing::debug!(?file, "Sent video, reusing for additional messages");
 
         let reply_markup = Some(ReplyMarkup::InlineKeyboardMarkup(InlineKeyboardMarkup {
@@ -242,6 +250,137 @@ impl InlineHandler {
 
         Ok(())
     }
+
+    /// Restart a failed video when the user presses the retry button.
+    async fn retry_video(
+        &self,
+        handler: &MessageHandler,
+        callback: &CallbackQuery,
+        display_name: &str,
+    ) -> anyhow::Result<()> {
+        let video = Video::lookup_display_name(&handler.conn, display_name)
+            .await?
+            .context("Video was missing")?;
+
+        tracing::info!(video_id = video.id, "user retried video");
+
+        handler
+            .make_request(&AnswerCallbackQuery {
+                callback_query_id: callback.id.clone(),
+                ..Default::default()
+            })
+            .await?;
+
+        // Videos that finished have already been sent to everyone waiting.
+        if video.state == VideoState::Done {
+            return Ok(());
+        }
+
+        start_video(handler, &video).await?;
+
+        if let Some(message) = &callback.message {
+            let text = handler
+                .get_fluent_bundle(callback.from.language_code.as_deref(), |bundle| {
+                    get_message(bundle, "video-starting", None).unwrap()
+                })
+                .await;
+
+            handler
+                .make_request(&EditMessageText {
+                    chat_id: message.chat_id(),
+                    message_id: Some(message.message_id),
+                    text,
+                    ..Default::default()
+                })
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Send a video to the transcoder, unless it is already encoding or done.
+async fn start_video(handler: &MessageHandler, video: &Video) -> anyhow::Result<()> {
+    if !Video::claim_pending(&handler.conn, video.id).await? {
+        tracing::debug!(state = ?video.state, "video was already started");
+        return Ok(());
+    }
+
+    match handler.transcoder.start_video(video).await {
+        Ok(job_id) => Video::set_job_id(&handler.conn, video.id, job_id).await,
+        Err(err) => {
+            Video::set_failed(&handler.conn, video.id).await?;
+            Err(err)
+        }
+    }
+}
+
+/// Find videos that have stopped making progress. Each is restarted until it
+/// runs out of attempts, then marked as failed and users waiting on it are
+/// offered a button to try again.
+pub async fn check_stalled_videos(handler: &MessageHandler) -> anyhow::Result<()> {
+    let videos = Video::stalled(&handler.conn, VIDEO_STALL_SECONDS).await?;
+
+    for video in videos {
+        if video.attempts < VIDEO_MAX_ATTEMPTS {
+            tracing::warn!(
+                video_id = video.id,
+                attempts = video.attempts,
+                "video stalled, restarting"
+            );
+
+            match handler.transcoder.start_video(&video).await {
+                Ok(job_id) => {
+                    Video::set_job_id(&handler.conn, video.id, job_id).await?;
+                    continue;
+                }
+                Err(err) => tracing::error!("unable to restart video: {:?}", err),
+            }
+        }
+
+        tracing::warn!(video_id = video.id, "video failed");
+        fail_video(handler, &video).await?;
+    }
+
+    Ok(())
+}
+
+/// Mark a video as failed and offer users waiting on it a button to try
+/// again.
+async fn fail_video(handler: &MessageHandler, video: &Video) -> anyhow::Result<()> {
+    Video::set_failed(&handler.conn, video.id).await?;
+
+    let (text, retry) = handler
+        .get_fluent_bundle(None, |bundle| {
+            (
+                get_message(bundle, "video-failed", None).unwrap(),
+                get_message(bundle, "video-retry", None).unwrap(),
+            )
+        })
+        .await;
+
+    let reply_markup = InlineKeyboardMarkup {
+        inline_keyboard: vec![vec![CallbackData::VideoRetry {
+            display_name: video.display_name.clone(),
+        }
+        .button(retry)]],
+    };
+
+    for (chat_id, message_id) in Video::associated_messages(&handler.conn, video.id).await? {
+        let edit_message = EditMessageText {
+            chat_id: chat_id.into(),
+            message_id: Some(message_id),
+            text: text.clone(),
+            reply_markup: Some(ReplyMarkup::InlineKeyboardMarkup(reply_markup.clone())),
+            ..Default::default()
+        };
+
+        if let Err(err) = handler.make_request(&edit_message).await {
+            tracing::warn!("unable to edit message for failed video: {:?}", err);
+        }
+    }
+
+    Ok(())
 }
 
 #[async_trait]
@@ -256,20 +395,6 @@ impl Handler for InlineHandler {
         update: &Update,
         _command: Option<&Command>,
     ) -> anyhow::Result<Status> {
-        if let Some(message) = &update.message {
-            match message.get_command() {
-                Some(cmd) if cmd.name == "/start" => {
-                    if let Some(text) = &message.text {
-                        if text.contains("process-") {
-                            self.process_video(handler, message).await?;
-                            return Ok(Completed);
-                        }
-                    }
-                }
-                _ => (),
-            }
-        }
-
         let inline = needs_field!(update, inline_query);
 
         let links: Vec<_> = handler.finder.links(&inline.query).collect();
@@ -327,21 +452,54 @@ impl Handler for InlineHandler {
 
         let is_personal = results.iter().any(|result| result.personal);
 
-        let include_tags = inline.query.contains("#tags");
-        let include_info = inline.query.contains("#info") || include_tags;
+        let options = ResultOptions::load(handler, &inline.query, inline.from.id).await?;
+        let options = &options;
 
         let mut futs: FuturesOrdered<_> = results
             .iter()
-            .map(|result| process_result(handler, result, &inline.from, include_info, include_tags))
+            .map(|result| async move {
+                let items = process_result(handler, result, &inline.from, options).await;
+                (result, items)
+            })
             .collect();
 
         let mut responses: Vec<(ResultType, InlineQueryResult)> = vec![];
-        while let Some(item) = futs.next().await {
+        let mut shared_posts: Vec<(String, SharedPost)> = vec![];
+        while let Some((result, item)) = futs.next().await {
             if let Ok(Some(items)) = item {
+                let post = shared_post(handler, result).await;
+
+                shared_posts.extend(
+                    items
+                        .iter()
+                        .filter(|item| item.0 == ResultType::Ready)
+                        .map(|item| (item.1.id.clone(), post.clone())),
+                );
                 responses.extend(items);
             }
         }
 
+        // An empty query has nothing to search, so show what the user
+        // recently sent instead.
+        let mut has_history = false;
+        if inline.query.is_empty() {
+            match build_history_results(handler, &inline.from).await {
+                Ok(items) => {
+                    has_history = !items.is_empty();
+
+                    for (item, post) in items {
+                        shared_posts.push((item.id.clone(), post));
+                        responses.push((ResultType::Ready, item));
+                    }
+                }
+                Err(err) => tracing::error!("unable to load inline history: {:?}", err),
+            }
+        }
+
+        if let Err(err) = InlineHistory::stash_results(&handler.redis, &shared_posts).await {
+            tracing::error!("unable to stash inline results: {:?}", err);
+        }
+
         // If we had no responses but the query was not empty, there were likely links
         // that we were unable to convert. We need to display that the links had no results.
         if responses.is_empty() && !inline.query.is_empty() {
@@ -358,6 +516,22 @@ impl Handler for InlineHandler {
             responses.push((ResultType::Ready, article));
         }
 
+        // Originals can only be sent through the file cache chat, so let the
+        // user know why they got regular images.
+        if inline.query.contains("#file") && handler.config.file_cache_chat.is_none() {
+            let article = handler
+                .get_fluent_bundle(inline.from.language_code.as_deref(), |bundle| {
+                    InlineQueryResult::article(
+                        generate_id(),
+                        get_message(bundle, "inline-file-unavailable-title", None).unwrap(),
+                        get_message(bundle, "inline-file-unavailable-body", None).unwrap(),
+                    )
+                })
+                .await;
+
+            responses.insert(0, (ResultType::Ready, article));
+        }
+
         // Check if we need to process any videos contained within the results.
         // These don't get returned as regular inline results.
         let has_video: Option<InlineQueryResult> = responses
@@ -375,10 +549,16 @@ impl Handler for InlineHandler {
         let mut answer_inline = AnswerInlineQuery {
             inline_query_id: inline.id.to_owned(),
             results: cleaned_responses,
-            is_personal: Some(is_personal),
+            is_personal: Some(is_personal || has_history),
             ..Default::default()
         };
 
+        // History changes every time something is sent, so it shouldn't be
+        // cached for long.
+        if has_history {
+            answer_inline.cache_time = Some(10);
+        }
+
         // If the query was empty, display a help button to make it easy to get
         // started using the bot.
         if inline.query.is_empty() {
@@ -417,6 +597,37 @@ impl Handler for InlineHandler {
         Ok(Completed)
     }
 
+    async fn handle_command(
+        &self,
+        handler: &MessageHandler,
+        message: &Message,
+        command: &ParsedCommand,
+    ) -> anyhow::Result<()> {
+        // Videos are processed after a user follows a link back to the bot
+        // from an inline query, which starts with a payload.
+        match command.args.text() {
+            Some(payload) if payload.starts_with("process-") => {
+                self.process_video(handler, message).await
+            }
+            _ => handler.handle_welcome(message, "/start").await,
+        }
+    }
+
+    async fn handle_callback(
+        &self,
+        handler: &MessageHandler,
+        callback_query: &CallbackQuery,
+        data: &CallbackData,
+    ) -> anyhow::Result<()> {
+        match data {
+            CallbackData::VideoRetry { display_name } => {
+                self.retry_video(handler, callback_query, display_name)
+                    .await
+            }
+            _ => Ok(()),
+        }
+    }
+
     async fn handle_service(
         &self,
         handler: &MessageHandler,
@@ -435,62 +646,191 @@ impl Handler for InlineHandler {
                 self.video_complete(handler, display_name, video_url, thumb_url)
                     .await
             }
+            ServiceData::VideoFailed { display_name } => {
+                let video = Video::lookup_display_name(&handler.conn, display_name)
+                    .await?
+                    .context("Video was missing")?;
+
+                tracing::warn!(video_id = video.id, "video transcode failed");
+                fail_video(handler, &video).await
+            }
             _ => Ok(()),
         }
     }
 }
 
-/// Convert a [PostInfo] struct into an InlineQueryResult.
-///
-/// It adds an inline keyboard for the direct link and source if available.
-async fn process_result(
+/// Create the information needed to send a result again.
+async fn shared_post(handler: &MessageHandler, result: &PostInfo) -> SharedPost {
+    let url_id = {
+        let source = result.source_link.as_deref().unwrap_or(&result.url);
+        let sites = handler.sites.lock().await;
+        sites.iter().find_map(|site| site.url_id(source))
+    };
+
+    SharedPost {
+        site: result.site_name.to_string(),
+        url_id,
+        source_link: result.source_link.clone(),
+        url: result.url.clone(),
+        thumb_url: result.thumb.clone(),
+        file_type: result.file_type.clone(),
+    }
+}
+
+/// Number of recently sent posts to show for an empty query.
+const HISTORY_RESULTS: i64 = 10;
+
+/// Build results for posts the user recently sent. Only media that can be
+/// sent without further processing is included.
+async fn build_history_results(
     handler: &MessageHandler,
-    result: &PostInfo,
     from: &User,
+) -> anyhow::Result<Vec<(InlineQueryResult, SharedPost)>> {
+    let posts = InlineHistory::recent(&handler.conn, from.id, HISTORY_RESULTS).await?;
+
+    let mut results = Vec::with_capacity(posts.len());
+
+    for post in posts {
+        let thumb_url = post.thumb_url.clone().unwrap_or_else(|| post.url.clone());
+
+        let mut item = match post.file_type.as_ref() {
+            "png" | "jpeg" | "jpg" => {
+                // Large images had to be cached to be sent, so reuse the
+                // cached copy if there is one.
+                match CachedPost::get(&handler.conn, &post.url, false).await? {
+                    Some(CachedPost {
+                        file_id: Some(file_id),
+                        ..
+                    }) => InlineQueryResult::cached_photo(generate_id(), file_id),
+                    Some(cached) => {
+                        InlineQueryResult::photo(generate_id(), cached.cdn_url, thumb_url)
+                    }
+                    None => InlineQueryResult::photo(generate_id(), post.url.clone(), thumb_url),
+                }
+            }
+            "gif" => InlineQueryResult::gif(generate_id(), post.url.clone(), thumb_url),
+            "mp4" => InlineQueryResult::video(
+                generate_id(),
+                post.url.clone(),
+                "video/mp4".to_string(),
+                thumb_url,
+                post.site.clone(),
+            ),
+            _ => continue,
+        };
+
+        item.reply_markup = Some(
+            handler
+                .post_keyboard(
+                    from.language_code.as_deref(),
+                    &post.url,
+                    post.source_link.as_deref(),
+                    &post.site,
+                )
+                .await,
+        );
+
+        results.push((item, post));
+    }
+
+    Ok(results)
+}
+
+/// Options for building results, from flags in the query and the user's
+/// defaults.
+struct ResultOptions {
     include_info: bool,
     include_tags: bool,
-) -> anyhow::Result<Option<Vec<(ResultType, InlineQueryResult)>>> {
-    let direct = handler
-        .get_fluent_bundle(from.language_code.as_deref(), |bundle| {
-            get_message(bundle, "inline-direct", None).unwrap()
-        })
-        .await;
+    as_document: bool,
+    caption_template: Option<String>,
+}
 
-    let mut row = vec![InlineKeyboardButton {
-        text: direct,
-        url: Some(result.url.clone()),
-        callback_data: None,
-        ..Default::default()
-    }];
+impl ResultOptions {
+    async fn load(handler: &MessageHandler, query: &str, user_id: i64) -> anyhow::Result<Self> {
+        let get_flag = |key| async move {
+            UserConfig::get::<bool>(&handler.conn, key, user_id)
+                .await
+                .map(|value| value.unwrap_or(false))
+        };
 
-    if let Some(source_link) = &result.source_link {
-        let text = result.site_name.to_string();
+        let include_tags = query.contains("#tags") || get_flag(UserConfigKey::InlineTags).await?;
+        let include_info =
+            query.contains("#info") || include_tags || get_flag(UserConfigKey::InlineInfo).await?;
+        let as_document = query.contains("#file") || get_flag(UserConfigKey::SendOriginals).await?;
 
-        row.push(InlineKeyboardButton {
-            text,
-            url: Some(source_link.clone()),
-            callback_data: None,
-            ..Default::default()
+        let caption_template =
+            UserConfig::get(&handler.conn, UserConfigKey::CaptionTemplate, user_id).await?;
+
+        Ok(Self {
+            include_info,
+            include_tags,
+            as_document,
+            caption_template,
         })
     }
 
-    let keyboard = InlineKeyboardMarkup {
-        inline_keyboard: vec![row],
-    };
+    /// The template for captions, if a caption was requested. The user's
+    /// own template replaces the default info and tags layout.
+    fn template(&self) -> Option<String> {
+        if !self.include_info && !self.include_tags {
+            return None;
+        }
+
+        if let Some(template) = &self.caption_template {
+            return Some(template.to_owned());
+        }
+
+        let mut templates = Vec::with_capacity(2);
+
+        if self.include_info {
+            templates.push(DEFAULT_INFO_TEMPLATE);
+        }
+
+        if self.include_tags {
+            templates.push(DEFAULT_TAGS_TEMPLATE);
+        }
+
+        Some(templates.join("\n"))
+    }
+}
+
+/// Convert a [PostInfo] struct into an InlineQueryResult.
+///
+/// It adds an inline keyboard for the direct link and source if available.
+async fn process_result(
+    handler: &MessageHandler,
+    result: &PostInfo,
+    from: &User,
+    options: &ResultOptions,
+) -> anyhow::Result<Option<Vec<(ResultType, InlineQueryResult)>>> {
+    let keyboard = handler
+        .post_keyboard(
+            from.language_code.as_deref(),
+            &result.url,
+            result.source_link.as_deref(),
+            result.site_name,
+        )
+        .await;
 
     let thumb_url = result.thumb.clone().unwrap_or_else(|| result.url.clone());
 
     match result.file_type.as_ref() {
+        "png" | "jpeg" | "jpg"
+            if options.as_document && handler.config.file_cache_chat.is_some() =>
+        {
+            match build_document_result(handler, result, &keyboard).await {
+                Ok(results) => Ok(Some(results)),
+                Err(err) => {
+                    tracing::warn!("unable to send original, using image: {:?}", err);
+                    Ok(Some(
+                        build_image_result(handler, result, from, thumb_url, &keyboard, options)
+                            .await?,
+                    ))
+                }
+            }
+        }
         "png" | "jpeg" | "jpg" => Ok(Some(
-            build_image_result(
-                handler,
-                result,
-                thumb_url,
-                &keyboard,
-                include_info,
-                include_tags,
-            )
-            .await?,
+            build_image_result(handler, result, from, thumb_url, &keyboard, options).await?,
         )),
         "webm" => {
             let source = match &result.source_link {
@@ -522,36 +862,13 @@ async fn process_result(
     }
 }
 
-fn escape_markdown<S: AsRef<str>>(input: S) -> String {
-    input
-        .as_ref()
-        .replace("_", r"\_")
-        .replace("-", r"\-")
-        .replace("!", r"\!")
-        .replace(".", r"\.")
-        .replace("(", r"\(")
-        .replace(")", r"\)")
-        .replace("[", r"\[")
-        .replace("]", r"\]")
-        .replace("{", r"\{")
-        .replace("}", r"\}")
-        .replace("*", r"\*")
-        .replace("#", r"\#")
-        .replace("`", r"\`")
-        .replace("+", r"\+")
-        .replace("=", r"\=")
-        .replace(">", r"\>")
-        .replace("|", r"\|")
-        .replace("~", r"\~")
-}
-
 async fn build_image_result(
     handler: &MessageHandler,
     result: &PostInfo,
+    from: &User,
     thumb_url: String,
     keyboard: &InlineKeyboardMarkup,
-    include_info: bool,
-    include_tags: bool,
+    options: &ResultOptions,
 ) -> anyhow::Result<Vec<(ResultType, InlineQueryResult)>> {
     let mut result = result.to_owned();
     result.thumb = Some(thumb_url);
@@ -565,88 +882,67 @@ async fn build_image_result(
     // Telegram Desktop[^1].
     //
     // [^1]: https://github.com/telegramdesktop/tdesktop/issues/4580
-    let data = download_image(&result.url).await?;
-    let result = if handler.config.cache_all_images.unwrap_or(false) {
-        cache_post(
-            &handler.conn,
-            &handler.s3,
-            &handler.config.s3_bucket,
-            &handler.config.s3_url,
-            &result,
-            &data,
-        )
-        .await?
-    } else {
-        let result = size_post(&result, &data).await?;
-
-        if result.image_size.unwrap_or_default() > MAX_IMAGE_SIZE {
-            cache_post(
-                &handler.conn,
-                &handler.s3,
-                &handler.config.s3_bucket,
-                &handler.config.s3_url,
-                &result,
-                &data,
-            )
-            .await?
-        } else {
-            result
+    //
+    // Images that were cached may already have a file ID from a previous
+    // time they were sent, so none of this is needed. If not, the result ID
+    // lets the chosen inline handler find the image to save one.
+    let original_url = result.url.clone();
+    let cached = CachedPost::get(&handler.conn, &original_url, false).await?;
+
+    let result = match &cached {
+        Some(CachedPost {
+            file_id: Some(_), ..
+        }) => result,
+        _ => {
+            let data = download_image(&result.url).await?;
+
+            if handler.config.cache_all_images.unwrap_or(false) {
+                cache_post(&handler.conn, handler.storage.as_ref(), &result, &data).await?
+            } else {
+                let result = size_post(&result, &data).await?;
+
+                if result.image_size.unwrap_or_default() > MAX_IMAGE_SIZE {
+                    cache_post(&handler.conn, handler.storage.as_ref(), &result, &data).await?
+                } else {
+                    result
+                }
+            }
         }
     };
 
-    let mut photo = InlineQueryResult::photo(
-        generate_id(),
-        result.url.to_owned(),
-        result.thumb.clone().unwrap(),
-    );
-
-    let mut data = Vec::new();
-
-    if include_info {
-        if let Some(title) = result.title {
-            data.push(format!("Title: {}", escape_markdown(title)));
-        }
-
-        match (result.artist_username, result.artist_url) {
-            (Some(username), None) => data.push(format!("Artist: {}", escape_markdown(username))),
-            (Some(username), Some(url)) => {
-                data.push(format!("Artist: [{}]({})", escape_markdown(username), url))
-            }
-            (None, Some(url)) => data.push(format!("Artist: {}", escape_markdown(url))),
-            (None, None) => (),
-        }
-    }
+    // Only use the cached image if it has a file ID or was used this time.
+    let cached = cached.filter(|cached| cached.file_id.is_some() || result.url != original_url);
+
+    let (url, thumb) = (result.url.clone(), result.thumb.clone().unwrap());
+    let make_photo = || match &cached {
+        Some(CachedPost {
+            file_id: Some(file_id),
+            ..
+        }) => InlineQueryResult::cached_photo(generate_id(), file_id.to_owned()),
+        Some(cached) => InlineQueryResult::photo(
+            format!("cached-{}-{}", cached.id, generate_id()),
+            url.clone(),
+            thumb.clone(),
+        ),
+        None => InlineQueryResult::photo(generate_id(), url.clone(), thumb.clone()),
+    };
 
-    if include_tags {
-        match result.tags {
-            Some(tags) if !tags.is_empty() => {
-                data.push(format!(
-                    "Tags: {}",
-                    tags.iter()
-                        // Tags can't be all numbers, and no transformation can fix that
-                        .filter(|tag| tag.parse::<i64>().is_err())
-                        // Escape tags for Markdown formatting and replace unsupported symbols
-                        .map(|tag| escape_markdown(format!(
-                            "#{}",
-                            tag.replace(' ', "_")
-                                .replace('/', "_")
-                                .replace('(', "")
-                                .replace(')', "")
-                                .replace('-', "_")
-                        )))
-                        .take(50)
-                        .collect::<Vec<_>>()
-                        .join(" ")
-                ));
-            }
-            _ => (),
-        }
-    }
+    let mut photo = make_photo();
+
+    if let Some(template) = options.template() {
+        let rating = match get_rating_bundle_name(&result.rating) {
+            Some(name) => Some(
+                handler
+                    .get_fluent_bundle(from.language_code.as_deref(), |bundle| {
+                        get_message(bundle, name, None).unwrap()
+                    })
+                    .await,
+            ),
+            None => None,
+        };
 
-    if !data.is_empty() {
-        if let InlineQueryType::Photo(ref mut photo) = photo.content {
-            photo.parse_mode = Some(ParseMode::MarkdownV2);
-            photo.caption = Some(data.join("\n"));
+        if let Some(caption) = render_caption(&template, &result, rating.as_deref()) {
+            set_photo_caption(&mut photo, caption, Some(ParseMode::MarkdownV2));
         }
     }
 
@@ -662,12 +958,12 @@ async fn build_image_result(
     let mut results = vec![(ResultType::Ready, photo)];
 
     if let Some(message) = &result.extra_caption {
-        let mut photo = InlineQueryResult::photo(generate_id(), result.url, result.thumb.unwrap());
+        let mut photo = make_photo();
         photo.reply_markup = Some(keyboard.clone());
 
-        if let InlineQueryType::Photo(ref mut photo) = photo.content {
-            photo.caption = Some(message.to_string());
+        set_photo_caption(&mut photo, message.to_string(), None);
 
+        if let InlineQueryType::Photo(ref mut photo) = photo.content {
             if let Some(dims) = result.image_dimensions {
                 photo.photo_width = Some(dims.0);
                 photo.photo_height = Some(dims.1);
@@ -680,6 +976,73 @@ async fn build_image_result(
     Ok(results)
 }
 
+/// Build a result that sends the untouched original image as a document.
+///
+/// Inline results can only contain documents that Telegram already has, so
+/// the original is first sent to the file cache chat to get a file ID.
+async fn build_document_result(
+    handler: &MessageHandler,
+    result: &PostInfo,
+    keyboard: &InlineKeyboardMarkup,
+) -> anyhow::Result<Vec<(ResultType, InlineQueryResult)>> {
+    let chat_id = handler
+        .config
+        .file_cache_chat
+        .context("documents require a file cache chat")?;
+
+    let file_id = match DocumentCache::get(&handler.redis, &result.url).await? {
+        Some(file_id) => file_id,
+        None => {
+            let message = handler
+                .send_original(chat_id.into(), &result.url, &result.file_type, None, None)
+                .await?;
+
+            if let Err(err) = handler
+                .make_request(&DeleteMessage {
+                    chat_id: chat_id.into(),
+                    message_id: message.message_id,
+                })
+                .await
+            {
+                tracing::warn!("unable to delete file cache message: {:?}", err);
+            }
+
+            message
+                .document
+                .context("original was not sent as a document")?
+                .file_id
+        }
+    };
+
+    let mut document = InlineQueryResult::cached_document(
+        generate_id(),
+        original_file_name(&result.url, &result.file_type),
+        file_id,
+    );
+    document.reply_markup = Some(keyboard.clone());
+
+    Ok(vec![(ResultType::Ready, document)])
+}
+
+/// Set the caption on a photo result, whether or not it is cached.
+fn set_photo_caption(
+    result: &mut InlineQueryResult,
+    caption: String,
+    parse_mode: Option<ParseMode>,
+) {
+    match result.content {
+        InlineQueryType::Photo(ref mut photo) => {
+            photo.caption = Some(caption);
+            photo.parse_mode = parse_mode;
+        }
+        InlineQueryType::CachedPhoto(ref mut photo) => {
+            photo.caption = Some(caption);
+            photo.parse_mode = parse_mode;
+        }
+        _ => (),
+    }
+}
+
 async fn build_webm_result(
     conn: &sqlx::Pool<sqlx::Postgres>,
     result: &PostInfo,
@@ -717,30 +1080,36 @@ async fn build_webm_result(
     };
 
     let full_url = video.mp4_url.unwrap();
+    let file_id = video.file_id;
 
-    let mut video = InlineQueryResult::video(
-        generate_id(),
-        full_url.to_owned(),
-        "video/mp4".to_owned(),
-        thumb_url.to_owned(),
-        result.url.clone(),
-    );
+    let make_video = || match &file_id {
+        Some(file_id) => {
+            InlineQueryResult::cached_video(generate_id(), file_id.to_owned(), result.url.clone())
+        }
+        None => InlineQueryResult::video(
+            generate_id(),
+            full_url.to_owned(),
+            "video/mp4".to_owned(),
+            thumb_url.to_owned(),
+            result.url.clone(),
+        ),
+    };
+
+    let mut video = make_video();
     video.reply_markup = Some(keyboard.clone());
 
     let mut results = vec![(ResultType::Ready, video)];
 
     if let Some(message) = &result.extra_caption {
-        let mut video = InlineQueryResult::video(
-            generate_id(),
-            full_url,
-            "video/mp4".to_owned(),
-            thumb_url,
-            result.url.clone(),
-        );
+        let mut video = make_video();
         video.reply_markup = Some(keyboard.clone());
 
-        if let InlineQueryType::Video(ref mut result) = video.content {
-            result.caption = Some(message.to_string());
+        match video.content {
+            InlineQueryType::Video(ref mut result) => result.caption = Some(message.to_string()),
+            InlineQueryType::CachedVideo(ref mut result) => {
+                result.caption = Some(message.to_string())
+            }
+            _ => (),
         }
 
         results.push((ResultType::Ready, video));
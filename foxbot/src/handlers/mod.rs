@@ -6,6 +6,8 @@ mod commands;
 mod error_cleanup;
 mod error_reply;
 mod group_add;
+mod group_mirror;
+mod group_settings;
 mod group_source;
 mod inline_handler;
 mod permissions;
@@ -13,21 +15,24 @@ mod photo;
 pub mod settings;
 mod subscribe;
 mod twitter;
+mod watch;
 
-use crate::{MessageHandler, ServiceData};
+use crate::{callback::CallbackData, registry::ParsedCommand, MessageHandler, ServiceData};
 pub use channel_photo::ChannelPhotoHandler;
 pub use chosen_inline_handler::ChosenInlineHandler;
 pub use commands::CommandHandler;
 pub use error_cleanup::ErrorCleanup;
 pub use error_reply::ErrorReplyHandler;
 pub use group_add::GroupAddHandler;
+pub use group_mirror::GroupMirrorHandler;
 pub use group_source::GroupSourceHandler;
-pub use inline_handler::InlineHandler;
+pub use inline_handler::{check_stalled_videos, InlineHandler};
 pub use permissions::PermissionHandler;
 pub use photo::PhotoHandler;
 pub use settings::SettingsHandler;
 pub use subscribe::SubscribeHandler;
 pub use twitter::TwitterHandler;
+pub use watch::WatchHandler;
 
 #[derive(Debug, PartialEq)]
 pub enum Status {
@@ -51,6 +56,27 @@ pub trait Handler: Send + Sync {
         command: Option<&tgbotapi::Command>,
     ) -> anyhow::Result<Status>;
 
+    /// Method called for registered commands routed to this handler, after
+    /// the chat, permissions, and arguments have been checked.
+    async fn handle_command(
+        &self,
+        _handler: &MessageHandler,
+        _message: &tgbotapi::Message,
+        _command: &ParsedCommand,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Method called for callback queries with data routed to this handler.
+    async fn handle_callback(
+        &self,
+        _handler: &MessageHandler,
+        _callback_query: &tgbotapi::CallbackQuery,
+        _data: &CallbackData,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+
     /// Method called for every service update.
     async fn handle_service(
         &self,
@@ -1,13 +1,16 @@
 use anyhow::Context;
 use async_trait::async_trait;
-use tgbotapi::{ChatMemberUpdated, ChatType};
+use tgbotapi::{ChatMemberStatus, ChatMemberUpdated, ChatType};
 
 use super::{
     Handler,
     Status::{self, Completed, Ignored},
 };
 use crate::MessageHandler;
-use foxbot_models::{ChatAdmin, GroupConfig, GroupConfigKey, Permissions};
+use foxbot_models::{
+    ChatAction, ChatActions, ChatAdmin, ChatDigest, GroupConfig, GroupConfigKey, Permissions,
+};
+use foxbot_utils::get_faktory_custom;
 
 pub struct PermissionHandler;
 
@@ -97,9 +100,79 @@ async fn handle_my_chat_member(
         tracing::error!("unable to save permission change: {:?}", err);
     }
 
+    if lost_permission(my_chat_member) {
+        if let Err(err) = ChatActions::record(
+            &handler.conn,
+            my_chat_member.chat.id,
+            ChatAction::PermissionLost,
+            None,
+        )
+        .await
+        {
+            tracing::error!("unable to record lost permission: {:?}", err);
+        }
+    }
+
+    // Send what happened before the bot was removed now, as the digest can't
+    // be sent for this chat anymore.
+    if matches!(
+        my_chat_member.new_chat_member.status,
+        ChatMemberStatus::Left | ChatMemberStatus::Kicked
+    ) {
+        if let Err(err) = enqueue_final_digest(handler, my_chat_member.chat.id).await {
+            tracing::error!("unable to enqueue final digest: {:?}", err);
+        }
+    }
+
     Ok(true)
 }
 
+/// Send a chat's digest one last time, if it is enabled.
+async fn enqueue_final_digest(handler: &MessageHandler, chat_id: i64) -> anyhow::Result<()> {
+    let mut digest = match ChatDigest::get(&handler.conn, chat_id).await? {
+        Some(digest) => digest,
+        None => return Ok(()),
+    };
+    digest.chat_gone = true;
+
+    let digest = serde_json::to_value(&digest)?;
+    let mut job = faktory::Job::new("chat_digest", vec![digest]).on_queue("foxbot_background");
+    job.custom = get_faktory_custom();
+
+    let faktory = handler.faktory.clone();
+    tokio::task::spawn_blocking(move || {
+        let mut faktory = faktory.lock().unwrap();
+
+        if let Err(err) = faktory.enqueue(job) {
+            tracing::error!(chat_id, "unable to enqueue digest: {:?}", err);
+        }
+    });
+
+    Ok(())
+}
+
+/// If the bot lost a permission it needs for the chat: being an
+/// administrator, editing posts in channels, or deleting messages in groups.
+fn lost_permission(update: &ChatMemberUpdated) -> bool {
+    let (old, new) = (&update.old_chat_member, &update.new_chat_member);
+
+    if matches!(old.status, ChatMemberStatus::Administrator)
+        && !matches!(new.status, ChatMemberStatus::Administrator)
+    {
+        return true;
+    }
+
+    let lost = |get: fn(&tgbotapi::ChatMember) -> Option<bool>| {
+        get(old).unwrap_or(false) && !get(new).unwrap_or(false)
+    };
+
+    if update.chat.chat_type == ChatType::Channel {
+        lost(|member| member.can_edit_messages)
+    } else {
+        lost(|member| member.can_delete_messages)
+    }
+}
+
 async fn handle_chat_member(
     handler: &MessageHandler,
     chat_member: &Option<ChatMemberUpdated>,
@@ -313,3 +386,68 @@ async fn migrate_chat(handler: &MessageHandler, chat_id: i64, from_id: i64) -> a
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::lost_permission;
+
+    fn update(
+        chat_type: &str,
+        old: serde_json::Value,
+        new: serde_json::Value,
+    ) -> tgbotapi::ChatMemberUpdated {
+        let with_bot = |mut member: serde_json::Value| {
+            member["user"] = serde_json::json!({"id": 2, "is_bot": true, "first_name": "foxbot"});
+            member
+        };
+
+        serde_json::from_value(serde_json::json!({
+            "chat": {"id": -100, "type": chat_type},
+            "from": {"id": 1, "is_bot": false, "first_name": "Admin"},
+            "date": 0,
+            "old_chat_member": with_bot(old),
+            "new_chat_member": with_bot(new),
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_lost_permission() {
+        use serde_json::json;
+
+        let admin = json!({"status": "administrator", "can_edit_messages": true, "can_delete_messages": true});
+
+        assert!(lost_permission(&update(
+            "supergroup",
+            admin.clone(),
+            json!({"status": "member"})
+        )));
+        assert!(lost_permission(&update(
+            "channel",
+            admin.clone(),
+            json!({"status": "left"})
+        )));
+        assert!(!lost_permission(&update(
+            "supergroup",
+            json!({"status": "member"}),
+            admin.clone()
+        )));
+
+        // Only the permission needed for the type of chat matters.
+        assert!(lost_permission(&update(
+            "channel",
+            admin.clone(),
+            json!({"status": "administrator", "can_edit_messages": false, "can_delete_messages": true})
+        )));
+        assert!(!lost_permission(&update(
+            "supergroup",
+            admin.clone(),
+            json!({"status": "administrator", "can_edit_messages": false, "can_delete_messages": true})
+        )));
+        assert!(lost_permission(&update(
+            "supergroup",
+            admin,
+            json!({"status": "administrator", "can_edit_messages": true, "can_delete_messages": false})
+        )));
+    }
+}
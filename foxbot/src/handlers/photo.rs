@@ -9,7 +9,7 @@ use super::{
     Handler,
     Status::{self, Completed, Ignored},
 };
-use crate::MessageHandler;
+use crate::{callback::CallbackData, MessageHandler};
 use foxbot_models::{GroupConfig, GroupConfigKey};
 use foxbot_utils::{
     continuous_action, find_best_photo, get_message, match_image, needs_field, sort_results,
@@ -89,11 +89,9 @@ impl Handler for PhotoHandler {
                     reply_to_message_id: Some(message.message_id),
                     reply_markup: Some(tgbotapi::requests::ReplyMarkup::InlineKeyboardMarkup(
                         tgbotapi::InlineKeyboardMarkup {
-                            inline_keyboard: vec![vec![tgbotapi::InlineKeyboardButton {
-                                text: subscribe,
-                                callback_data: Some(format!("notify-{}", hash)),
-                                ..Default::default()
-                            }]],
+                            inline_keyboard: vec![vec![
+                                CallbackData::Notify { hash }.button(subscribe)
+                            ]],
                         },
                     )),
                     ..Default::default()
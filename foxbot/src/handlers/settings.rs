@@ -4,16 +4,20 @@ use tgbotapi::{
     requests::{
         AnswerCallbackQuery, EditMessageReplyMarkup, EditMessageText, ReplyMarkup, SendMessage,
     },
-    CallbackQuery, Command, InlineKeyboardButton, InlineKeyboardMarkup, Message, Update,
+    CallbackQuery, Command, InlineKeyboardMarkup, Message, Update,
 };
 
 use super::{
-    Handler,
+    group_settings, Handler,
     Status::{self, Completed, Ignored},
 };
-use crate::MessageHandler;
+use crate::{
+    callback::{CallbackData, SettingsToggle},
+    registry::ParsedCommand,
+    MessageHandler,
+};
 use foxbot_models::{Sites, UserConfig, UserConfigKey};
-use foxbot_utils::{get_message, needs_field};
+use foxbot_utils::{get_message, validate_caption_template, TemplateError, CAPTION_PLACEHOLDERS};
 
 pub struct SettingsHandler;
 
@@ -27,175 +31,323 @@ impl Handler for SettingsHandler {
         &self,
         handler: &MessageHandler,
         update: &Update,
-        command: Option<&Command>,
+        _command: Option<&Command>,
     ) -> anyhow::Result<Status> {
-        if let Some(command) = command {
-            if command.name == "/settings" {
-                send_settings_message(handler, update.message.as_ref().unwrap())
-                    .await
-                    .context("unable to send settings message")?;
+        // Commands in channels aren't sent as messages, so they aren't routed
+        // through the command registry.
+        let message = match &update.channel_post {
+            Some(message) => message,
+            None => return Ok(Ignored),
+        };
+
+        let command = match message.get_command() {
+            Some(command) if command.name.eq_ignore_ascii_case("/channelsettings") => command,
+            _ => return Ok(Ignored),
+        };
+
+        if let Some(username) = &command.username {
+            let bot_username = handler.bot_user.username.as_deref().unwrap_or_default();
+            if !username.eq_ignore_ascii_case(bot_username) {
                 return Ok(Completed);
             }
         }
 
-        let callback_query = needs_field!(update, callback_query);
-        let data = needs_field!(callback_query, data);
+        group_settings::send_channel_settings(handler, message)
+            .await
+            .context("unable to send channel settings")?;
 
-        if !data.starts_with("s:") {
-            return Ok(Ignored);
-        }
+        Ok(Completed)
+    }
 
-        if data.starts_with("s:order:") {
-            return order(handler, callback_query, data).await;
+    async fn handle_command(
+        &self,
+        handler: &MessageHandler,
+        message: &Message,
+        command: &ParsedCommand,
+    ) -> anyhow::Result<()> {
+        match command.name() {
+            "settings" => send_settings_message(handler, message)
+                .await
+                .context("unable to send settings message"),
+            "caption" => caption_template(handler, message, command.args.text())
+                .await
+                .context("unable to update caption template"),
+            "groupsettings" => group_settings::send_group_settings(handler, message).await,
+            _ => Ok(()),
         }
+    }
 
-        Ok(Completed)
+    async fn handle_callback(
+        &self,
+        handler: &MessageHandler,
+        callback_query: &CallbackQuery,
+        data: &CallbackData,
+    ) -> anyhow::Result<()> {
+        match data {
+            CallbackData::SettingsOrder => order_menu(handler, callback_query).await,
+            CallbackData::SettingsOrderSite { .. } => {
+                answer_callback(handler, callback_query, "settings-unsupported", None).await
+            }
+            CallbackData::SettingsOrderUnable { site } => {
+                let mut args = fluent::FluentArgs::new();
+                args.insert("name", site.as_str().into());
+
+                answer_callback(handler, callback_query, "settings-move-unable", Some(args)).await
+            }
+            CallbackData::SettingsOrderMove { site, pos } => {
+                move_site(handler, callback_query, site, *pos).await
+            }
+            CallbackData::SettingsToggle(setting) => {
+                toggle(handler, callback_query, *setting).await
+            }
+            CallbackData::SettingsCaption => caption_help(handler, callback_query).await,
+            data if is_group_callback(data) => {
+                group_settings::handle_callback(handler, callback_query, data).await
+            }
+            _ => Ok(()),
+        }
     }
 }
 
-async fn order(
+/// If a callback is for group or channel settings.
+fn is_group_callback(data: &CallbackData) -> bool {
+    matches!(
+        data,
+        CallbackData::GroupSettings
+            | CallbackData::GroupToggle(_)
+            | CallbackData::GroupCycle(_)
+            | CallbackData::GroupSites
+            | CallbackData::GroupSiteToggle { .. }
+            | CallbackData::GroupOrder
+            | CallbackData::GroupOrderMove { .. }
+            | CallbackData::GroupOrderReset
+            | CallbackData::GroupMirrorSites
+            | CallbackData::GroupMirrorSiteToggle { .. }
+            | CallbackData::ChannelCycle(_)
+            | CallbackData::ChatDigest
+    )
+}
+
+/// Answer a callback query with a message.
+pub(super) async fn answer_callback(
     handler: &MessageHandler,
     callback_query: &CallbackQuery,
-    data: &str,
-) -> anyhow::Result<Status> {
-    if data.ends_with(":e") {
-        let text = handler
-            .get_fluent_bundle(callback_query.from.language_code.as_deref(), |bundle| {
-                get_message(bundle, "settings-unsupported", None).unwrap()
-            })
-            .await;
+    name: &str,
+    args: Option<fluent::FluentArgs<'_>>,
+) -> anyhow::Result<()> {
+    let text = handler
+        .get_fluent_bundle(callback_query.from.language_code.as_deref(), |bundle| {
+            get_message(bundle, name, args).unwrap()
+        })
+        .await;
 
-        let answer = AnswerCallbackQuery {
-            callback_query_id: callback_query.id.clone(),
-            text: Some(text),
-            ..Default::default()
-        };
+    let answer = AnswerCallbackQuery {
+        callback_query_id: callback_query.id.clone(),
+        text: Some(text),
+        ..Default::default()
+    };
 
-        handler
-            .make_request(&answer)
-            .await
-            .context("unable to answer order callback query")?;
+    handler
+        .make_request(&answer)
+        .await
+        .context("unable to answer callback query")?;
 
-        return Ok(Completed);
+    Ok(())
+}
+
+async fn move_site(
+    handler: &MessageHandler,
+    callback_query: &CallbackQuery,
+    site: &Sites,
+    pos: usize,
+) -> anyhow::Result<()> {
+    let reply_message = match &callback_query.message {
+        Some(message) => message,
+        None => return Ok(()),
+    };
+
+    let mut sites = user_site_order(&handler.conn, callback_query.from.id).await?;
+
+    if let Some(existing_pos) = sites.iter().position(|item| item == site) {
+        sites.remove(existing_pos);
     }
 
-    if data.ends_with(":-") {
-        let site: Sites = data.split(':').nth(2).unwrap().parse().unwrap();
+    sites.insert(pos.min(sites.len()), site.clone());
 
-        let mut args = fluent::FluentArgs::new();
-        args.insert("name", site.as_str().into());
+    UserConfig::set(
+        &handler.conn,
+        UserConfigKey::SiteSortOrder,
+        callback_query.from.id,
+        sites,
+    )
+    .await
+    .context("unable to set user sort order")?;
 
-        let text = handler
-            .get_fluent_bundle(callback_query.from.language_code.as_deref(), |bundle| {
-                get_message(bundle, "settings-move-unable", Some(args)).unwrap()
-            })
-            .await;
+    let mut args = fluent::FluentArgs::new();
+    args.insert("name", site.as_str().into());
 
-        let answer = AnswerCallbackQuery {
-            callback_query_id: callback_query.id.clone(),
-            text: Some(text),
-            ..Default::default()
-        };
+    let text = handler
+        .get_fluent_bundle(callback_query.from.language_code.as_deref(), |bundle| {
+            get_message(bundle, "settings-move-updated", Some(args)).unwrap()
+        })
+        .await;
 
-        handler
-            .make_request(&answer)
-            .await
-            .context("unable to answer order callback query")?;
+    let answer = AnswerCallbackQuery {
+        callback_query_id: callback_query.id.clone(),
+        text: Some(text),
+        ..Default::default()
+    };
 
-        return Ok(Completed);
-    }
+    let keyboard = sort_order_keyboard(&handler.conn, callback_query.from.id).await?;
 
-    let reply_message = needs_field!(callback_query, message);
-    let from = reply_message
-        .from
-        .as_ref()
-        .and_then(|from| from.language_code.as_deref());
+    let edit_message = EditMessageReplyMarkup {
+        message_id: Some(reply_message.message_id),
+        chat_id: reply_message.chat_id(),
+        reply_markup: Some(ReplyMarkup::InlineKeyboardMarkup(keyboard)),
+        ..Default::default()
+    };
 
-    let pos = data.split(':').nth(3);
-    if let Some(pos) = pos {
-        let site = data.split(':').nth(2).unwrap().parse().unwrap();
-        let pos: usize = pos.parse().unwrap();
+    futures::try_join!(
+        handler.make_request(&edit_message),
+        handler.make_request(&answer)
+    )
+    .context("unable to edit message or answer query")?;
 
-        let order: Option<Vec<String>> = UserConfig::get(
-            &handler.conn,
-            UserConfigKey::SiteSortOrder,
-            callback_query.from.id,
-        )
-        .await
-        .context("unable to query user site sort order")?;
-        let mut sites = match order {
-            Some(sites) => sites.iter().map(|item| item.parse().unwrap()).collect(),
-            None => Sites::default_order(),
-        };
+    Ok(())
+}
 
-        let mut existing_pos = None;
-        for (idx, item) in sites.iter().enumerate() {
-            if item == &site {
-                existing_pos = Some(idx);
-            }
-        }
+async fn order_menu(
+    handler: &MessageHandler,
+    callback_query: &CallbackQuery,
+) -> anyhow::Result<()> {
+    let reply_message = match &callback_query.message {
+        Some(message) => message,
+        None => return Ok(()),
+    };
 
-        if let Some(pos) = existing_pos {
-            sites.remove(pos);
-        }
+    let text = handler
+        .get_fluent_bundle(callback_query.from.language_code.as_deref(), |bundle| {
+            get_message(bundle, "settings-site-order", None).unwrap()
+        })
+        .await;
 
-        sites.insert(pos, site.clone());
+    let keyboard = sort_order_keyboard(&handler.conn, callback_query.from.id).await?;
 
-        UserConfig::set(
-            &handler.conn,
-            UserConfigKey::SiteSortOrder,
-            callback_query.from.id,
-            sites,
-        )
-        .await
-        .context("unable to set user sort order")?;
+    let edit_message = EditMessageText {
+        message_id: Some(reply_message.message_id),
+        chat_id: reply_message.chat_id(),
+        text,
+        reply_markup: Some(ReplyMarkup::InlineKeyboardMarkup(keyboard)),
+        ..Default::default()
+    };
 
-        let mut args = fluent::FluentArgs::new();
-        args.insert("name", site.as_str().into());
+    let answer = AnswerCallbackQuery {
+        callback_query_id: callback_query.id.clone(),
+        ..Default::default()
+    };
 
-        let text = handler
-            .get_fluent_bundle(callback_query.from.language_code.as_deref(), |bundle| {
-                get_message(bundle, "settings-move-updated", Some(args)).unwrap()
-            })
-            .await;
+    futures::try_join!(
+        handler.make_request(&edit_message),
+        handler.make_request(&answer)
+    )
+    .context("unable to edit message or answer callback query")?;
 
-        let answer = AnswerCallbackQuery {
-            callback_query_id: callback_query.id.clone(),
-            text: Some(text),
-            ..Default::default()
-        };
+    Ok(())
+}
 
-        let keyboard = sort_order_keyboard(&handler.conn, callback_query.from.id).await?;
+/// Settings that can be turned on or off from the main settings menu, with
+/// the key they are stored as and the name of their label.
+const TOGGLES: &[(SettingsToggle, UserConfigKey, &str)] = &[
+    (
+        SettingsToggle::InlineInfo,
+        UserConfigKey::InlineInfo,
+        "settings-inline-info",
+    ),
+    (
+        SettingsToggle::InlineTags,
+        UserConfigKey::InlineTags,
+        "settings-inline-tags",
+    ),
+    (
+        SettingsToggle::SendOriginals,
+        UserConfigKey::SendOriginals,
+        "settings-originals",
+    ),
+];
+
+async fn toggle(
+    handler: &MessageHandler,
+    callback_query: &CallbackQuery,
+    setting: SettingsToggle,
+) -> anyhow::Result<()> {
+    let reply_message = match &callback_query.message {
+        Some(message) => message,
+        None => return Ok(()),
+    };
+    let user_id = callback_query.from.id;
 
-        let edit_message = EditMessageReplyMarkup {
-            message_id: Some(reply_message.message_id),
-            chat_id: reply_message.chat_id(),
-            reply_markup: Some(ReplyMarkup::InlineKeyboardMarkup(keyboard)),
-            ..Default::default()
-        };
+    let key = match TOGGLES.iter().find(|toggle| toggle.0 == setting) {
+        Some((_setting, key, _label)) => *key,
+        None => return Ok(()),
+    };
 
-        futures::try_join!(
-            handler.make_request(&edit_message),
-            handler.make_request(&answer)
-        )
-        .context("unable to edit message or answer query")?;
+    let enabled: bool = UserConfig::get(&handler.conn, key, user_id)
+        .await
+        .context("unable to query user toggle")?
+        .unwrap_or(false);
 
-        return Ok(Completed);
-    }
+    UserConfig::set(&handler.conn, key, user_id, !enabled)
+        .await
+        .context("unable to set user toggle")?;
 
-    let text = handler
-        .get_fluent_bundle(from, |bundle| {
-            get_message(bundle, "settings-site-order", None).unwrap()
-        })
-        .await;
+    let keyboard = main_keyboard(
+        handler,
+        callback_query.from.language_code.as_deref(),
+        user_id,
+    )
+    .await?;
 
-    let keyboard = sort_order_keyboard(&handler.conn, callback_query.from.id).await?;
+    let edit_message = EditMessageReplyMarkup {
+        message_id: Some(reply_message.message_id),
+        chat_id: reply_message.chat_id(),
+        reply_markup: Some(ReplyMarkup::InlineKeyboardMarkup(keyboard)),
+        ..Default::default()
+    };
+
+    let answer = AnswerCallbackQuery {
+        callback_query_id: callback_query.id.clone(),
+        ..Default::default()
+    };
+
+    futures::try_join!(
+        handler.make_request(&edit_message),
+        handler.make_request(&answer)
+    )
+    .context("unable to edit message or answer callback query")?;
+
+    Ok(())
+}
+
+async fn caption_help(
+    handler: &MessageHandler,
+    callback_query: &CallbackQuery,
+) -> anyhow::Result<()> {
+    let reply_message = match &callback_query.message {
+        Some(message) => message,
+        None => return Ok(()),
+    };
+
+    let text = caption_help_text(
+        handler,
+        callback_query.from.language_code.as_deref(),
+        callback_query.from.id,
+    )
+    .await?;
 
     let edit_message = EditMessageText {
         message_id: Some(reply_message.message_id),
         chat_id: reply_message.chat_id(),
         text,
-        reply_markup: Some(ReplyMarkup::InlineKeyboardMarkup(keyboard)),
         ..Default::default()
     };
 
@@ -210,7 +362,148 @@ async fn order(
     )
     .context("unable to edit message or answer callback query")?;
 
-    Ok(Completed)
+    Ok(())
+}
+
+/// Explain how to write a caption template, including the user's current
+/// template if they have one.
+async fn caption_help_text(
+    handler: &MessageHandler,
+    from: Option<&str>,
+    user_id: i64,
+) -> anyhow::Result<String> {
+    let template: Option<String> =
+        UserConfig::get(&handler.conn, UserConfigKey::CaptionTemplate, user_id)
+            .await
+            .context("unable to query user caption template")?;
+
+    let placeholders: Vec<String> = CAPTION_PLACEHOLDERS
+        .iter()
+        .map(|placeholder| format!("{{{}}}", placeholder))
+        .collect();
+
+    let mut args = fluent::FluentArgs::new();
+    args.insert("placeholders", placeholders.join(", ").into());
+
+    let text = handler
+        .get_fluent_bundle(from, |bundle| match template {
+            Some(template) => {
+                args.insert("template", template.into());
+                get_message(bundle, "settings-caption-current", Some(args)).unwrap()
+            }
+            None => get_message(bundle, "settings-caption-none", Some(args)).unwrap(),
+        })
+        .await;
+
+    Ok(text)
+}
+
+/// Set, reset, or explain the user's caption template with `/caption`.
+async fn caption_template(
+    handler: &MessageHandler,
+    message: &Message,
+    template: Option<&str>,
+) -> anyhow::Result<()> {
+    let from = message.from.as_ref().context("message was missing user")?;
+    let lang = from.language_code.as_deref();
+
+    let template = template.unwrap_or_default();
+
+    let text = if template.is_empty() {
+        caption_help_text(handler, lang, from.id).await?
+    } else if template.eq_ignore_ascii_case("reset") {
+        UserConfig::delete(&handler.conn, UserConfigKey::CaptionTemplate, from.id).await?;
+
+        handler
+            .get_fluent_bundle(lang, |bundle| {
+                get_message(bundle, "settings-caption-reset", None).unwrap()
+            })
+            .await
+    } else if let Err(err) = validate_caption_template(template) {
+        let (name, args) = match err {
+            TemplateError::UnknownPlaceholder(placeholder) => {
+                let mut args = fluent::FluentArgs::new();
+                args.insert("placeholder", placeholder.into());
+
+                ("settings-caption-invalid", Some(args))
+            }
+            TemplateError::UnclosedBrace => ("settings-caption-unclosed", None),
+        };
+
+        handler
+            .get_fluent_bundle(lang, |bundle| get_message(bundle, name, args).unwrap())
+            .await
+    } else {
+        UserConfig::set(
+            &handler.conn,
+            UserConfigKey::CaptionTemplate,
+            from.id,
+            template,
+        )
+        .await?;
+
+        handler
+            .get_fluent_bundle(lang, |bundle| {
+                get_message(bundle, "settings-caption-updated", None).unwrap()
+            })
+            .await
+    };
+
+    handler
+        .make_request(&SendMessage {
+            chat_id: message.chat_id(),
+            text,
+            reply_to_message_id: Some(message.message_id),
+            ..Default::default()
+        })
+        .await?;
+
+    Ok(())
+}
+
+async fn main_keyboard(
+    handler: &MessageHandler,
+    from: Option<&str>,
+    user_id: i64,
+) -> anyhow::Result<InlineKeyboardMarkup> {
+    let mut enabled = Vec::with_capacity(TOGGLES.len());
+    for (_setting, key, _label) in TOGGLES {
+        let value: bool = UserConfig::get(&handler.conn, *key, user_id)
+            .await
+            .context("unable to query user toggle")?
+            .unwrap_or(false);
+        enabled.push(value);
+    }
+
+    let buttons = handler
+        .get_fluent_bundle(from, |bundle| {
+            let mut buttons = vec![vec![CallbackData::SettingsOrder
+                .button(get_message(bundle, "settings-site-preference", None).unwrap())]];
+
+            for ((setting, _key, label), enabled) in TOGGLES.iter().zip(enabled) {
+                let state = if enabled {
+                    "settings-toggle-on"
+                } else {
+                    "settings-toggle-off"
+                };
+
+                let mut args = fluent::FluentArgs::new();
+                args.insert("name", get_message(bundle, label, None).unwrap().into());
+
+                buttons.push(vec![CallbackData::SettingsToggle(*setting)
+                    .button(get_message(bundle, state, Some(args)).unwrap())]);
+            }
+
+            buttons.push(vec![CallbackData::SettingsCaption
+                .button(get_message(bundle, "settings-caption", None).unwrap())]);
+
+            buttons
+        })
+        .await;
+
+    Ok(InlineKeyboardMarkup {
+        inline_keyboard: buttons,
+    })
 }
 
 async fn send_settings_message(
@@ -222,19 +515,12 @@ async fn send_settings_message(
         .as_ref()
         .and_then(|user| user.language_code.as_deref());
 
-    let site_preference = handler
-        .get_fluent_bundle(from, |bundle| {
-            get_message(bundle, "settings-site-preference", None).unwrap()
-        })
-        .await;
-
-    let keyboard = InlineKeyboardMarkup {
-        inline_keyboard: vec![vec![InlineKeyboardButton {
-            text: site_preference,
-            callback_data: Some("s:order:".into()),
-            ..Default::default()
-        }]],
-    };
+    let user_id = message
+        .from
+        .as_ref()
+        .context("message was missing user")?
+        .id;
+    let keyboard = main_keyboard(handler, from, user_id).await?;
 
     let text = handler
         .get_fluent_bundle(from, |bundle| {
@@ -262,18 +548,30 @@ async fn send_settings_message(
     Ok(sent_message)
 }
 
-async fn sort_order_keyboard(
+/// Get the user's preferred order of sites, skipping any sites that are no
+/// longer known.
+async fn user_site_order(
     conn: &sqlx::Pool<sqlx::Postgres>,
     user_id: i64,
-) -> anyhow::Result<InlineKeyboardMarkup> {
+) -> anyhow::Result<Vec<Sites>> {
     let row: Option<Vec<String>> = UserConfig::get(conn, UserConfigKey::SiteSortOrder, user_id)
         .await
         .context("unable to query user sort order")?;
-    let mut sites = match row {
-        Some(row) => row.iter().map(|item| item.parse().unwrap()).collect(),
+
+    let sites = match row {
+        Some(row) => row.iter().filter_map(|item| item.parse().ok()).collect(),
         None => Sites::default_order(),
     };
 
+    Ok(sites)
+}
+
+async fn sort_order_keyboard(
+    conn: &sqlx::Pool<sqlx::Postgres>,
+    user_id: i64,
+) -> anyhow::Result<InlineKeyboardMarkup> {
+    let mut sites = user_site_order(conn, user_id).await?;
+
     let mut buttons = vec![];
 
     // If the available sites has changed, reset ordering to add new items.
@@ -283,34 +581,30 @@ async fn sort_order_keyboard(
     }
 
     for (idx, site) in sites.iter().enumerate() {
+        let site = site.clone();
+
         let up = if idx == 0 {
-            format!("s:order:{}:-", site.as_str())
+            CallbackData::SettingsOrderUnable { site: site.clone() }
         } else {
-            format!("s:order:{}:{}", site.as_str(), idx - 1)
+            CallbackData::SettingsOrderMove {
+                site: site.clone(),
+                pos: idx - 1,
+            }
         };
 
         let down = if idx == sites.len() - 1 {
-            format!("s:order:{}:-", site.as_str())
+            CallbackData::SettingsOrderUnable { site: site.clone() }
         } else {
-            format!("s:order:{}:{}", site.as_str(), idx + 1)
+            CallbackData::SettingsOrderMove {
+                site: site.clone(),
+                pos: idx + 1,
+            }
         };
 
         buttons.push(vec![
-            InlineKeyboardButton {
-                text: site.as_str().into(),
-                callback_data: Some(format!("s:order:{}:e", site.as_str())),
-                ..Default::default()
-            },
-            InlineKeyboardButton {
-                text: "⬆".into(),
-                callback_data: Some(up),
-                ..Default::default()
-            },
-            InlineKeyboardButton {
-                text: "⬇".into(),
-                callback_data: Some(down),
-                ..Default::default()
-            },
+            CallbackData::SettingsOrderSite { site: site.clone() }.button(site.as_str().into()),
+            up.button("⬆".into()),
+            down.button("⬇".into()),
         ]);
     }
 
@@ -318,3 +612,33 @@ async fn sort_order_keyboard(
         inline_keyboard: buttons,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::callback::{tests::example_callbacks, CallbackData};
+
+    #[test]
+    fn test_settings_callbacks_handled() {
+        for callback in example_callbacks() {
+            if callback.handler() != "settings" {
+                continue;
+            }
+
+            let user_callback = matches!(
+                callback,
+                CallbackData::SettingsOrder
+                    | CallbackData::SettingsOrderSite { .. }
+                    | CallbackData::SettingsOrderUnable { .. }
+                    | CallbackData::SettingsOrderMove { .. }
+                    | CallbackData::SettingsToggle(_)
+                    | CallbackData::SettingsCaption
+            );
+
+            assert!(
+                user_callback || super::is_group_callback(&callback),
+                "{:?} was not handled",
+                callback
+            );
+        }
+    }
+}
@@ -1,14 +1,25 @@
 use async_trait::async_trait;
-use tgbotapi::requests::{AnswerCallbackQuery, EditMessageReplyMarkup};
+use tgbotapi::{
+    requests::{
+        AnswerCallbackQuery, DeleteMessage, EditMessageReplyMarkup, EditMessageText, ReplyMarkup,
+        SendMessage, SendPhoto,
+    },
+    InlineKeyboardButton, InlineKeyboardMarkup,
+};
 
 use super::{
     Handler,
-    Status::{self, Completed, Ignored},
+    Status::{self, Ignored},
 };
-use crate::{MessageHandler, ServiceData};
+use crate::{callback::CallbackData, registry::ParsedCommand, MessageHandler, ServiceData};
 use foxbot_models::Subscriptions;
 use foxbot_utils::{find_best_photo, get_faktory_custom, get_message};
 
+/// Default maximum number of pending subscriptions for each user.
+const MAX_SUBSCRIPTIONS: i64 = 25;
+/// Number of subscriptions displayed on each page.
+const PAGE_SIZE: i64 = 5;
+
 pub struct SubscribeHandler;
 
 #[async_trait]
@@ -19,25 +30,50 @@ impl Handler for SubscribeHandler {
 
     async fn handle(
         &self,
-        handler: &MessageHandler,
-        update: &tgbotapi::Update,
+        _handler: &MessageHandler,
+        _update: &tgbotapi::Update,
         _command: Option<&tgbotapi::Command>,
     ) -> anyhow::Result<Status> {
-        if let Some(callback_query) = &update.callback_query {
-            if !callback_query
-                .data
-                .as_deref()
-                .unwrap_or_default()
-                .starts_with("notify-")
-            {
-                return Ok(Ignored);
+        Ok(Ignored)
+    }
+
+    async fn handle_command(
+        &self,
+        handler: &MessageHandler,
+        message: &tgbotapi::Message,
+        _command: &ParsedCommand,
+    ) -> anyhow::Result<()> {
+        self.list(handler, message).await
+    }
+
+    async fn handle_callback(
+        &self,
+        handler: &MessageHandler,
+        callback_query: &tgbotapi::CallbackQuery,
+        data: &CallbackData,
+    ) -> anyhow::Result<()> {
+        match *data {
+            CallbackData::Notify { hash } => self.subscribe(handler, callback_query, hash).await,
+            CallbackData::SubscriptionsPage { page } => {
+                self.manage(handler, callback_query, page, None).await
             }
+            CallbackData::SubscriptionView { hash } => {
+                self.view(handler, callback_query, hash).await
+            }
+            CallbackData::SubscriptionCancel { hash, page } => {
+                Subscriptions::remove_subscription(&handler.conn, callback_query.from.id, hash)
+                    .await?;
 
-            self.subscribe(handler, callback_query).await?;
-            return Ok(Completed);
+                self.manage(
+                    handler,
+                    callback_query,
+                    page,
+                    Some("subscriptions-cancelled"),
+                )
+                .await
+            }
+            _ => Ok(()),
         }
-
-        Ok(Ignored)
     }
 
     async fn handle_service(
@@ -72,13 +108,43 @@ impl SubscribeHandler {
         &self,
         handler: &MessageHandler,
         callback_query: &tgbotapi::CallbackQuery,
+        hash: i64,
     ) -> anyhow::Result<()> {
-        let data = callback_query.data.as_deref().unwrap();
-        let hash = data.strip_prefix("notify-").unwrap();
-        let hash: i64 = hash.parse()?;
-
         tracing::trace!(hash, "attempting to add subscription for hash");
 
+        // Subscribing again to an existing subscription doesn't add another,
+        // so it shouldn't count against the limit.
+        let existing = Subscriptions::get_subscription(&handler.conn, callback_query.from.id, hash)
+            .await?
+            .is_some();
+
+        let max = handler.config.subscription_max.unwrap_or(MAX_SUBSCRIPTIONS);
+        let count =
+            Subscriptions::count_subscriptions(&handler.conn, callback_query.from.id).await?;
+        if !existing && count >= max {
+            tracing::debug!(count, max, "user had too many subscriptions");
+
+            let mut args = fluent::FluentArgs::new();
+            args.insert("max", max.into());
+
+            let text = handler
+                .get_fluent_bundle(callback_query.from.language_code.as_deref(), |bundle| {
+                    get_message(bundle, "subscribe-too-many", Some(args)).unwrap()
+                })
+                .await;
+
+            handler
+                .make_request(&AnswerCallbackQuery {
+                    callback_query_id: callback_query.id.clone(),
+                    show_alert: Some(true),
+                    text: Some(text),
+                    ..Default::default()
+                })
+                .await?;
+
+            return Ok(());
+        }
+
         let (message_id, photo_id) = match callback_query.message.as_deref() {
             Some(tgbotapi::Message {
                 reply_to_message: Some(message),
@@ -96,6 +162,21 @@ impl SubscribeHandler {
             None => (None, None),
         };
 
+        // Buttons sent in groups may be used by anyone in the group and
+        // notifications are sent as private messages, so the message ID would
+        // not be valid and the button should remain for other users.
+        let is_private = matches!(
+            callback_query.message.as_deref(),
+            Some(tgbotapi::Message {
+                chat: tgbotapi::Chat {
+                    chat_type: tgbotapi::ChatType::Private,
+                    ..
+                },
+                ..
+            })
+        );
+        let message_id = if is_private { message_id } else { None };
+
         if let Err(err) = Subscriptions::add_subscription(
             &handler.conn,
             callback_query.from.id,
@@ -135,7 +216,7 @@ impl SubscribeHandler {
             message_id,
             chat: tgbotapi::Chat { id, .. },
             ..
-        }) = callback_query.message.as_deref()
+        }) = callback_query.message.as_deref().filter(|_| is_private)
         {
             let _ = handler
                 .bot
@@ -148,9 +229,15 @@ impl SubscribeHandler {
                 .await;
         }
 
+        let name = if is_private {
+            "subscribe-success"
+        } else {
+            "subscribe-success-group"
+        };
+
         let text = handler
             .get_fluent_bundle(callback_query.from.language_code.as_deref(), |bundle| {
-                get_message(bundle, "subscribe-success", None).unwrap()
+                get_message(bundle, name, None).unwrap()
             })
             .await;
 
@@ -165,4 +252,286 @@ impl SubscribeHandler {
 
         Ok(())
     }
+
+    /// Send the first page of a user's pending subscriptions.
+    async fn list(
+        &self,
+        handler: &MessageHandler,
+        message: &tgbotapi::Message,
+    ) -> anyhow::Result<()> {
+        let from = message.from.as_ref().unwrap();
+        let (text, markup) = self.build_page(handler, from, 0).await?;
+
+        handler
+            .make_request(&SendMessage {
+                chat_id: message.chat_id(),
+                text,
+                reply_markup: markup.map(ReplyMarkup::InlineKeyboardMarkup),
+                ..Default::default()
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    /// Handle callbacks from the subscription list, to change pages, view the
+    /// image for a subscription, or cancel a subscription.
+    async fn manage(
+        &self,
+        handler: &MessageHandler,
+        callback_query: &tgbotapi::CallbackQuery,
+        page: i64,
+        answer: Option<&str>,
+    ) -> anyhow::Result<()> {
+        let text = match answer {
+            Some(name) => Some(
+                handler
+                    .get_fluent_bundle(callback_query.from.language_code.as_deref(), |bundle| {
+                        get_message(bundle, name, None).unwrap()
+                    })
+                    .await,
+            ),
+            None => None,
+        };
+
+        handler
+            .make_request(&AnswerCallbackQuery {
+                callback_query_id: callback_query.id.clone(),
+                text,
+                ..Default::default()
+            })
+            .await?;
+
+        let message = match callback_query.message.as_deref() {
+            Some(message) => message,
+            None => return Ok(()),
+        };
+
+        let (text, markup) = self.build_page(handler, &callback_query.from, page).await?;
+        let reply_markup = markup.map(ReplyMarkup::InlineKeyboardMarkup);
+
+        // Buttons on a viewed subscription image can't have their message
+        // edited into text, so replace the image with the list instead.
+        if message.photo.is_some() {
+            handler
+                .make_request(&DeleteMessage {
+                    chat_id: message.chat_id(),
+                    message_id: message.message_id,
+                })
+                .await?;
+
+            handler
+                .make_request(&SendMessage {
+                    chat_id: message.chat_id(),
+                    text,
+                    reply_markup,
+                    ..Default::default()
+                })
+                .await?;
+
+            return Ok(());
+        }
+
+        handler
+            .make_request(&EditMessageText {
+                chat_id: message.chat_id(),
+                message_id: Some(message.message_id),
+                text,
+                reply_markup,
+                ..Default::default()
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    /// Send the image a subscription was created for, replying to the original
+    /// message when it still exists.
+    async fn view(
+        &self,
+        handler: &MessageHandler,
+        callback_query: &tgbotapi::CallbackQuery,
+        hash: i64,
+    ) -> anyhow::Result<()> {
+        let subscription =
+            Subscriptions::get_subscription(&handler.conn, callback_query.from.id, hash).await?;
+
+        let (subscription, photo_id) = match subscription {
+            Some(subscription) => match subscription.photo_id.clone() {
+                Some(photo_id) => (subscription, photo_id),
+                None => {
+                    self.answer(handler, callback_query, "subscriptions-no-photo")
+                        .await?;
+                    return Ok(());
+                }
+            },
+            None => {
+                self.answer(handler, callback_query, "subscriptions-missing")
+                    .await?;
+                return Ok(());
+            }
+        };
+
+        let mut args = fluent::FluentArgs::new();
+        args.insert(
+            "date",
+            subscription
+                .created_at
+                .format("%Y-%m-%d")
+                .to_string()
+                .into(),
+        );
+
+        let (caption, cancel) = handler
+            .get_fluent_bundle(callback_query.from.language_code.as_deref(), |bundle| {
+                (
+                    get_message(bundle, "subscriptions-view", Some(args)).unwrap(),
+                    get_message(bundle, "subscriptions-cancel", None).unwrap(),
+                )
+            })
+            .await;
+
+        handler
+            .make_request(&SendPhoto {
+                chat_id: callback_query.from.id.into(),
+                photo: tgbotapi::FileType::FileID(photo_id),
+                caption: Some(caption),
+                reply_to_message_id: subscription.message_id,
+                allow_sending_without_reply: Some(true),
+                reply_markup: Some(ReplyMarkup::InlineKeyboardMarkup(InlineKeyboardMarkup {
+                    inline_keyboard: vec![vec![CallbackData::SubscriptionCancel {
+                        hash: subscription.hash,
+                        page: 0,
+                    }
+                    .button(cancel)]],
+                })),
+                ..Default::default()
+            })
+            .await?;
+
+        handler
+            .make_request(&AnswerCallbackQuery {
+                callback_query_id: callback_query.id.clone(),
+                ..Default::default()
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    async fn answer(
+        &self,
+        handler: &MessageHandler,
+        callback_query: &tgbotapi::CallbackQuery,
+        name: &str,
+    ) -> anyhow::Result<()> {
+        let text = handler
+            .get_fluent_bundle(callback_query.from.language_code.as_deref(), |bundle| {
+                get_message(bundle, name, None).unwrap()
+            })
+            .await;
+
+        handler
+            .make_request(&AnswerCallbackQuery {
+                callback_query_id: callback_query.id.clone(),
+                text: Some(text),
+                ..Default::default()
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    /// Build the text and keyboard for a page of a user's subscriptions.
+    ///
+    /// The page is clamped to the last available page, as subscriptions may
+    /// have been cancelled or notified since the list was sent.
+    async fn build_page(
+        &self,
+        handler: &MessageHandler,
+        user: &tgbotapi::User,
+        page: i64,
+    ) -> anyhow::Result<(String, Option<InlineKeyboardMarkup>)> {
+        let count = Subscriptions::count_subscriptions(&handler.conn, user.id).await?;
+        let pages = (count + PAGE_SIZE - 1) / PAGE_SIZE;
+        let page = page.min(pages - 1).max(0);
+
+        let subscriptions =
+            Subscriptions::list_subscriptions(&handler.conn, user.id, PAGE_SIZE, page * PAGE_SIZE)
+                .await?;
+
+        let page_data = handler
+            .get_fluent_bundle(user.language_code.as_deref(), |bundle| {
+                if subscriptions.is_empty() {
+                    return (
+                        get_message(bundle, "subscriptions-none", None).unwrap(),
+                        None,
+                    );
+                }
+
+                let mut args = fluent::FluentArgs::new();
+                args.insert("count", count.into());
+                args.insert("page", (page + 1).into());
+                args.insert("pages", pages.into());
+                let text = get_message(bundle, "subscriptions-list", Some(args)).unwrap();
+
+                let cancel = get_message(bundle, "subscriptions-cancel", None).unwrap();
+
+                let mut buttons: Vec<Vec<InlineKeyboardButton>> = subscriptions
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, subscription)| {
+                        let mut args = fluent::FluentArgs::new();
+                        args.insert("number", (page * PAGE_SIZE + idx as i64 + 1).into());
+                        args.insert(
+                            "date",
+                            subscription
+                                .created_at
+                                .format("%Y-%m-%d")
+                                .to_string()
+                                .into(),
+                        );
+
+                        vec![
+                            CallbackData::SubscriptionView {
+                                hash: subscription.hash,
+                            }
+                            .button(get_message(bundle, "subscriptions-item", Some(args)).unwrap()),
+                            CallbackData::SubscriptionCancel {
+                                hash: subscription.hash,
+                                page,
+                            }
+                            .button(cancel.clone()),
+                        ]
+                    })
+                    .collect();
+
+                let mut navigation = Vec::with_capacity(2);
+                if page > 0 {
+                    navigation.push(
+                        CallbackData::SubscriptionsPage { page: page - 1 }
+                            .button(get_message(bundle, "subscriptions-previous", None).unwrap()),
+                    );
+                }
+                if page + 1 < pages {
+                    navigation.push(
+                        CallbackData::SubscriptionsPage { page: page + 1 }
+                            .button(get_message(bundle, "subscriptions-next", None).unwrap()),
+                    );
+                }
+                if !navigation.is_empty() {
+                    buttons.push(navigation);
+                }
+
+                (
+                    text,
+                    Some(InlineKeyboardMarkup {
+                        inline_keyboard: buttons,
+                    }),
+                )
+            })
+            .await;
+
+        Ok(page_data)
+    }
 }
@@ -2,11 +2,11 @@ use async_trait::async_trait;
 
 use super::{
     Handler,
-    Status::{self, Completed, Ignored},
+    Status::{self, Ignored},
 };
-use crate::{Config, MessageHandler, ServiceData};
+use crate::{callback::CallbackData, registry::ParsedCommand, Config, MessageHandler, ServiceData};
 use foxbot_models::{Twitter, TwitterAccount};
-use foxbot_utils::{get_message, needs_field};
+use foxbot_utils::get_message;
 
 pub struct TwitterHandler;
 
@@ -18,42 +18,40 @@ impl Handler for TwitterHandler {
 
     async fn handle(
         &self,
-        handler: &MessageHandler,
-        update: &tgbotapi::Update,
-        command: Option<&tgbotapi::Command>,
+        _handler: &MessageHandler,
+        _update: &tgbotapi::Update,
+        _command: Option<&tgbotapi::Command>,
     ) -> anyhow::Result<Status> {
-        match command {
-            Some(cmd) if cmd.name == "/twitter" => {
-                let message = needs_field!(update, message);
-                let user = needs_field!(message, from);
-                return handle_command(handler, message, user)
-                    .await
-                    .map(|_| Completed);
-            }
-            Some(_) => return Ok(Ignored),
-            _ => (),
+        Ok(Ignored)
+    }
+
+    async fn handle_command(
+        &self,
+        handler: &MessageHandler,
+        message: &tgbotapi::Message,
+        _command: &ParsedCommand,
+    ) -> anyhow::Result<()> {
+        match &message.from {
+            Some(user) => handle_command(handler, message, user).await,
+            None => Ok(()),
         }
+    }
 
-        match &update.callback_query {
-            Some(tgbotapi::CallbackQuery {
-                data: Some(data), ..
-            }) if data == "twitter-add" => {
-                let callback = needs_field!(update, callback_query);
-                let message = needs_field!(callback, message);
-                handle_add(handler, callback, message)
-                    .await
-                    .map(|_| Completed)
-            }
-            Some(tgbotapi::CallbackQuery {
-                data: Some(data), ..
-            }) if data == "twitter-remove" => {
-                let callback = needs_field!(update, callback_query);
-                let message = needs_field!(callback, message);
-                handle_remove(handler, callback, message)
-                    .await
-                    .map(|_| Completed)
-            }
-            _ => Ok(Ignored),
+    async fn handle_callback(
+        &self,
+        handler: &MessageHandler,
+        callback: &tgbotapi::CallbackQuery,
+        data: &CallbackData,
+    ) -> anyhow::Result<()> {
+        let message = match &callback.message {
+            Some(message) => message,
+            None => return Ok(()),
+        };
+
+        match data {
+            CallbackData::TwitterAdd => handle_add(handler, callback, message).await,
+            CallbackData::TwitterRemove => handle_remove(handler, callback, message).await,
+            _ => Ok(()),
         }
     }
 
@@ -75,13 +73,6 @@ async fn handle_command(
     message: &tgbotapi::Message,
     user: &tgbotapi::User,
 ) -> anyhow::Result<()> {
-    if message.chat.chat_type != tgbotapi::ChatType::Private {
-        handler
-            .send_generic_reply(message, "twitter-private")
-            .await?;
-        return Ok(());
-    }
-
     if let Some(account) = Twitter::get_account(&handler.conn, user.id).await? {
         let access = get_access(&handler.config, account);
 
@@ -107,16 +98,8 @@ async fn handle_command(
             let markup = tgbotapi::requests::ReplyMarkup::InlineKeyboardMarkup(
                 tgbotapi::InlineKeyboardMarkup {
                     inline_keyboard: vec![vec![
-                        tgbotapi::InlineKeyboardButton {
-                            text: change,
-                            callback_data: Some("twitter-add".into()),
-                            ..Default::default()
-                        },
-                        tgbotapi::InlineKeyboardButton {
-                            text: remove,
-                            callback_data: Some("twitter-remove".into()),
-                            ..Default::default()
-                        },
+                        CallbackData::TwitterAdd.button(change),
+                        CallbackData::TwitterRemove.button(remove),
                     ]],
                 },
             );
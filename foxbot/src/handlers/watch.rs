@@ -0,0 +1,165 @@
+use async_trait::async_trait;
+use tgbotapi::{requests::SendMessage, Command, Message, Update};
+
+use super::{
+    Handler,
+    Status::{self, Ignored},
+};
+use crate::{
+    registry::{CommandArgs, ParsedCommand},
+    MessageHandler,
+};
+use foxbot_models::{ArtistWatches, Sites};
+use foxbot_utils::get_message;
+
+/// Maximum number of artists each user may watch.
+const MAX_WATCHES: usize = 50;
+
+pub struct WatchHandler;
+
+#[async_trait]
+impl Handler for WatchHandler {
+    fn name(&self) -> &'static str {
+        "watch"
+    }
+
+    async fn handle(
+        &self,
+        _handler: &MessageHandler,
+        _update: &Update,
+        _command: Option<&Command>,
+    ) -> anyhow::Result<Status> {
+        Ok(Ignored)
+    }
+
+    async fn handle_command(
+        &self,
+        handler: &MessageHandler,
+        message: &Message,
+        command: &ParsedCommand,
+    ) -> anyhow::Result<()> {
+        let args = match &command.args {
+            CommandArgs::SiteArtist(Some(args)) => args.clone(),
+            _ => return list_watches(handler, message).await,
+        };
+
+        if command.name() == "watch" {
+            add_watch(handler, message, args).await
+        } else {
+            remove_watch(handler, message, args).await
+        }
+    }
+}
+
+async fn add_watch(
+    handler: &MessageHandler,
+    message: &Message,
+    (site, artist): (Sites, String),
+) -> anyhow::Result<()> {
+    let user_id = message.from.as_ref().unwrap().id;
+
+    let watches = ArtistWatches::list_watches(&handler.conn, user_id).await?;
+    if watches.len() >= MAX_WATCHES {
+        handler
+            .send_generic_reply(message, "watch-too-many")
+            .await?;
+        return Ok(());
+    }
+
+    ArtistWatches::add_watch(&handler.conn, user_id, &site, &artist).await?;
+
+    send_watch_reply(handler, message, "watch-added", &site, &artist).await
+}
+
+async fn remove_watch(
+    handler: &MessageHandler,
+    message: &Message,
+    (site, artist): (Sites, String),
+) -> anyhow::Result<()> {
+    let user_id = message.from.as_ref().unwrap().id;
+
+    let name = if ArtistWatches::remove_watch(&handler.conn, user_id, &site, &artist).await? {
+        "watch-removed"
+    } else {
+        "watch-not-found"
+    };
+
+    send_watch_reply(handler, message, name, &site, &artist).await
+}
+
+async fn send_watch_reply(
+    handler: &MessageHandler,
+    message: &Message,
+    name: &str,
+    site: &Sites,
+    artist: &str,
+) -> anyhow::Result<()> {
+    let mut args = fluent::FluentArgs::new();
+    args.insert("site", site.as_str().into());
+    args.insert("artist", artist.to_string().into());
+
+    let text = handler
+        .get_fluent_bundle(
+            message.from.as_ref().unwrap().language_code.as_deref(),
+            |bundle| get_message(bundle, name, Some(args)).unwrap(),
+        )
+        .await;
+
+    handler
+        .make_request(&SendMessage {
+            chat_id: message.chat_id(),
+            reply_to_message_id: Some(message.message_id),
+            text,
+            ..Default::default()
+        })
+        .await?;
+
+    Ok(())
+}
+
+async fn list_watches(handler: &MessageHandler, message: &Message) -> anyhow::Result<()> {
+    let watches =
+        ArtistWatches::list_watches(&handler.conn, message.from.as_ref().unwrap().id).await?;
+
+    let text = handler
+        .get_fluent_bundle(
+            message.from.as_ref().unwrap().language_code.as_deref(),
+            |bundle| {
+                let mut buf = String::new();
+
+                if watches.is_empty() {
+                    buf.push_str(&get_message(bundle, "watch-none", None).unwrap());
+                    buf.push('\n');
+                } else {
+                    buf.push_str(&get_message(bundle, "watch-list", None).unwrap());
+                    buf.push('\n');
+
+                    for watch in &watches {
+                        let mut args = fluent::FluentArgs::new();
+                        args.insert("site", watch.site.as_str().into());
+                        args.insert("artist", watch.artist.clone().into());
+
+                        buf.push_str(&get_message(bundle, "watch-list-item", Some(args)).unwrap());
+                        buf.push('\n');
+                    }
+                }
+
+                buf.push('\n');
+                buf.push_str(&get_message(bundle, "watch-usage", None).unwrap());
+
+                buf
+            },
+        )
+        .await;
+
+    handler
+        .make_request(&SendMessage {
+            chat_id: message.chat_id(),
+            reply_to_message_id: Some(message.message_id),
+            text,
+            ..Default::default()
+        })
+        .await?;
+
+    Ok(())
+}
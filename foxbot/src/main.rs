@@ -6,11 +6,16 @@ use tokio::sync::{Mutex, RwLock};
 use tracing::Instrument;
 use unic_langid::LanguageIdentifier;
 
+use callback::CallbackData;
 use foxbot_models::DisplayableErrorMessage;
 use foxbot_utils::*;
+use registry::{CommandChats, CommandDef, CommandPermission, ParsedCommand};
 
+mod callback;
 mod coconut;
 mod handlers;
+mod registry;
+mod transcode;
 mod web;
 
 lazy_static::lazy_static! {
@@ -18,6 +23,7 @@ lazy_static::lazy_static! {
     static ref HANDLING_DURATION: prometheus::Histogram = prometheus::register_histogram!("foxbot_handling_duration_seconds", "Request processing time duration").unwrap();
     static ref HANDLER_DURATION: prometheus::HistogramVec = prometheus::register_histogram_vec!("foxbot_handler_duration_seconds", "Time for a handler to complete", &["handler"]).unwrap();
     static ref TELEGRAM_REQUEST: prometheus::Counter = prometheus::register_counter!("foxbot_telegram_request_total", "Number of requests made to Telegram").unwrap();
+    static ref USED_COMMANDS: prometheus::HistogramVec = prometheus::register_histogram_vec!("foxbot_commands_duration_seconds", "Processing duration for each command", &["command"]).unwrap();
     static ref TELEGRAM_ERROR: prometheus::Counter = prometheus::register_counter!("foxbot_telegram_error_total", "Number of errors returned by Telegram").unwrap();
 }
 
@@ -27,6 +33,17 @@ pub type UpdateSender = tokio::sync::mpsc::Sender<(HandlerUpdate, tracing::Span)
 static CONCURRENT_HANDLERS: usize = 2;
 static INLINE_HANDLERS: usize = 10;
 
+/// Default number of days to keep source notification subscriptions.
+static SUBSCRIPTION_RETENTION_DAYS: i32 = 30;
+/// Default number of days to keep inline history.
+static INLINE_HISTORY_RETENTION_DAYS: i32 = 90;
+/// Default number of days to keep group images for repost detection.
+static GROUP_REPOST_RETENTION_DAYS: i32 = 30;
+/// Default number of days to keep actions taken in chats.
+static CHAT_ACTION_RETENTION_DAYS: i32 = 30;
+/// Hours between digests of chat actions sent to admins.
+static CHAT_DIGEST_INTERVAL_HOURS: i32 = 24;
+
 /// Artwork used for examples throughout the bot.
 static STARTING_ARTWORK: &[&str] = &[
     "https://www.furaffinity.net/view/33742297/",
@@ -52,6 +69,7 @@ pub struct Config {
     pub e621_login: String,
     pub e621_api_key: String,
     pub fautil_apitoken: String,
+    pub fautil_endpoint: Option<String>,
 
     // Twitter config
     pub twitter_consumer_key: String,
@@ -67,25 +85,32 @@ pub struct Config {
     // Telegram config
     telegram_apitoken: String,
 
-    // File storage
-    pub s3_endpoint: String,
-    pub s3_region: String,
-    pub s3_token: String,
-    pub s3_secret: String,
-    pub s3_bucket: String,
-    pub s3_url: String,
-
     // Video storage
-    b2_account_id: String,
-    b2_app_key: String,
-    b2_bucket_id: String,
+    b2_account_id: Option<String>,
+    b2_app_key: Option<String>,
+    b2_bucket_id: Option<String>,
 
     // Video encoding
-    coconut_apitoken: String,
-    coconut_secret: String,
+    video_transcoder: Option<String>,
+    coconut_apitoken: Option<String>,
+    coconut_secret: Option<String>,
 
     // Inline image processing options
     pub cache_all_images: Option<bool>,
+    pub file_cache_chat: Option<i64>,
+
+    // Source notification options
+    pub subscription_max: Option<i64>,
+    pub subscription_retention_days: Option<i32>,
+
+    // Inline history options
+    pub inline_history_retention_days: Option<i32>,
+
+    // Repost detection options
+    pub group_repost_retention_days: Option<i32>,
+
+    // Admin digest options
+    pub chat_action_retention_days: Option<i32>,
 
     // Connections
     redis_dsn: String,
@@ -97,6 +122,36 @@ pub struct Config {
     internal_secret: String,
 }
 
+/// Create the configured video transcoder, defaulting to Coconut.
+fn get_transcoder(
+    config: &Config,
+    faktory: Arc<std::sync::Mutex<faktory::Producer<std::net::TcpStream>>>,
+) -> transcode::BoxedTranscoder {
+    match config.video_transcoder.as_deref() {
+        Some("local") => Box::new(transcode::LocalTranscoder::new(faktory)),
+        None | Some("coconut") => {
+            let required = |value: &Option<String>, name: &str| {
+                value
+                    .clone()
+                    .unwrap_or_else(|| panic!("Coconut transcoder requires {}", name))
+            };
+
+            Box::new(coconut::Coconut::new(
+                required(&config.coconut_apitoken, "COCONUT_APITOKEN"),
+                format!(
+                    "{}/coconut/{}",
+                    config.internet_url,
+                    required(&config.coconut_secret, "COCONUT_SECRET")
+                ),
+                required(&config.b2_account_id, "B2_ACCOUNT_ID"),
+                required(&config.b2_app_key, "B2_APP_KEY"),
+                required(&config.b2_bucket_id, "B2_BUCKET_ID"),
+            ))
+        }
+        Some(other) => panic!("Unknown video transcoder: {}", other),
+    }
+}
+
 /// Configure tracing with Jaeger.
 fn configure_tracing(collector: String) {
     use opentelemetry::KeyValue;
@@ -167,6 +222,9 @@ pub enum ServiceData {
         video_url: String,
         thumb_url: String,
     },
+    VideoFailed {
+        display_name: String,
+    },
     TwitterVerified {
         token: String,
         verifier: String,
@@ -197,6 +255,11 @@ async fn main() {
         Err(err) => panic!("{:#?}", err),
     };
 
+    let storage_config = match envy::from_env::<foxbot_utils::StorageConfig>() {
+        Ok(config) => config,
+        Err(err) => panic!("{:#?}", err),
+    };
+
     let jaeger_collector = match &config.jaeger_collector {
         Some(collector) => collector.clone(),
         _ => panic!("Missing JAEGER_COLLECTOR"),
@@ -215,14 +278,15 @@ async fn main() {
         .await
         .expect("unable to run database migrations");
 
-    let fapi = Arc::new(fuzzysearch::FuzzySearch::new(
+    let fapi = foxbot_sites::new_fuzzysearch(
         config.fautil_apitoken.clone(),
-    ));
+        config.fautil_endpoint.clone(),
+    );
 
     let sites = foxbot_sites::get_all_sites(
         config.fa_a.clone(),
         config.fa_b.clone(),
-        config.fautil_apitoken.clone(),
+        fapi.clone(),
         config.weasyl_apitoken.clone(),
         config.twitter_consumer_key.clone(),
         config.twitter_consumer_secret.clone(),
@@ -275,33 +339,19 @@ async fn main() {
         Box::new(handlers::PhotoHandler),
         Box::new(handlers::CommandHandler),
         Box::new(handlers::GroupSourceHandler),
+        Box::new(handlers::GroupMirrorHandler),
         Box::new(handlers::ErrorReplyHandler::new()),
         Box::new(handlers::SettingsHandler),
         Box::new(handlers::TwitterHandler),
         Box::new(handlers::SubscribeHandler),
+        Box::new(handlers::WatchHandler),
         Box::new(handlers::ErrorCleanup),
         Box::new(handlers::PermissionHandler),
     ];
 
-    let region = rusoto_core::Region::Custom {
-        name: config.s3_region.clone(),
-        endpoint: config.s3_endpoint.clone(),
-    };
-
-    let client = rusoto_core::request::HttpClient::new().unwrap();
-    let provider = rusoto_credential::StaticProvider::new_minimal(
-        config.s3_token.clone(),
-        config.s3_secret.clone(),
-    );
-    let s3 = rusoto_s3::S3Client::new_with(client, provider, region);
-
-    let coconut = coconut::Coconut::new(
-        config.coconut_apitoken.clone(),
-        format!("{}/coconut/{}", config.internet_url, config.coconut_secret),
-        config.b2_account_id.clone(),
-        config.b2_app_key.clone(),
-        config.b2_bucket_id.clone(),
-    );
+    let storage = foxbot_utils::get_storage(&storage_config)
+        .await
+        .expect("Unable to configure storage");
 
     let redis_client = redis::Client::open(config.redis_dsn.clone()).unwrap();
     let redis = redis::aio::ConnectionManager::new(redis_client)
@@ -310,6 +360,9 @@ async fn main() {
 
     let faktory = faktory::Producer::connect(config.faktory_url.as_deref())
         .expect("Unable to connect to Faktory");
+    let faktory = Arc::new(std::sync::Mutex::new(faktory));
+
+    let transcoder = get_transcoder(&config, faktory.clone());
 
     let handler = Arc::new(MessageHandler {
         bot_user,
@@ -321,9 +374,9 @@ async fn main() {
         bot: bot.clone(),
         fapi,
         finder,
-        s3,
-        coconut,
-        faktory: Arc::new(std::sync::Mutex::new(faktory)),
+        storage: storage.clone(),
+        transcoder,
+        faktory,
 
         sites: Mutex::new(sites),
         conn: pool.clone(),
@@ -360,6 +413,7 @@ async fn main() {
         allowed_updates: Some(vec![
             "message".into(),
             "channel_post".into(),
+            "edited_channel_post".into(),
             "inline_query".into(),
             "chosen_inline_result".into(),
             "callback_query".into(),
@@ -371,9 +425,116 @@ async fn main() {
         panic!("unable to set webhook: {:?}", e);
     }
 
+    if let Err(err) = registry::publish_commands(&bot, &handler.langs, L10N_LANGS).await {
+        tracing::error!("unable to publish commands: {:?}", err);
+    }
+
+    // Periodically remove subscriptions that were never matched, old inline
+    // history, old group images, and old chat actions, and send any digests
+    // that are due.
+    let conn = pool.clone();
+    let faktory = handler.faktory.clone();
+    let retention_days = config
+        .subscription_retention_days
+        .unwrap_or(SUBSCRIPTION_RETENTION_DAYS);
+    let history_retention_days = config
+        .inline_history_retention_days
+        .unwrap_or(INLINE_HISTORY_RETENTION_DAYS);
+    let repost_retention_days = config
+        .group_repost_retention_days
+        .unwrap_or(GROUP_REPOST_RETENTION_DAYS);
+    let action_retention_days = config
+        .chat_action_retention_days
+        .unwrap_or(CHAT_ACTION_RETENTION_DAYS);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(60 * 60));
+
+        loop {
+            interval.tick().await;
+
+            match foxbot_models::Subscriptions::expire_subscriptions(&conn, retention_days).await {
+                Ok(count) => tracing::debug!(count, "expired old subscriptions"),
+                Err(err) => tracing::error!("unable to expire subscriptions: {:?}", err),
+            }
+
+            match foxbot_models::InlineHistory::expire(&conn, history_retention_days).await {
+                Ok(count) => tracing::debug!(count, "expired old inline history"),
+                Err(err) => tracing::error!("unable to expire inline history: {:?}", err),
+            }
+
+            match foxbot_models::GroupRepost::expire(&conn, repost_retention_days).await {
+                Ok(count) => tracing::debug!(count, "expired old group images"),
+                Err(err) => tracing::error!("unable to expire group images: {:?}", err),
+            }
+
+            match foxbot_models::ChatActions::expire(&conn, action_retention_days).await {
+                Ok(count) => tracing::debug!(count, "expired old chat actions"),
+                Err(err) => tracing::error!("unable to expire chat actions: {:?}", err),
+            }
+
+            // Digests are only marked as sent once they were delivered, so any
+            // that fail here are tried again next time.
+            match foxbot_models::ChatDigest::due(&conn, CHAT_DIGEST_INTERVAL_HOURS).await {
+                Ok(digests) => {
+                    tracing::debug!(count = digests.len(), "sending chat digests");
+
+                    let custom = get_faktory_custom();
+                    let faktory = faktory.clone();
+                    tokio::task::spawn_blocking(move || {
+                        let mut faktory = faktory.lock().unwrap();
+
+                        for digest in digests {
+                            let chat_id = digest.chat_id;
+
+                            let digest = match serde_json::to_value(&digest) {
+                                Ok(digest) => digest,
+                                Err(err) => {
+                                    tracing::error!(chat_id, "unable to encode digest: {:?}", err);
+                                    continue;
+                                }
+                            };
+
+                            let mut job = faktory::Job::new("chat_digest", vec![digest])
+                                .on_queue("foxbot_background");
+                            job.custom = custom.clone();
+
+                            if let Err(err) = faktory.enqueue(job) {
+                                tracing::error!(chat_id, "unable to enqueue digest: {:?}", err);
+                            }
+                        }
+                    });
+                }
+                Err(err) => tracing::error!("unable to get due chat digests: {:?}", err),
+            }
+        }
+    });
+
+    // Periodically restart or fail videos that stopped making progress.
+    let video_handler = handler.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+
+        loop {
+            interval.tick().await;
+
+            if let Err(err) = handlers::check_stalled_videos(&video_handler).await {
+                tracing::error!("unable to check stalled videos: {:?}", err);
+            }
+        }
+    });
+
     std::thread::spawn(|| {
         actix_web::rt::System::new().block_on(async move {
-            web::serve(config, inline_tx, update_tx, pool, bot).await;
+            web::serve(
+                config,
+                inline_tx,
+                update_tx,
+                pool,
+                bot,
+                storage,
+                storage_config.local_path(),
+            )
+            .await;
         });
     });
 
@@ -433,10 +594,10 @@ pub struct MessageHandler {
 
     // API clients
     pub bot: Arc<Telegram>,
-    pub fapi: Arc<fuzzysearch::FuzzySearch>,
+    pub fapi: foxbot_sites::BoxedReverseSearch,
     pub finder: linkify::LinkFinder,
-    pub s3: rusoto_s3::S3Client,
-    pub coconut: coconut::Coconut,
+    pub storage: foxbot_utils::BoxedStorage,
+    pub transcoder: transcode::BoxedTranscoder,
     pub faktory: Arc<std::sync::Mutex<faktory::Producer<std::net::TcpStream>>>,
 
     // Configuration
@@ -558,11 +719,7 @@ impl MessageHandler {
             .unwrap();
 
         let delete_markup = Some(ReplyMarkup::InlineKeyboardMarkup(InlineKeyboardMarkup {
-            inline_keyboard: vec![vec![InlineKeyboardButton {
-                text: "Delete".to_string(),
-                callback_data: Some("delete".to_string()),
-                ..Default::default()
-            }]],
+            inline_keyboard: vec![vec![CallbackData::Delete.button("Delete".to_string())]],
         }));
 
         if recent_error_count > 0 {
@@ -696,6 +853,74 @@ impl MessageHandler {
         self.make_request(&send_message).await.map_err(Into::into)
     }
 
+    /// Build an inline keyboard with the direct link to a post and its
+    /// source, if available.
+    async fn post_keyboard(
+        &self,
+        language_code: Option<&str>,
+        url: &str,
+        source_link: Option<&str>,
+        site_name: &str,
+    ) -> InlineKeyboardMarkup {
+        let direct = self
+            .get_fluent_bundle(language_code, |bundle| {
+                get_message(bundle, "inline-direct", None).unwrap()
+            })
+            .await;
+
+        let mut row = vec![InlineKeyboardButton {
+            text: direct,
+            url: Some(url.to_string()),
+            callback_data: None,
+            ..Default::default()
+        }];
+
+        if let Some(source_link) = source_link {
+            row.push(InlineKeyboardButton {
+                text: site_name.to_string(),
+                url: Some(source_link.to_string()),
+                callback_data: None,
+                ..Default::default()
+            })
+        }
+
+        InlineKeyboardMarkup {
+            inline_keyboard: vec![row],
+        }
+    }
+
+    /// Send the untouched original file at a URL as a document. The file ID
+    /// is remembered so each original only has to be downloaded once.
+    async fn send_original(
+        &self,
+        chat_id: ChatID,
+        url: &str,
+        file_type: &str,
+        reply_markup: Option<ReplyMarkup>,
+        reply_to_message_id: Option<i32>,
+    ) -> anyhow::Result<Message> {
+        let document = match foxbot_models::DocumentCache::get(&self.redis, url).await? {
+            Some(file_id) => FileType::FileID(file_id),
+            None => download_original(url, file_type).await?,
+        };
+
+        let message = self
+            .make_request(&SendDocument {
+                chat_id,
+                document,
+                reply_markup,
+                reply_to_message_id,
+                ..Default::default()
+            })
+            .await?;
+
+        if let Some(document) = &message.document {
+            foxbot_models::DocumentCache::set(&self.redis, url, &document.file_id).await?;
+        }
+
+        Ok(message)
+    }
+
     #[tracing::instrument(skip(self, handler_update), fields(user_id, chat_id))]
     async fn handle_update(&self, handler_update: HandlerUpdate) {
         let _hist = HANDLING_DURATION.start_timer();
@@ -733,11 +958,22 @@ impl MessageHandler {
             tracing::Span::current().record("chat_id", &chat.id);
         }
 
+        if let Some(callback_query) = &update.callback_query {
+            self.route_callback(callback_query).await;
+            return;
+        }
+
         let command = update
             .message
             .as_ref()
             .and_then(|message| message.get_command());
 
+        if let (Some(message), Some(command)) = (&update.message, &command) {
+            if self.route_command(message, command).await {
+                return;
+            }
+        }
+
         for handler in &self.handlers {
             let hist = HANDLER_DURATION
                 .get_metric_with_label_values(&[handler.name()])
@@ -789,6 +1025,239 @@ impl MessageHandler {
         }
     }
 
+    /// Send a registered command to the handler responsible for it. Returns
+    /// if the command was used and should not be passed to other handlers.
+    async fn route_command(&self, message: &Message, command: &Command) -> bool {
+        if let Some(username) = &command.username {
+            let bot_username = self.bot_user.username.as_ref().unwrap();
+            if !username.eq_ignore_ascii_case(bot_username) {
+                tracing::debug!(?username, "got command for other bot");
+                return true;
+            }
+        }
+
+        let def = match registry::find_command(&command.name) {
+            Some(def) => def,
+            None => {
+                tracing::info!(command = ?command.name, "unknown command");
+                return false;
+            }
+        };
+
+        let handler = match self
+            .handlers
+            .iter()
+            .find(|handler| handler.name() == def.handler)
+        {
+            Some(handler) => handler,
+            None => {
+                tracing::error!(handler = def.handler, "no handler for command");
+                return true;
+            }
+        };
+
+        let _hist = USED_COMMANDS
+            .get_metric_with_label_values(&[def.name])
+            .unwrap()
+            .start_timer();
+        tracing::debug!(command = def.name, "got command");
+
+        if let Err(err) = self
+            .run_command(handler, message, def)
+            .instrument(tracing::info_span!(
+                "handler_command",
+                handler = handler.name()
+            ))
+            .await
+        {
+            tracing::error!(handled_by = handler.name(), "Handler error: {:?}", err);
+
+            let mut tags = vec![
+                ("handler", handler.name().to_string()),
+                ("command", def.name.to_string()),
+                ("chat_id", message.chat.id.to_string()),
+            ];
+            if let Some(user) = &message.from {
+                tags.push(("user_id", user.id.to_string()));
+            }
+
+            self.report_error(message, err, Some(tags), |err| capture_anyhow(&err))
+                .await;
+        }
+
+        true
+    }
+
+    /// Check that a command may be used in this chat by this user and parse
+    /// its arguments before running it, replying with an explanation if not.
+    async fn run_command(
+        &self,
+        handler: &BoxedHandler,
+        message: &Message,
+        def: &'static CommandDef,
+    ) -> anyhow::Result<()> {
+        if !def.chats.allows(&message.chat.chat_type) {
+            let name = match def.chats {
+                CommandChats::Private => "command-private-only",
+                _ => "command-group-only",
+            };
+
+            self.send_generic_reply(message, name).await?;
+            return Ok(());
+        }
+
+        if let CommandPermission::GroupAdmin { bot_admin } = def.permission {
+            if !self.is_group_admin(message, bot_admin).await? {
+                return Ok(());
+            }
+        }
+
+        let args = match def.parse_args(message.text.as_deref().unwrap_or_default()) {
+            Some(args) => args,
+            None => {
+                self.send_generic_reply(message, def.usage.unwrap_or("command-invalid-args"))
+                    .await?;
+                return Ok(());
+            }
+        };
+
+        handler
+            .handle_command(self, message, &ParsedCommand { def, args })
+            .await
+    }
+
+    /// Check if the user who sent a message in a group is an admin, and
+    /// optionally if the bot is also an admin, replying with an explanation
+    /// if not.
+    async fn is_group_admin(
+        &self,
+        message: &Message,
+        bot_needs_admin: bool,
+    ) -> anyhow::Result<bool> {
+        let user = message.from.as_ref().unwrap();
+
+        if !self.is_chat_admin(message.chat.id, user.id).await? {
+            self.send_generic_reply(message, "automatic-enable-not-admin")
+                .await?;
+            return Ok(false);
+        }
+
+        if !bot_needs_admin {
+            return Ok(true);
+        }
+
+        if !self
+            .is_chat_admin(message.chat.id, self.bot_user.id)
+            .await?
+        {
+            self.send_generic_reply(message, "automatic-enable-bot-not-admin")
+                .await?;
+            return Ok(false);
+        }
+
+        Ok(true)
+    }
+
+    /// Check if a user is an admin in a chat.
+    async fn is_chat_admin(&self, chat_id: i64, user_id: i64) -> anyhow::Result<bool> {
+        use tgbotapi::ChatMemberStatus::*;
+
+        // As of the Bot API 5.1, Telegram can now proactively send updates
+        // about user information. There is another handler that listens for
+        // these changes and saves them to the database. When possible we should
+        // use these saved values instead of making more requests.
+        if let Some(is_admin) =
+            foxbot_models::ChatAdmin::is_admin(&self.conn, user_id, chat_id).await?
+        {
+            return Ok(is_admin);
+        }
+
+        let get_chat_member = GetChatMember {
+            chat_id: chat_id.into(),
+            user_id,
+        };
+        let chat_member = self.make_request(&get_chat_member).await?;
+
+        if user_id == self.bot_user.id {
+            // Already fetching it, should save it for trying to delete summoning
+            // messages.
+            foxbot_models::GroupConfig::set(
+                &self.conn,
+                foxbot_models::GroupConfigKey::HasDeletePermission,
+                chat_id,
+                chat_member.can_delete_messages.unwrap_or(false),
+            )
+            .await?;
+        }
+
+        Ok(matches!(chat_member.status, Administrator | Creator))
+    }
+
+    /// Decode callback data and send it to the handler responsible for it.
+    /// Data that can't be decoded is answered with an error instead.
+    async fn route_callback(&self, callback_query: &CallbackQuery) {
+        let data = match callback_query
+            .data
+            .as_deref()
+            .map(str::parse::<CallbackData>)
+        {
+            Some(Ok(data)) => data,
+            Some(Err(err)) => {
+                tracing::warn!("got invalid callback data: {}", err);
+
+                let text = self
+                    .get_fluent_bundle(callback_query.from.language_code.as_deref(), |bundle| {
+                        get_message(bundle, "error-callback-invalid", None).unwrap()
+                    })
+                    .await;
+
+                if let Err(err) = self
+                    .make_request(&AnswerCallbackQuery {
+                        callback_query_id: callback_query.id.clone(),
+                        text: Some(text),
+                        ..Default::default()
+                    })
+                    .await
+                {
+                    tracing::error!("unable to answer invalid callback query: {:?}", err);
+                }
+
+                return;
+            }
+            None => return,
+        };
+
+        let handler = match self
+            .handlers
+            .iter()
+            .find(|handler| handler.name() == data.handler())
+        {
+            Some(handler) => handler,
+            None => {
+                tracing::error!(handler = data.handler(), "no handler for callback data");
+                return;
+            }
+        };
+
+        let _hist = HANDLER_DURATION
+            .get_metric_with_label_values(&[handler.name()])
+            .unwrap()
+            .start_timer();
+
+        if let Err(err) = handler
+            .handle_callback(self, callback_query, &data)
+            .instrument(tracing::info_span!(
+                "handler_callback",
+                handler = handler.name()
+            ))
+            .await
+        {
+            tracing::error!(handled_by = handler.name(), "Handler error: {:?}", err);
+
+            capture_anyhow(&err);
+        }
+    }
+
     pub async fn make_request<T>(&self, request: &T) -> Result<T::Response, Error>
     where
         T: TelegramRequest,
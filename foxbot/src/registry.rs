@@ -0,0 +1,546 @@
+use foxbot_models::Sites;
+use tgbotapi::ChatType;
+
+/// Chats a command may be used in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CommandChats {
+    /// Any chat with a user, private or group.
+    All,
+    /// Only private chats with the bot.
+    Private,
+    /// Only groups and supergroups.
+    Groups,
+}
+
+impl CommandChats {
+    /// If a command with these chats may be used in a chat of this type.
+    pub fn allows(self, chat_type: &ChatType) -> bool {
+        match self {
+            CommandChats::All => *chat_type == ChatType::Private || chat_type.is_group(),
+            CommandChats::Private => *chat_type == ChatType::Private,
+            CommandChats::Groups => chat_type.is_group(),
+        }
+    }
+}
+
+/// Permission a user needs before they may use a command.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CommandPermission {
+    /// Anyone may use the command.
+    Anyone,
+    /// The user must be a group admin. If `bot_admin` is set, the bot must
+    /// also be an admin in the group.
+    GroupAdmin { bot_admin: bool },
+}
+
+/// How the text after a command is parsed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ArgParser {
+    /// Anything after the command is ignored.
+    None,
+    /// Everything after the command, if anything was given.
+    Text,
+    /// Any of the given words, in any order. Other words are ignored so
+    /// they can still be used by the command, such as links.
+    Flags(&'static [&'static str]),
+    /// A site name followed by an artist name. The site name is not case
+    /// sensitive and the artist is everything after the site.
+    SiteArtist { required: bool },
+}
+
+/// Arguments parsed from a command.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CommandArgs {
+    None,
+    Text(Option<String>),
+    Flags(Vec<&'static str>),
+    SiteArtist(Option<(Sites, String)>),
+}
+
+impl CommandArgs {
+    /// Text after the command, if the command takes text.
+    pub fn text(&self) -> Option<&str> {
+        match self {
+            CommandArgs::Text(text) => text.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// If a flag was given.
+    pub fn has_flag(&self, flag: &str) -> bool {
+        match self {
+            CommandArgs::Flags(flags) => flags.contains(&flag),
+            _ => false,
+        }
+    }
+}
+
+/// A command the bot responds to.
+#[derive(Debug)]
+pub struct CommandDef {
+    /// Name of the command, without the leading slash.
+    pub name: &'static str,
+    /// Fluent message key describing the command. Commands without a
+    /// description are not published to Telegram.
+    pub description: Option<&'static str>,
+    /// Fluent message key explaining how to use the command, sent when the
+    /// arguments could not be parsed.
+    pub usage: Option<&'static str>,
+    pub args: ArgParser,
+    pub chats: CommandChats,
+    pub permission: CommandPermission,
+    /// Name of the handler that should receive this command.
+    pub handler: &'static str,
+}
+
+/// Every command the bot responds to.
+///
+/// Commands are published to Telegram in this order.
+pub static COMMANDS: &[CommandDef] = &[
+    CommandDef {
+        name: "start",
+        description: None,
+        usage: None,
+        args: ArgParser::Text,
+        chats: CommandChats::All,
+        permission: CommandPermission::Anyone,
+        handler: "inline",
+    },
+    CommandDef {
+        name: "help",
+        description: Some("command-help-description"),
+        usage: None,
+        args: ArgParser::None,
+        chats: CommandChats::All,
+        permission: CommandPermission::Anyone,
+        handler: "command",
+    },
+    CommandDef {
+        name: "mirror",
+        description: Some("command-mirror-description"),
+        usage: None,
+        args: ArgParser::Flags(&["file"]),
+        chats: CommandChats::All,
+        permission: CommandPermission::Anyone,
+        handler: "command",
+    },
+    CommandDef {
+        name: "source",
+        description: Some("command-source-description"),
+        usage: None,
+        args: ArgParser::None,
+        chats: CommandChats::All,
+        permission: CommandPermission::Anyone,
+        handler: "command",
+    },
+    CommandDef {
+        name: "alts",
+        description: Some("command-alts-description"),
+        usage: None,
+        args: ArgParser::None,
+        chats: CommandChats::All,
+        permission: CommandPermission::Anyone,
+        handler: "command",
+    },
+    CommandDef {
+        name: "settings",
+        description: Some("command-settings-description"),
+        usage: None,
+        args: ArgParser::None,
+        chats: CommandChats::All,
+        permission: CommandPermission::Anyone,
+        handler: "settings",
+    },
+    CommandDef {
+        name: "caption",
+        description: Some("command-caption-description"),
+        usage: None,
+        args: ArgParser::Text,
+        chats: CommandChats::All,
+        permission: CommandPermission::Anyone,
+        handler: "settings",
+    },
+    CommandDef {
+        name: "subscriptions",
+        description: Some("command-subscriptions-description"),
+        usage: None,
+        args: ArgParser::None,
+        chats: CommandChats::Private,
+        permission: CommandPermission::Anyone,
+        handler: "subscribe",
+    },
+    CommandDef {
+        name: "watch",
+        description: Some("command-watch-description"),
+        usage: Some("watch-usage"),
+        args: ArgParser::SiteArtist { required: false },
+        chats: CommandChats::Private,
+        permission: CommandPermission::Anyone,
+        handler: "watch",
+    },
+    CommandDef {
+        name: "unwatch",
+        description: Some("command-unwatch-description"),
+        usage: Some("watch-usage"),
+        args: ArgParser::SiteArtist { required: true },
+        chats: CommandChats::Private,
+        permission: CommandPermission::Anyone,
+        handler: "watch",
+    },
+    CommandDef {
+        name: "twitter",
+        description: Some("command-twitter-description"),
+        usage: None,
+        args: ArgParser::None,
+        chats: CommandChats::Private,
+        permission: CommandPermission::Anyone,
+        handler: "twitter",
+    },
+    CommandDef {
+        name: "clearhistory",
+        description: Some("command-clearhistory-description"),
+        usage: None,
+        args: ArgParser::None,
+        chats: CommandChats::All,
+        permission: CommandPermission::Anyone,
+        handler: "command",
+    },
+    CommandDef {
+        name: "groupsettings",
+        description: Some("command-groupsettings-description"),
+        usage: None,
+        args: ArgParser::None,
+        chats: CommandChats::Groups,
+        permission: CommandPermission::GroupAdmin { bot_admin: false },
+        handler: "settings",
+    },
+    CommandDef {
+        name: "groupsource",
+        description: Some("command-groupsource-description"),
+        usage: None,
+        args: ArgParser::None,
+        chats: CommandChats::Groups,
+        permission: CommandPermission::GroupAdmin { bot_admin: true },
+        handler: "command",
+    },
+    CommandDef {
+        name: "grouppreviews",
+        description: Some("command-grouppreviews-description"),
+        usage: None,
+        args: ArgParser::None,
+        chats: CommandChats::Groups,
+        permission: CommandPermission::GroupAdmin { bot_admin: false },
+        handler: "command",
+    },
+    CommandDef {
+        name: "groupalbums",
+        description: Some("command-groupalbums-description"),
+        usage: None,
+        args: ArgParser::None,
+        chats: CommandChats::Groups,
+        permission: CommandPermission::GroupAdmin { bot_admin: false },
+        handler: "command",
+    },
+    CommandDef {
+        name: "groupcompact",
+        description: Some("command-groupcompact-description"),
+        usage: None,
+        args: ArgParser::None,
+        chats: CommandChats::Groups,
+        permission: CommandPermission::GroupAdmin { bot_admin: false },
+        handler: "command",
+    },
+    CommandDef {
+        name: "error",
+        description: None,
+        usage: None,
+        args: ArgParser::None,
+        chats: CommandChats::All,
+        permission: CommandPermission::Anyone,
+        handler: "command",
+    },
+];
+
+/// Find a command by its name, as sent by Telegram with the leading slash.
+pub fn find_command(name: &str) -> Option<&'static CommandDef> {
+    let name = name.strip_prefix('/')?;
+
+    COMMANDS
+        .iter()
+        .find(|def| def.name.eq_ignore_ascii_case(name))
+}
+
+/// A command from a message, with its parsed arguments.
+#[derive(Debug)]
+pub struct ParsedCommand {
+    pub def: &'static CommandDef,
+    pub args: CommandArgs,
+}
+
+impl ParsedCommand {
+    /// Name of the command, without the leading slash.
+    pub fn name(&self) -> &'static str {
+        self.def.name
+    }
+}
+
+impl CommandDef {
+    /// Parse arguments from a message's text, returning `None` if they were
+    /// not valid.
+    pub fn parse_args(&self, text: &str) -> Option<CommandArgs> {
+        let rest = text
+            .splitn(2, char::is_whitespace)
+            .nth(1)
+            .map(str::trim)
+            .filter(|rest| !rest.is_empty());
+
+        let args = match self.args {
+            ArgParser::None => CommandArgs::None,
+            ArgParser::Text => CommandArgs::Text(rest.map(str::to_string)),
+            ArgParser::Flags(known) => CommandArgs::Flags(
+                known
+                    .iter()
+                    .copied()
+                    .filter(|flag| {
+                        rest.unwrap_or_default()
+                            .split_whitespace()
+                            .any(|word| word.eq_ignore_ascii_case(flag))
+                    })
+                    .collect(),
+            ),
+            ArgParser::SiteArtist { required } => match rest {
+                Some(rest) => CommandArgs::SiteArtist(Some(parse_site_artist(rest)?)),
+                None if required => return None,
+                None => CommandArgs::SiteArtist(None),
+            },
+        };
+
+        Some(args)
+    }
+
+    /// If this command should be published for the given chat scope. Admin
+    /// commands are only published to admins.
+    fn published_for(&self, chats: CommandChats, admins: bool) -> bool {
+        if self.description.is_none() {
+            return false;
+        }
+
+        let chats_match = match chats {
+            CommandChats::Private => self.chats != CommandChats::Groups,
+            CommandChats::Groups => self.chats != CommandChats::Private,
+            CommandChats::All => true,
+        };
+
+        let permission_match = match self.permission {
+            CommandPermission::Anyone => true,
+            CommandPermission::GroupAdmin { .. } => admins,
+        };
+
+        chats_match && permission_match
+    }
+}
+
+fn parse_site_artist(text: &str) -> Option<(Sites, String)> {
+    let mut parts = text.splitn(2, char::is_whitespace);
+
+    let site = parts.next()?;
+    let site = Sites::default_order()
+        .into_iter()
+        .find(|known| known.as_str().eq_ignore_ascii_case(site))?;
+
+    let artist = parts.next()?.trim();
+    if artist.is_empty() {
+        return None;
+    }
+
+    Some((site, artist.to_string()))
+}
+
+/// Scopes commands are published for, with the chats and if admin commands
+/// should be included.
+fn scopes() -> Vec<(tgbotapi::BotCommandScope, CommandChats, bool)> {
+    vec![
+        (
+            tgbotapi::BotCommandScope::AllPrivateChats,
+            CommandChats::Private,
+            false,
+        ),
+        (
+            tgbotapi::BotCommandScope::AllGroupChats,
+            CommandChats::Groups,
+            false,
+        ),
+        (
+            tgbotapi::BotCommandScope::AllChatAdministrators,
+            CommandChats::Groups,
+            true,
+        ),
+    ]
+}
+
+/// Build the list of commands to publish for a scope.
+fn bot_commands(
+    bundle: &fluent::concurrent::FluentBundle<fluent::FluentResource>,
+    chats: CommandChats,
+    admins: bool,
+) -> Vec<tgbotapi::BotCommand> {
+    COMMANDS
+        .iter()
+        .filter(|def| def.published_for(chats, admins))
+        .filter_map(|def| {
+            let description = foxbot_utils::get_message(bundle, def.description?, None).ok()?;
+
+            Some(tgbotapi::BotCommand {
+                command: def.name.to_string(),
+                description,
+            })
+        })
+        .collect()
+}
+
+/// Publish the command list for each scope and language to Telegram, so
+/// clients can suggest them.
+///
+/// The first language is also published without a language code so it is
+/// used for any language we do not have.
+pub async fn publish_commands(
+    bot: &tgbotapi::Telegram,
+    langs: &foxbot_utils::Langs,
+    lang_codes: &[&str],
+) -> anyhow::Result<()> {
+    for (idx, lang) in lang_codes.iter().enumerate() {
+        let bundle = foxbot_utils::get_lang_bundle(langs, lang);
+
+        // Telegram only accepts the two letter language code.
+        let language_code = lang.split('-').next().map(str::to_string);
+
+        for (scope, chats, admins) in scopes() {
+            let commands = bot_commands(&bundle, chats, admins);
+
+            let mut language_codes = vec![language_code.clone()];
+            if idx == 0 {
+                language_codes.push(None);
+            }
+
+            for language_code in language_codes {
+                tracing::debug!(
+                    ?scope,
+                    ?language_code,
+                    count = commands.len(),
+                    "publishing commands"
+                );
+
+                bot.make_request(&tgbotapi::requests::SetMyCommands {
+                    commands: commands.clone(),
+                    scope: Some(scope.clone()),
+                    language_code,
+                })
+                .await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_commands_valid() {
+        let mut langs = foxbot_utils::Langs::new();
+        langs.insert(
+            "en-US".parse().unwrap(),
+            vec![include_str!("../../langs/en-US/foxbot.ftl").to_string()],
+        );
+        let bundle = foxbot_utils::get_lang_bundle(&langs, "en-US");
+
+        for (idx, def) in COMMANDS.iter().enumerate() {
+            assert!(
+                COMMANDS[idx + 1..]
+                    .iter()
+                    .all(|other| other.name != def.name),
+                "{} was registered more than once",
+                def.name
+            );
+
+            assert!(
+                def.name.len() <= 32
+                    && def
+                        .name
+                        .chars()
+                        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_'),
+                "{} is not a valid command name",
+                def.name
+            );
+
+            for key in def.description.iter().chain(def.usage.iter()) {
+                let message = foxbot_utils::get_message(&bundle, key, None)
+                    .unwrap_or_else(|_| panic!("{} was missing message {}", def.name, key));
+
+                if Some(*key) == def.description {
+                    assert!(
+                        (3..=256).contains(&message.chars().count()),
+                        "{} description was the wrong length",
+                        def.name
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let mirror = find_command("/mirror").unwrap();
+        let args = mirror
+            .parse_args("/mirror https://example.com FILE")
+            .unwrap();
+        assert!(args.has_flag("file"));
+        assert!(!mirror.parse_args("/mirror").unwrap().has_flag("file"));
+
+        let caption = find_command("/caption").unwrap();
+        assert_eq!(
+            caption
+                .parse_args("/caption  {title}\n{tags} ")
+                .unwrap()
+                .text(),
+            Some("{title}\n{tags}")
+        );
+        assert_eq!(caption.parse_args("/caption").unwrap().text(), None);
+
+        let watch = find_command("/watch").unwrap();
+        assert_eq!(
+            watch.parse_args("/watch furaffinity some artist"),
+            Some(CommandArgs::SiteArtist(Some((
+                Sites::FurAffinity,
+                "some artist".to_string()
+            ))))
+        );
+        assert_eq!(
+            watch.parse_args("/watch"),
+            Some(CommandArgs::SiteArtist(None))
+        );
+        assert_eq!(watch.parse_args("/watch nowhere artist"), None);
+        assert_eq!(watch.parse_args("/watch e621"), None);
+
+        let unwatch = find_command("/unwatch").unwrap();
+        assert_eq!(unwatch.parse_args("/unwatch"), None);
+    }
+
+    #[test]
+    fn test_published_for() {
+        let groupsource = find_command("/groupsource").unwrap();
+        assert!(groupsource.published_for(CommandChats::Groups, true));
+        assert!(!groupsource.published_for(CommandChats::Groups, false));
+        assert!(!groupsource.published_for(CommandChats::Private, false));
+
+        let watch = find_command("/watch").unwrap();
+        assert!(watch.published_for(CommandChats::Private, false));
+        assert!(!watch.published_for(CommandChats::Groups, true));
+
+        assert!(!find_command("/error")
+            .unwrap()
+            .published_for(CommandChats::Private, false));
+    }
+}
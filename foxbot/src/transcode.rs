@@ -0,0 +1,71 @@
+use async_trait::async_trait;
+
+use foxbot_models::Video;
+use foxbot_utils::get_faktory_custom;
+
+use crate::coconut::Coconut;
+
+pub type BoxedTranscoder = Box<dyn VideoTranscoder>;
+
+/// A service that converts videos into MP4s that can be sent to Telegram.
+///
+/// Transcoders are expected to report their progress and completion as
+/// `ServiceData::VideoProgress` and `ServiceData::VideoComplete` events, and
+/// may report a failure as `ServiceData::VideoFailed`. Transcodes that stop
+/// without reporting anything are found by the stalled video check.
+#[async_trait]
+pub trait VideoTranscoder: Send + Sync {
+    /// Start converting a video, returning the job ID.
+    async fn start_video(&self, video: &Video) -> anyhow::Result<i32>;
+}
+
+#[async_trait]
+impl VideoTranscoder for Coconut {
+    async fn start_video(&self, video: &Video) -> anyhow::Result<i32> {
+        Coconut::start_video(self, &video.url, &video.display_name).await
+    }
+}
+
+/// Transcoder that converts videos with ffmpeg in the background worker.
+pub struct LocalTranscoder {
+    faktory: std::sync::Arc<std::sync::Mutex<faktory::Producer<std::net::TcpStream>>>,
+}
+
+impl LocalTranscoder {
+    pub fn new(
+        faktory: std::sync::Arc<std::sync::Mutex<faktory::Producer<std::net::TcpStream>>>,
+    ) -> Self {
+        Self { faktory }
+    }
+}
+
+#[async_trait]
+impl VideoTranscoder for LocalTranscoder {
+    async fn start_video(&self, video: &Video) -> anyhow::Result<i32> {
+        let data = serde_json::json!({
+            "id": video.id,
+            "source": video.url,
+            "name": video.display_name,
+        });
+
+        let mut job =
+            faktory::Job::new("video_transcode", vec![data]).on_queue("foxbot_background");
+        job.custom = get_faktory_custom();
+        // Stalled videos are restarted by the bot, so Faktory retries would
+        // only create duplicate work.
+        job.retry = Some(0);
+
+        let faktory = self.faktory.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut faktory = faktory.lock().unwrap();
+            faktory
+                .enqueue(job)
+                .map_err(|err| anyhow::anyhow!("unable to enqueue video job: {}", err))
+        })
+        .await??;
+
+        // There is no external job, so the video ID is used to track that it
+        // was started.
+        Ok(video.id)
+    }
+}
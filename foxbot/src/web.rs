@@ -8,7 +8,7 @@ use serde::Deserialize;
 use tgbotapi::Update;
 
 use crate::{Config, HandlerUpdate, ServiceData, UpdateSender};
-use foxbot_utils::find_best_photo;
+use foxbot_utils::{find_best_photo, BoxedStorage, LocalStorage};
 
 #[derive(Deserialize)]
 struct TwitterCallbackRequest {
@@ -45,6 +45,16 @@ struct CoconutWebHookRequestBody {
     output_urls: Option<CoconutWebHookRequestBodyOutputUrls>,
 }
 
+#[derive(Deserialize)]
+struct VideoProgressRequest {
+    name: String,
+    progress: Option<String>,
+    video_url: Option<String>,
+    thumb_url: Option<String>,
+    #[serde(default)]
+    failed: bool,
+}
+
 #[get("/")]
 async fn index(hbs: web::Data<Handlebars<'_>>) -> impl Responder {
     let body = hbs.render("home", &None::<()>).unwrap();
@@ -159,7 +169,7 @@ async fn coconut_webhook(
     config: web::Data<Config>,
     sender: web::Data<(UpdateSender, UpdateSender)>,
 ) -> impl Responder {
-    if secret.into_inner().0 != config.coconut_secret {
+    if Some(secret.into_inner().0) != config.coconut_secret {
         return HttpResponse::Forbidden().finish();
     }
 
@@ -212,6 +222,42 @@ async fn coconut_webhook(
     HttpResponse::Ok().body("✓")
 }
 
+/// Progress updates from the local video transcoder.
+#[post("/video")]
+async fn video_progress(
+    data: web::Json<VideoProgressRequest>,
+    sender: web::Data<(UpdateSender, UpdateSender)>,
+) -> impl Responder {
+    let data = data.into_inner();
+
+    let service_update = match (data.progress, data.video_url, data.thumb_url) {
+        _ if data.failed => ServiceData::VideoFailed {
+            display_name: data.name,
+        },
+        (Some(progress), _, _) => ServiceData::VideoProgress {
+            display_name: data.name,
+            progress,
+        },
+        (None, Some(video_url), Some(thumb_url)) => ServiceData::VideoComplete {
+            display_name: data.name,
+            video_url,
+            thumb_url,
+        },
+        _ => return HttpResponse::BadRequest().body("No progress and no output URLs"),
+    };
+
+    sender
+        .1
+        .send((
+            HandlerUpdate::Service(service_update),
+            tracing::Span::current(),
+        ))
+        .await
+        .unwrap();
+
+    HttpResponse::Ok().body("✓")
+}
+
 #[get("/metrics")]
 async fn metrics() -> impl Responder {
     use prometheus::Encoder;
@@ -234,6 +280,7 @@ struct SourceInfo<'a> {
     media_group_id: &'a str,
     message_id: i32,
     file_id: &'a str,
+    image_url: String,
     urls: Vec<String>,
 }
 
@@ -242,7 +289,7 @@ async fn mediagroup(
     path: web::Path<MediaGroupPath>,
     conn: web::Data<sqlx::Pool<sqlx::Postgres>>,
     hbs: web::Data<Handlebars<'_>>,
-    config: web::Data<Config>,
+    storage: web::Data<BoxedStorage>,
 ) -> impl Responder {
     use foxbot_models::MediaGroup;
 
@@ -252,19 +299,25 @@ async fn mediagroup(
 
     let mut source_info: Vec<_> = messages
         .iter()
-        .map(|message| SourceInfo {
-            media_group_id: message.message.media_group_id.as_ref().unwrap(),
-            message_id: message.message.message_id,
-            file_id: &find_best_photo(&message.message.photo.as_deref().unwrap())
+        .map(|message| {
+            let media_group_id = message.message.media_group_id.as_ref().unwrap();
+            let file_id = &find_best_photo(&message.message.photo.as_deref().unwrap())
                 .unwrap()
-                .file_id,
-            urls: message
-                .sources
-                .as_deref()
-                .unwrap_or_default()
-                .iter()
-                .map(|file| file.url())
-                .collect(),
+                .file_id;
+
+            SourceInfo {
+                media_group_id,
+                message_id: message.message.message_id,
+                file_id,
+                image_url: storage.public_url(&format!("mg/{}/{}", media_group_id, file_id)),
+                urls: message
+                    .sources
+                    .as_deref()
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|file| file.url())
+                    .collect(),
+            }
         })
         .collect();
     source_info.sort_by(|a, b| a.message_id.cmp(&b.message_id));
@@ -273,8 +326,6 @@ async fn mediagroup(
         .render(
             "mediagroup",
             &serde_json::json!({
-                "cdn_url": config.s3_url,
-                "cdn_bucket": config.s3_bucket,
                 "items": source_info,
             }),
         )
@@ -283,12 +334,47 @@ async fn mediagroup(
     HttpResponse::Ok().body(body)
 }
 
+/// Serve files from local storage. Only registered when the local storage
+/// backend is in use.
+#[get("/storage/{key:.*}")]
+async fn storage_file(
+    path: web::Path<(String,)>,
+    root: web::Data<std::path::PathBuf>,
+) -> impl Responder {
+    let path = match LocalStorage::resolve(&root, &path.into_inner().0) {
+        Some(path) => path,
+        None => return HttpResponse::NotFound().finish(),
+    };
+
+    let data = match tokio::fs::read(&path).await {
+        Ok(data) => data,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            return HttpResponse::NotFound().finish()
+        }
+        Err(err) => {
+            tracing::error!("unable to read storage file: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let content_type = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("png") => "image/png",
+        Some("mp4") => "video/mp4",
+        _ => "application/octet-stream",
+    };
+
+    HttpResponse::Ok().content_type(content_type).body(data)
+}
+
 pub async fn serve(
     config: Config,
     high_priority: UpdateSender,
     low_priority: UpdateSender,
     conn: sqlx::Pool<sqlx::Postgres>,
     bot: std::sync::Arc<tgbotapi::Telegram>,
+    storage: BoxedStorage,
+    storage_local_path: Option<std::path::PathBuf>,
 ) {
     tracing::info!("starting web server");
 
@@ -298,6 +384,7 @@ pub async fn serve(
         .expect("templates contained bad data");
     let hbs = web::Data::new(hbs);
     let conn = web::Data::new(conn);
+    let storage = web::Data::new(storage);
 
     let sender = (high_priority, low_priority);
 
@@ -319,12 +406,14 @@ pub async fn serve(
                 },
             ))
             .service(health)
-            .service(metrics);
+            .service(metrics)
+            .service(video_progress);
 
-        App::new()
+        let mut app = App::new()
             .wrap(tracing_actix_web::TracingLogger::default())
             .app_data(hbs.clone())
             .app_data(conn.clone())
+            .app_data(storage.clone())
             .data(bot.clone())
             .data(sender.clone())
             .data(config.clone())
@@ -335,7 +424,13 @@ pub async fn serve(
             .service(health)
             .service(twitter_callback)
             .service(mediagroup)
-            .service(internal_resources)
+            .service(internal_resources);
+
+        if let Some(root) = &storage_local_path {
+            app = app.data(root.clone()).service(storage_file);
+        }
+
+        app
     })
     .workers(4)
     .bind("0.0.0.0:8080")
@@ -13,7 +13,7 @@ welcome =
     
     Add me to your group for features like /mirror (where I mirror all the links in a message, including messages you reply to) or /source (where I attempt to find the source of an image you're replying to).
     
-    If I'm given edit permissions in your channel, I'll automatically edit posts to include a source link.
+    If I'm given edit permissions in your channel, I'll automatically edit posts to include a source link. Send /channelsettings in your channel to choose how sources are added.
     
     For more details, visit { -docsLink }. Also check out { -newsChannelName } for feature updates.
     
@@ -25,7 +25,7 @@ welcome-group =
     I'm here to help with sharing images! If you use me as an inline bot, I can easily get images from most furry sites, while keeping a link to the original source.
     
     I've also got a few commands to help in groups:
-    · /mirror - I'll look at all the links in your message or the message you're replying to and mirror them
+    · /mirror - I'll look at all the links in your message or the message you're replying to and mirror them, or use /mirror file to get the originals as files
     · /source - I'll attempt to find if the photo you're replying to has been posted on FurAffinity
     
     You can also enable automatically finding sources for images posted in here with the /groupsource command. However, I must be an administrator in the group for this to work and it can only be enabled by an administrator.
@@ -34,6 +34,27 @@ welcome-group =
 
 welcome-try-me = Try Me!
 
+# Commands
+command-help-description = Learn what I can do
+command-mirror-description = Mirror links from a message, add "file" for originals
+command-source-description = Find the source of the image you're replying to
+command-alts-description = Find other versions of the image you're replying to
+command-settings-description = Change your settings
+command-caption-description = Set a template for inline captions
+command-subscriptions-description = Manage your source notifications
+command-watch-description = Get notified when an artist uploads
+command-unwatch-description = Stop watching an artist
+command-twitter-description = Add your Twitter account
+command-clearhistory-description = Clear your recent inline results
+command-groupsettings-description = Change settings for this group
+command-groupsource-description = Toggle automatic sourcing in this group
+command-grouppreviews-description = Toggle previews of sourced images in this group
+command-groupalbums-description = Toggle sourcing albums in this group
+command-groupcompact-description = Toggle showing sources as buttons in this group
+command-private-only = Let's do this in a private chat.
+command-group-only = This command only works in groups.
+command-invalid-args = Sorry, I didn't understand that.
+
 # Inline Keyboard
 inline-direct = Direct Link
 inline-source = Source
@@ -41,31 +62,50 @@ inline-source = Source
 # No Inline Results
 inline-no-results-title = No results found
 inline-no-results-body = I could not find any results for the provided query.
+inline-file-unavailable-title = Originals unavailable
+inline-file-unavailable-body = Sending original files is not enabled for this bot, so images are sent normally.
 
 # Inline Results Misc
 inline-help = Help
 inline-process = Process Video
 
+# Inline History
+history-cleared = I've forgotten everything you've sent through inline queries.
+
 # Inline Videos
 video-starting = Starting to process video...
 video-progress = Video processing is { $percent } complete...
 video-finished = Finished transcoding, uploading video...
 video-return-button = Return and send
 video-unknown = Sorry, something went wrong.
+video-failed = Sorry, I wasn't able to process this video.
+video-retry = Try again
 
 # Reverse Search
 reverse-no-results = I was unable to find anything, sorry.
-reverse-result = I found this: { $link } ({ $rating })
-reverse-result-unknown = I found this: { $link }
+reverse-result = I found this on { $site }: { $link } ({ $rating }, { $confidence })
+reverse-result-unknown = I found this on { $site }: { $link } ({ $confidence })
 reverse-multiple-results = I found multiple sources:
-reverse-multiple-item = · { $link } ({ $rating })
-reverse-multiple-item-unknown = · { $link }
+reverse-multiple-item = · { $site }: { $link } ({ $rating }, { $confidence })
+reverse-multiple-item-unknown = · { $site }: { $link } ({ $confidence })
+reverse-compact = { $count ->
+    [one] I found a source:
+   *[other] I found { $count } sources:
+}
 reverse-subscribe = Notify Me
 
+# Source details
+source-site-artist = { $site } by { $artist }
+source-button = { $site } ({ $rating }, { $confidence })
+source-button-unknown = { $site } ({ $confidence })
+confidence-exact = exact match
+confidence-high = likely match
+confidence-medium = possible match
+confidence-low = unlikely match
+
 # Twitter Onboarding
 twitter-callback = Please follow this link to add your Twitter account: { $link }
 twitter-welcome = Welcome aboard, { $userName }!
-twitter-private = Let's do this in a private chat.
 twitter-not-for-you = It doesn't look like anything to me
 twitter-existing-account = It looks like you already have the account { $account } added. Are you sure you want to change this?
 twitter-change-anyway = Change Account 
@@ -94,23 +134,35 @@ alternate-multiple-photo = I can only find alternates for a single photo, sorry.
 alternate-unknown-link = Sorry, I don't understand the provided link.
 
 # Automatic group sourcing
-automatic-single = It looks like this image may have come from here: { $link } ({ $rating })
-automatic-single-unknown = It looks like this image may have come from here: { $link }
+automatic-single = It looks like this image may have come from { $site }: { $link } ({ $rating }, { $confidence })
+automatic-single-unknown = It looks like this image may have come from { $site }: { $link } ({ $confidence })
 automatic-multiple = I found a few places this image may have come from:
-automatic-multiple-result = · { $link } ({ $rating })
-automatic-multiple-result-unknown = · { $link }
+automatic-multiple-result = · { $site }: { $link } ({ $rating }, { $confidence })
+automatic-multiple-result-unknown = · { $site }: { $link } ({ $confidence })
+automatic-compact = It looks like this image may have come from here:
 automatic-enable-not-admin = Sorry, you must be a group admin to enable this.
 automatic-enable-bot-not-admin = Sorry, you must give me admin permissions due to a Telegram limitation.
 automatic-enable-success = Automatic group sourcing is now enabled!
 automatic-disable = This feature is now turned off.
-automatic-enable-not-group = This feature is only supported in groups.
 automatic-preview-disable = Sourced image previews disabled.
 automatic-preview-enable = Sourced image previews enabled.
 automatic-album-enable = In-group album sourcing enabled.
 automatic-album-disable = In-group album sourcing disabled.
+automatic-compact-enable = Sources will now be shown as buttons.
+automatic-compact-disable = Sources will now be shown as links.
+automatic-compact-edit = Sources are added to images sent through me. Use /groupsettings to change how sources are shown.
 automatic-sources-link = I've collected the sources here: { $link }
 automatic-image-number = Image { $number }
 
+channel-artist-credit = Art by { $artists }
+
+repost-detected = This image was already posted here { $hours ->
+    [0] in the last hour.
+    [one] an hour ago.
+   *[other] { $hours } hours ago.
+}
+repost-view = View Earlier Post
+
 # Error Messages
 error-generic = Oh no, something went wrong! Please send a message to my creator, { -creatorName }, saying what happened.
 error-generic-message =
@@ -127,6 +179,7 @@ error-uuid-count = Oh no, something went wrong! I've encountered { $count } erro
 error-feedback = Thank you for the feedback, hopefully we can get this issue resolved soon.
 error-delete-callback = Error retrieving message to delete 
 error-deleted = Deleted message
+error-callback-invalid = Sorry, this button is no longer supported.
 
 # Settings
 settings-main = Let's take a look at some settings.
@@ -138,6 +191,125 @@ settings-unsupported = Sorry, not yet supported.
 settings-move-unable = Unable to move { $name } to that position
 settings-move-updated = Updated position for { $name }
 settings-site-preference = Site Preference
+settings-toggle-on = { $name }: On
+settings-toggle-off = { $name }: Off
+settings-inline-info = Inline Info
+settings-inline-tags = Inline Tags
+settings-originals = Send Originals
+settings-caption = Caption Template
+settings-caption-none =
+    You don't have a caption template. Set one with /caption followed by your template, which is used when inline info or tags are enabled.
+    
+    Available placeholders: { $placeholders }
+settings-caption-current =
+    Your caption template is:
+    
+    { $template }
+    
+    Change it with /caption followed by a new template, or remove it with /caption reset.
+    
+    Available placeholders: { $placeholders }
+settings-caption-updated = Updated your caption template.
+settings-caption-reset = Removed your caption template.
+settings-caption-invalid = Sorry, { $placeholder } is not a placeholder I know.
+settings-caption-unclosed = Sorry, your template has a {"{"} without a matching {"}"}.
+
+# Group Settings
+group-settings-main =
+    Settings for this group. Changes apply immediately.
+    
+    A lower match distance only finds sources for images that look more alike.
+group-settings-sourcing = Automatic Sourcing
+group-settings-previews = Source Previews
+group-settings-albums = Album Sourcing
+group-settings-mirroring = Automatic Mirroring
+group-settings-mirror-sites = Mirrored Sites
+group-settings-order = Site Order
+group-settings-order-main = Sources in this group are shown in this order.
+group-settings-order-members = Sources in this group are shown in each member's preferred order. Move a site to choose an order for everyone.
+group-settings-order-reset = Use Member Preferences
+group-settings-reposts = Repost Detection
+group-settings-linked-replies = Reply After Channel Edits
+group-settings-forwards = Channel Forwards: { $rule }
+group-settings-forwards-skip = Never Source
+group-settings-forwards-source = Always Source
+group-settings-forwards-wait = Source After { $hours ->
+    [one] 1 Hour
+   *[other] { $hours } Hours
+}
+group-settings-repost-distance = Repost Distance: { $distance }
+group-settings-repost-window = Repost Window: { $days ->
+    [one] 1 day
+   *[other] { $days } days
+}
+group-settings-mirror-main = Choose which sites I automatically mirror links from when automatic mirroring is enabled.
+group-settings-refresh = Refresh
+group-settings-back = « Back
+group-settings-distance = Match Distance: { $distance }
+group-settings-max-links = Max Links: { $count ->
+    [0] No limit
+   *[other] { $count }
+}
+group-settings-noisy = Skip Noisy Images: { $rule }
+group-settings-noisy-off = Never
+group-settings-noisy-twitter = Mostly Twitter
+group-settings-noisy-any = Any Site
+group-settings-delivery = Delivery: { $mode }
+group-settings-delivery-reply = Reply
+group-settings-delivery-buttons = Buttons
+group-settings-delivery-edit = Edit My Images
+group-settings-delete-after = Delete Replies: { $minutes ->
+    [0] Never
+    [one] After 1 minute
+   *[other] After { $minutes } minutes
+}
+group-settings-sites = Sites: { $enabled } of { $total }
+group-settings-sites-main = Choose which sites I can use for sources in this group.
+group-settings-sites-empty = At least one site must be allowed.
+group-settings-not-admin = Only group admins can change these settings.
+group-settings-warning-admin = ⚠️ I'm not an admin here, so I can't see images to automatically find sources.
+group-settings-warning-delete = ⚠️ I can't delete messages here, so commands used to summon me will stay in the chat.
+
+# Channel Settings
+channel-settings-main =
+    Settings for how I add sources to posts in this channel. Changes apply to new sources.
+channel-settings-style = Sources: { $style }
+channel-settings-style-buttons = Buttons
+channel-settings-style-caption = Caption
+channel-settings-style-both = Buttons and Caption
+channel-settings-credit = Credit Artist
+channel-settings-hashtags = Hashtags: { $tags }
+channel-settings-hashtags-off = None
+channel-settings-hashtags-artist = Artist
+channel-settings-hashtags-site = Site
+channel-settings-hashtags-both = Artist and Site
+
+# Admin Digest
+settings-digest = Daily Digest
+settings-digest-recipient = { $name }: { $recipient }
+digest-enabled = I'll send you a daily summary of what I did in { $chat }. Turn off the digest in the settings to stop.
+digest-taken-over = I'll send you the daily summary of what I did in { $chat } instead of the previous admin. Turn off the digest in the settings to stop.
+digest-start-bot = Start a private chat with me first so I can send you the digest.
+digest-title = Here's what happened in { $chat } since the last summary:
+digest-channel-edited = { $count ->
+    [one] · Added sources to 1 post
+   *[other] · Added sources to { $count } posts
+}
+digest-channel-unsourced = { $count ->
+    [one] · Couldn't find sources for 1 post
+   *[other] · Couldn't find sources for { $count } posts
+}
+digest-channel-edit-failed = { $count ->
+    [one] · Found sources for 1 post but couldn't edit it
+   *[other] · Found sources for { $count } posts but couldn't edit them
+}
+digest-group-sourced = { $count ->
+    [one] · Sent sources for 1 image
+   *[other] · Sent sources for { $count } images
+}
+digest-permission-lost = ⚠️ I lost permissions I need here. Check my admin rights so I can keep working.
+digest-chat-gone = I can no longer access this chat, so this is the last digest.
+digest-empty = · Nothing new
 
 rating-general = SFW
 rating-adult = NSFW
@@ -145,9 +317,39 @@ rating-unknown = Unknown
 
 subscribe-error = Unable to subscribe to notifications
 subscribe-success = I'll let you know if I find this later!
+subscribe-success-group = I'll send you a private message if I find this later! Make sure you've started a chat with me.
 subscribe-found-single =
     I found a match to an image you were looking for!
     
     You can see it here: { $link }
 subscribe-found-multiple = I found matches for an image you were looking for!
 subscribe-found-multiple-item = · { $link }
+subscribe-too-many = You already have { $max } pending notifications. Cancel some with /subscriptions before adding more.
+
+subscriptions-none = You don't have any pending notifications.
+subscriptions-list =
+    You are waiting on sources for { $count ->
+        [one] one image
+       *[other] { $count } images
+    }. I'll let you know if I find them later!
+    
+    Page { $page } of { $pages }
+subscriptions-item = #{ $number } · { $date }
+subscriptions-view = Waiting on a source since { $date }
+subscriptions-cancel = Cancel
+subscriptions-cancelled = Notification cancelled
+subscriptions-no-photo = I don't have a copy of that image
+subscriptions-missing = That notification no longer exists
+subscriptions-previous = « Previous
+subscriptions-next = Next »
+
+watch-usage = Use /watch followed by a site and artist name to get notified when they upload something new, like "/watch FurAffinity artist". Use /unwatch the same way to stop.
+watch-none = You aren't watching any artists.
+watch-list = You are watching these artists:
+watch-list-item = · { $artist } on { $site }
+watch-added = I'll let you know when { $artist } uploads something new to { $site }!
+watch-removed = You are no longer watching { $artist } on { $site }.
+watch-not-found = You weren't watching { $artist } on { $site }.
+watch-too-many = You are already watching too many artists. Remove some with /unwatch before adding more.
+watch-new-uploads = New uploads from artists you're watching:
+watch-new-upload-item = · { $artist } on { $site }: { $link }
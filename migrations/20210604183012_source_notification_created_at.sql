@@ -0,0 +1,4 @@
+ALTER TABLE source_notification
+    ADD COLUMN created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT current_timestamp;
+
+CREATE INDEX source_notification_created_at_idx ON source_notification (created_at);
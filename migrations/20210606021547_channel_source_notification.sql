@@ -0,0 +1,12 @@
+CREATE TABLE channel_source_notification (
+    chat_id INTEGER NOT NULL REFERENCES chat (id) ON DELETE CASCADE,
+    message_id INTEGER NOT NULL,
+    media_group_id TEXT,
+    hash BIGINT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT current_timestamp,
+
+    PRIMARY KEY (chat_id, message_id)
+);
+
+CREATE INDEX bk_channel_source_notification_idx ON channel_source_notification USING spgist (hash bktree_ops);
+CREATE INDEX channel_source_notification_created_at_idx ON channel_source_notification (created_at);
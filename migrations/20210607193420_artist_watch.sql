@@ -0,0 +1,10 @@
+CREATE TABLE artist_watch (
+    account_id INTEGER NOT NULL REFERENCES account (id) ON DELETE CASCADE,
+    site TEXT NOT NULL,
+    artist TEXT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT current_timestamp,
+
+    PRIMARY KEY (account_id, site, artist)
+);
+
+CREATE INDEX artist_watch_lookup_idx ON artist_watch (site, artist);
@@ -0,0 +1,11 @@
+ALTER TABLE videos
+    ADD COLUMN state TEXT NOT NULL DEFAULT 'pending',
+    ADD COLUMN attempts INTEGER NOT NULL DEFAULT 0,
+    ADD COLUMN created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT current_timestamp,
+    ADD COLUMN state_updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT current_timestamp;
+
+UPDATE videos SET state = 'done' WHERE processed = true;
+-- Jobs that never completed before state was tracked are not recoverable.
+UPDATE videos SET state = 'failed' WHERE processed = false AND job_id IS NOT NULL;
+
+CREATE INDEX videos_encoding_idx ON videos (state_updated_at) WHERE state = 'encoding';
@@ -0,0 +1,2 @@
+ALTER TABLE cached_post ADD COLUMN file_id TEXT;
+ALTER TABLE videos ADD COLUMN file_id TEXT;
@@ -0,0 +1,14 @@
+CREATE TABLE inline_history (
+    id SERIAL PRIMARY KEY,
+    account_id INTEGER NOT NULL REFERENCES account (id) ON DELETE CASCADE,
+    site TEXT NOT NULL,
+    url_id TEXT,
+    source_link TEXT,
+    url TEXT NOT NULL,
+    thumb_url TEXT,
+    file_type TEXT NOT NULL,
+    chosen_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT current_timestamp
+);
+
+CREATE INDEX inline_history_account_idx ON inline_history (account_id, chosen_at DESC);
+CREATE INDEX inline_history_chosen_at_idx ON inline_history (chosen_at);
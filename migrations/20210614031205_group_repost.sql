@@ -0,0 +1,12 @@
+CREATE TABLE group_repost (
+    chat_id INTEGER NOT NULL REFERENCES chat (id) ON DELETE CASCADE,
+    message_id INTEGER NOT NULL,
+    hash BIGINT NOT NULL,
+    posted_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT current_timestamp,
+
+    PRIMARY KEY (chat_id, message_id)
+);
+
+CREATE INDEX bk_group_repost_idx ON group_repost USING spgist (hash bktree_ops);
+CREATE INDEX group_repost_chat_idx ON group_repost (chat_id, posted_at);
+CREATE INDEX group_repost_posted_at_idx ON group_repost (posted_at);
@@ -0,0 +1,2 @@
+ALTER TABLE channel_source_notification ADD COLUMN caption TEXT;
+ALTER TABLE channel_source_notification ADD COLUMN caption_entities JSONB;
@@ -0,0 +1,17 @@
+CREATE TABLE chat_action (
+    id SERIAL PRIMARY KEY,
+    chat_id INTEGER NOT NULL REFERENCES chat (id) ON DELETE CASCADE,
+    action TEXT NOT NULL,
+    message_id INTEGER,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT current_timestamp
+);
+
+CREATE INDEX chat_action_chat_idx ON chat_action (chat_id, created_at);
+CREATE INDEX chat_action_created_at_idx ON chat_action (created_at);
+
+CREATE TABLE chat_digest (
+    chat_id INTEGER PRIMARY KEY REFERENCES chat (id) ON DELETE CASCADE,
+    account_id INTEGER NOT NULL REFERENCES account (id) ON DELETE CASCADE,
+    language_code TEXT,
+    last_sent_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT current_timestamp
+);